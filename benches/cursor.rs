@@ -0,0 +1,91 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Benchmarks of cursor seeks on large SafeMode stores. Seeking with `iter_from`
+//! and `MultiStore::get` is compared with the linear scans they used to perform,
+//! which are reproduced on top of the `iter_start` of the same stores.
+
+use byteorder::{
+    BigEndian,
+    ByteOrder,
+};
+use criterion::{
+    black_box,
+    criterion_group,
+    criterion_main,
+    Criterion,
+};
+use tempfile::Builder;
+
+use rkv::{
+    backend::{
+        SafeMode,
+        SafeModeEnvironment,
+    },
+    Rkv,
+    StoreOptions,
+    Value,
+};
+
+const KEYS: u32 = 1_000_000;
+
+fn key(i: u32) -> [u8; 4] {
+    let mut bytes = [0; 4];
+    BigEndian::write_u32(&mut bytes, i);
+    bytes
+}
+
+fn populate(k: &Rkv<SafeModeEnvironment>) {
+    let single = k.open_single("single", StoreOptions::create()).expect("opened");
+    let multi = k.open_multi("multi", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    for i in 0..KEYS {
+        single.put(&mut writer, key(i), &Value::U64(u64::from(i))).expect("wrote");
+        multi.put(&mut writer, key(i), &Value::U64(u64::from(i))).expect("wrote");
+        multi.put(&mut writer, key(i), &Value::U64(u64::from(i) + 1)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+}
+
+fn bench_cursors(c: &mut Criterion) {
+    let root = Builder::new().prefix("bench_cursors").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    populate(&k);
+
+    let single = k.open_single("single", StoreOptions::default()).expect("opened");
+    let multi = k.open_multi("multi", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    let target = key(KEYS / 2);
+
+    c.bench_function("safe_iter_from_1m", |b| {
+        b.iter(|| single.iter_from(&reader, black_box(target)).expect("iter").next().expect("found").expect("read"))
+    });
+    c.bench_function("safe_scan_from_1m", |b| {
+        b.iter(|| {
+            let iter = single.iter_start(&reader).expect("iter");
+            iter.map(|item| item.expect("read")).find(|&(k, _)| k >= &black_box(target)[..]).expect("found")
+        })
+    });
+
+    c.bench_function("safe_multi_get_1m", |b| b.iter(|| multi.get(&reader, black_box(target)).expect("iter").count()));
+    c.bench_function("safe_scan_dup_of_1m", |b| {
+        b.iter(|| {
+            let iter = multi.iter_start(&reader).expect("iter").map(|item| item.expect("read"));
+            iter.skip_while(|&(k, _)| k < &black_box(target)[..]).take_while(|&(k, _)| k == &target[..]).count()
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_cursors
+}
+criterion_main!(benches);
@@ -11,9 +11,14 @@
 mod common;
 mod impl_lmdb;
 mod impl_safe;
+mod iter;
 mod traits;
 
 pub use common::*;
+pub use iter::{
+    RangeIter,
+    RevIter,
+};
 pub use traits::*;
 
 pub use impl_lmdb::{
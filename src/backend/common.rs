@@ -42,3 +42,57 @@ pub enum WriteFlags {
     APPEND,
     APPEND_DUP,
 }
+
+/// How to grow the map of an environment once it's full, instead of failing with
+/// `StoreError::MapFull`. Only `Rkv::write_batch` follows it, since the transactions it
+/// runs can be replayed: writes and commits of a `Writer` from `Rkv::write` still fail
+/// with `StoreError::MapFull`.
+#[derive(Debug, Copy, Clone)]
+pub struct MapGrowth {
+    /// The factor by which the map size is multiplied each time it grows.
+    pub factor: f32,
+    /// The size in bytes that the map never grows past.
+    pub max_size: usize,
+    /// The load ratio from which the map grows before it's full, see `Rkv::load_ratio`.
+    pub load_ratio: Option<f32>,
+}
+
+impl MapGrowth {
+    pub fn new(factor: f32, max_size: usize) -> MapGrowth {
+        MapGrowth {
+            factor,
+            max_size,
+            load_ratio: None,
+        }
+    }
+
+    /// The size that a map of `map_size` bytes grows to, as a multiple of `page_size`,
+    /// or `None` if it can't grow anymore.
+    pub(crate) fn grow(&self, map_size: usize, page_size: usize) -> Option<usize> {
+        let size = (map_size as f64 * f64::from(self.factor)).min(self.max_size as f64) as usize;
+        let size = size - size % page_size;
+        Some(size).filter(|&size| size > map_size)
+    }
+}
+
+// Factors are compared bitwise, so that the builders holding a policy stay `Eq`.
+impl PartialEq for MapGrowth {
+    fn eq(&self, other: &MapGrowth) -> bool {
+        self.factor.to_bits() == other.factor.to_bits()
+            && self.max_size == other.max_size
+            && self.load_ratio.map(f32::to_bits) == other.load_ratio.map(f32::to_bits)
+    }
+}
+
+impl Eq for MapGrowth {}
+
+/// A slot of the reader table of an environment, see `Rkv::readers`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReaderSlot {
+    /// The id of the process which owns the slot.
+    pub pid: u32,
+    /// The id of the thread which owns the slot, within its process.
+    pub thread: u64,
+    /// The id of the transaction that the reader sees, or `None` if it's not reading.
+    pub txnid: Option<usize>,
+}
@@ -503,7 +503,39 @@ impl Migrator {
     pub fn new(path: &Path) -> MigrateResult<Migrator> {
         let mut path = PathBuf::from(path);
         path.push("data.mdb");
-        let mut file = File::open(&path)?;
+        Migrator::open(&path)
+    }
+
+    /// Check that the meta pages of the given data.mdb file can be parsed, and that the
+    /// databases they describe start within the file. This fails when the file has been
+    /// corrupted, or isn't an LMDB data file at all. Otherwise, return whether the file
+    /// was created with the bit depth of the current executable, since LMDB can't open
+    /// it if that's not the case.
+    pub(crate) fn check_meta_pages(path: &Path) -> MigrateResult<bool> {
+        let mut migrator = Migrator::open(path)?;
+        let meta_data = migrator.get_meta_data()?;
+        let Databases {
+            free,
+            main,
+        } = &meta_data.mm_dbs;
+        for root in &[free.md_root, main.md_root] {
+            if validate_page_num(*root, migrator.bits).is_ok() && *root > meta_data.mm_last_pg {
+                return Err(MigrateError::InvalidPageNum);
+            }
+        }
+
+        let bits = if cfg!(target_pointer_width = "64") {
+            Bits::U64
+        } else {
+            Bits::U32
+        };
+        Ok(migrator.bits == bits)
+    }
+
+    // Open the given data.mdb file and determine the bit depth of the executable that
+    // created it.
+    fn open(path: &Path) -> MigrateResult<Migrator> {
+        let mut file = File::open(path)?;
 
         file.seek(SeekFrom::Start(page_header_size(Bits::U32)))?;
         let mut buf = [0; 4];
@@ -8,13 +8,101 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+use std::{
+    cmp::Ordering,
+    os::raw::c_void,
+};
+
 use lmdb::Cursor;
+use lmdb_sys::{
+    mdb_cmp,
+    mdb_cursor_dbi,
+    mdb_cursor_txn,
+    mdb_dbi_flags,
+    MDB_cursor_op,
+    MDB_dbi,
+    MDB_txn,
+    MDB_val,
+    MDB_DUPSORT,
+    MDB_FIRST,
+    MDB_GET_CURRENT,
+    MDB_INTEGERKEY,
+    MDB_LAST,
+    MDB_NEXT,
+    MDB_NEXT_DUP,
+    MDB_PREV,
+    MDB_PREV_DUP,
+    MDB_REVERSEKEY,
+    MDB_SET_KEY,
+    MDB_SET_RANGE,
+};
 
-use super::IterImpl;
-use crate::backend::traits::BackendRoCursor;
+use super::{
+    ErrorImpl,
+    IterImpl,
+};
+use crate::backend::traits::{
+    BackendPositionedCursor,
+    BackendRoCursor,
+    BackendRwCursor,
+};
 
 #[derive(Debug)]
-pub struct RoCursorImpl<'c>(pub(crate) lmdb::RoCursor<'c>);
+pub struct RoCursorImpl<'c> {
+    cursor: lmdb::RoCursor<'c>,
+    // LMDB doesn't tell whether a cursor is positioned, and reads out of bounds when
+    // moving between the values of a key before it is, so this is tracked here.
+    positioned: bool,
+}
+
+impl<'c> RoCursorImpl<'c> {
+    pub(crate) fn new(cursor: lmdb::RoCursor<'c>) -> RoCursorImpl<'c> {
+        RoCursorImpl {
+            cursor,
+            positioned: false,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get(&mut self, key: Option<&[u8]>, op: MDB_cursor_op) -> Option<Result<(&'c [u8], &'c [u8]), ErrorImpl>> {
+        match self.cursor.get(key, None, op) {
+            Ok((key, value)) => {
+                self.positioned = true;
+                Some(Ok((key.unwrap_or_default(), value)))
+            },
+            Err(lmdb::Error::NotFound) => None,
+            Err(error) => Some(Err(ErrorImpl::LmdbError(error))),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn seek_with(&mut self, key: Option<&[u8]>, op: MDB_cursor_op) -> Option<Result<(&'c [u8], &'c [u8]), ErrorImpl>> {
+        let found = self.get(key, op);
+        if found.is_none() {
+            self.positioned = false;
+        }
+        found
+    }
+
+    // Without `DUP_SORT`, LMDB moves on to the following keys when asked for the
+    // following values of the current one.
+    #[allow(clippy::type_complexity)]
+    fn get_dup(&mut self, op: MDB_cursor_op) -> Option<Result<(&'c [u8], &'c [u8]), ErrorImpl>> {
+        if !self.positioned {
+            return None;
+        }
+        let cursor = self.cursor.cursor();
+        let mut flags = 0;
+        let result = unsafe { mdb_dbi_flags(mdb_cursor_txn(cursor), mdb_cursor_dbi(cursor), &mut flags) };
+        if result != 0 {
+            return Some(Err(ErrorImpl::LmdbError(lmdb::Error::from_err_code(result))));
+        }
+        if flags & MDB_DUPSORT == 0 {
+            return None;
+        }
+        self.get(None, op)
+    }
+}
 
 impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
     type Iter = IterImpl<'c, lmdb::RoCursor<'c>>;
@@ -25,22 +113,113 @@ impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
         // former returns an iterator that yields no items. And since we create
         // the Cursor and don't change its position, we can be sure that a call
         // to Cursor.iter() will start at the beginning.
-        IterImpl::new(self.0, lmdb::RoCursor::iter)
+        IterImpl::new(self.cursor, lmdb::RoCursor::iter)
     }
 
     fn into_iter_from<K>(self, key: K) -> Self::Iter
     where
         K: AsRef<[u8]> + 'c,
     {
-        IterImpl::new(self.0, |cursor| cursor.iter_from(key))
+        IterImpl::new(self.cursor, |cursor| cursor.iter_from(key))
     }
 
     fn into_iter_dup_of<K>(self, key: K) -> Self::Iter
     where
         K: AsRef<[u8]> + 'c,
     {
-        IterImpl::new(self.0, |cursor| cursor.iter_dup_of(key))
+        IterImpl::new(self.cursor, |cursor| cursor.iter_dup_of(key))
+    }
+}
+
+#[allow(clippy::type_complexity)]
+impl<'c> BackendPositionedCursor<'c> for RoCursorImpl<'c> {
+    type Error = ErrorImpl;
+
+    fn first(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        self.get(None, MDB_FIRST)
+    }
+
+    fn last(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        self.get(None, MDB_LAST)
+    }
+
+    // LMDB rejects empty keys when seeking, but they can't be stored either, so every
+    // key comes after them.
+    fn seek(&mut self, key: &[u8]) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        if key.is_empty() {
+            return self.seek_with(None, MDB_FIRST);
+        }
+        self.seek_with(Some(key), MDB_SET_RANGE)
+    }
+
+    fn seek_exact(&mut self, key: &[u8]) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        if key.is_empty() {
+            self.positioned = false;
+            return None;
+        }
+        self.seek_with(Some(key), MDB_SET_KEY)
+    }
+
+    fn next(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        let op = if self.positioned {
+            MDB_NEXT
+        } else {
+            MDB_FIRST
+        };
+        self.get(None, op)
+    }
+
+    fn prev(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        let op = if self.positioned {
+            MDB_PREV
+        } else {
+            MDB_LAST
+        };
+        self.get(None, op)
+    }
+
+    fn next_dup(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        self.get_dup(MDB_NEXT_DUP)
+    }
+
+    fn prev_dup(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>> {
+        self.get_dup(MDB_PREV_DUP)
+    }
+
+    fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        let cursor = self.cursor.cursor();
+        unsafe { compare_keys(mdb_cursor_txn(cursor), mdb_cursor_dbi(cursor), a, b) }
+    }
+
+    fn sorts_keys_bytewise(&self) -> Result<bool, Self::Error> {
+        let cursor = self.cursor.cursor();
+        unsafe { sorts_keys_bytewise(mdb_cursor_txn(cursor), mdb_cursor_dbi(cursor)) }
+    }
+}
+
+// Compares two keys with the comparison function of the database, which the caller has
+// to make sure is open in the transaction.
+pub(crate) unsafe fn compare_keys(txn: *mut MDB_txn, dbi: MDB_dbi, a: &[u8], b: &[u8]) -> Ordering {
+    let a = MDB_val {
+        mv_size: a.len(),
+        mv_data: a.as_ptr() as *mut c_void,
+    };
+    let b = MDB_val {
+        mv_size: b.len(),
+        mv_data: b.as_ptr() as *mut c_void,
+    };
+    mdb_cmp(txn, dbi, &a, &b).cmp(&0)
+}
+
+// Tells whether the database compares its keys byte by byte, which the caller has to
+// make sure is open in the transaction.
+pub(crate) unsafe fn sorts_keys_bytewise(txn: *mut MDB_txn, dbi: MDB_dbi) -> Result<bool, ErrorImpl> {
+    let mut flags = 0;
+    let result = mdb_dbi_flags(txn, dbi, &mut flags);
+    if result != 0 {
+        return Err(ErrorImpl::LmdbError(lmdb::Error::from_err_code(result)));
     }
+    Ok(flags & (MDB_REVERSEKEY | MDB_INTEGERKEY) == 0)
 }
 
 #[derive(Debug)]
@@ -67,3 +246,28 @@ impl<'c> BackendRoCursor<'c> for RwCursorImpl<'c> {
         IterImpl::new(self.0, |cursor| cursor.iter_dup_of(key))
     }
 }
+
+impl<'c> BackendRwCursor<'c> for RwCursorImpl<'c> {
+    type Error = ErrorImpl;
+
+    #[allow(clippy::type_complexity)]
+    fn next(&mut self) -> Option<Result<(&[u8], &[u8]), Self::Error>> {
+        match self.0.get(None, None, MDB_NEXT) {
+            Ok((key, value)) => Some(Ok((key.unwrap_or_default(), value))),
+            Err(lmdb::Error::NotFound) => None,
+            Err(error) => Some(Err(ErrorImpl::LmdbError(error))),
+        }
+    }
+
+    fn del_current(&mut self) -> Result<(), Self::Error> {
+        self.0.del(lmdb::WriteFlags::empty()).map_err(ErrorImpl::LmdbError)
+    }
+
+    fn put_current(&mut self, value: &[u8]) -> Result<(), Self::Error> {
+        // The current key points into a page that the write may move around, so
+        // it has to be copied first.
+        let (key, _) = self.0.get(None, None, MDB_GET_CURRENT).map_err(ErrorImpl::LmdbError)?;
+        let key = key.unwrap_or_default().to_vec();
+        self.0.put(&key, &value, lmdb::WriteFlags::CURRENT).map_err(ErrorImpl::LmdbError)
+    }
+}
@@ -9,16 +9,47 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
     fs,
+    io,
+    os::raw::{
+        c_char,
+        c_int,
+        c_void,
+    },
     path::{
         Path,
         PathBuf,
     },
+    sync::{
+        Arc,
+        Mutex,
+        MutexGuard,
+        PoisonError,
+    },
 };
 
-use lmdb::Error as LmdbError;
+use lmdb::{
+    Cursor,
+    Error as LmdbError,
+    Transaction,
+};
+use lmdb_sys::{
+    mdb_env_copy2,
+    mdb_put,
+    mdb_reader_check,
+    mdb_reader_list,
+    MDB_val,
+    MDB_CP_COMPACT,
+};
+use log::warn;
 
 use super::{
+    ArchMigrateError,
+    ArchMigrator,
     DatabaseFlagsImpl,
     DatabaseImpl,
     EnvironmentFlagsImpl,
@@ -28,14 +59,20 @@ use super::{
     RwTransactionImpl,
     StatImpl,
 };
-use crate::backend::traits::{
-    BackendEnvironment,
-    BackendEnvironmentBuilder,
-    BackendInfo,
-    BackendIter,
-    BackendRoCursor,
-    BackendRoCursorTransaction,
-    BackendStat,
+use crate::backend::{
+    common::{
+        MapGrowth,
+        ReaderSlot,
+    },
+    traits::{
+        BackendEnvironment,
+        BackendEnvironmentBuilder,
+        BackendInfo,
+        BackendIter,
+        BackendRoCursor,
+        BackendRoCursorTransaction,
+        BackendStat,
+    },
 };
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -45,6 +82,9 @@ pub struct EnvironmentBuilderImpl {
     env_lock_type: EnvironmentLockType,
     env_db_type: EnvironmentDefaultDbType,
     make_dir_if_needed: bool,
+    discard_if_corrupted: bool,
+    map_growth: Option<MapGrowth>,
+    reader_check_on_open: bool,
 }
 
 impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
@@ -59,6 +99,9 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
             env_lock_type: EnvironmentLockType::Lockfile,
             env_db_type: EnvironmentDefaultDbType::SingleDatabase,
             make_dir_if_needed: false,
+            discard_if_corrupted: false,
+            map_growth: None,
+            reader_check_on_open: false,
         }
     }
 
@@ -100,10 +143,19 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
         self
     }
 
-    fn set_discard_if_corrupted(&mut self, _discard_if_corrupted: bool) -> &mut Self {
-        // Unfortunately, when opening a database, LMDB doesn't handle all the ways it could have
-        // been corrupted. Prefer using the `SafeMode` backend if this is important.
-        unimplemented!();
+    fn set_discard_if_corrupted(&mut self, discard_if_corrupted: bool) -> &mut Self {
+        self.discard_if_corrupted = discard_if_corrupted;
+        self
+    }
+
+    fn set_map_growth(&mut self, map_growth: MapGrowth) -> &mut Self {
+        self.map_growth = Some(map_growth);
+        self
+    }
+
+    fn set_reader_check_on_open(&mut self, reader_check_on_open: bool) -> &mut Self {
+        self.reader_check_on_open = reader_check_on_open;
+        self
     }
 
     fn open(&self, path: &Path) -> Result<Self::Environment, Self::Error> {
@@ -123,12 +175,143 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
             },
         }
 
-        self.builder.open(path).map_err(ErrorImpl::LmdbError).and_then(|lmdbenv| {
-            EnvironmentImpl::new(path, self.env_path_type, self.env_lock_type, self.env_db_type, lmdbenv)
+        // Unfortunately, when opening a database, LMDB doesn't handle all the ways it could have
+        // been corrupted, so check its meta pages first. Data files created with another bit
+        // depth aren't corrupted though, and can be migrated instead.
+        let data_path = match self.env_path_type {
+            EnvironmentPathType::NoSubDir => path.to_path_buf(),
+            EnvironmentPathType::SubDir => path.join("data.mdb"),
+        };
+        let lock_path = match (self.env_lock_type, self.env_path_type) {
+            (EnvironmentLockType::NoLockfile, _) => None,
+            (_, EnvironmentPathType::NoSubDir) => {
+                let mut lock_path = path.as_os_str().to_owned();
+                lock_path.push("-lock");
+                Some(PathBuf::from(lock_path))
+            },
+            (_, EnvironmentPathType::SubDir) => Some(path.join("lock.mdb")),
+        };
+        let mut other_bit_depth = false;
+        if self.discard_if_corrupted && data_path.metadata().map(|m| m.len() > 0).unwrap_or(false) {
+            match check_data_file(&data_path)? {
+                Some(bit_depth) => other_bit_depth = bit_depth,
+                None => {
+                    discard(&data_path, lock_path.as_deref(), true)?;
+                },
+            }
+        }
+
+        let lmdbenv = match self.builder.open(path) {
+            Err(error @ LmdbError::Corrupted) | Err(error @ LmdbError::Invalid)
+                if self.discard_if_corrupted && !other_bit_depth =>
+            {
+                if discard(&data_path, lock_path.as_deref(), false)? {
+                    self.builder.open(path)
+                } else {
+                    Err(error)
+                }
+            },
+            result => result,
+        };
+        lmdbenv.map_err(ErrorImpl::LmdbError).and_then(|lmdbenv| {
+            let mut env =
+                EnvironmentImpl::new(path, self.env_path_type, self.env_lock_type, self.env_db_type, lmdbenv)?;
+            env.map_growth = self.map_growth;
+            if self.reader_check_on_open {
+                env.reader_check()?;
+            }
+            Ok(env)
         })
     }
 }
 
+// Checks the meta pages of a data file, and returns whether it was created with another
+// bit depth, or `None` if it's corrupted.
+fn check_data_file(data_path: &Path) -> Result<Option<bool>, ErrorImpl> {
+    match ArchMigrator::check_meta_pages(data_path) {
+        Ok(same_bit_depth) => Ok(Some(!same_bit_depth)),
+        Err(ArchMigrateError::IoError(error)) if error.kind() != io::ErrorKind::UnexpectedEof => {
+            Err(ErrorImpl::IoError(error))
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+// Moves a corrupted data file out of the way for LMDB to create a new one, keeping it
+// next to the new one in case its data can be salvaged, and returns whether it did.
+// Another process which has the environment open may be writing to the file, so it's
+// left alone in that case. Otherwise, the file is checked again if `recheck` is set,
+// since it may have been read while another process was still writing to it.
+fn discard(data_path: &Path, lock_path: Option<&Path>, recheck: bool) -> Result<bool, ErrorImpl> {
+    let _lock = match lock_path {
+        Some(lock_path) => {
+            match lock_if_unused(lock_path)? {
+                Some(lock) => Some(lock),
+                None => {
+                    warn!("Not discarding LMDB data file {:?}, which is in use by another process", data_path);
+                    return Ok(false);
+                },
+            }
+        },
+        None => None,
+    };
+    if recheck && check_data_file(data_path)?.is_some() {
+        return Ok(false);
+    }
+    let backup = backup_path(data_path);
+    warn!("Discarding corrupted LMDB data file {:?} to {:?}", data_path, backup);
+    fs::rename(data_path, backup)?;
+    Ok(true)
+}
+
+// Returns a path that isn't taken yet for the backup of a discarded data file, so that
+// the backups of earlier ones are kept: `data.mdb.corrupted`, then with a counter.
+fn backup_path(data_path: &Path) -> PathBuf {
+    let mut base = data_path.as_os_str().to_owned();
+    base.push(".corrupted");
+    let mut backup = PathBuf::from(&base);
+    let mut counter = 0;
+    while backup.exists() {
+        counter += 1;
+        let mut name = base.clone();
+        name.push(format!(".{}", counter));
+        backup = PathBuf::from(name);
+    }
+    backup
+}
+
+// LMDB holds a POSIX lock on the first byte of the lock file for as long as a process
+// has the environment open. Taking it exclusively succeeds if no other process does,
+// and keeps them from opening the environment until the returned file is closed.
+#[cfg(unix)]
+fn lock_if_unused(lock_path: &Path) -> Result<Option<fs::File>, ErrorImpl> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(lock_path)?;
+    // Safe because `flock` is a plain C struct, for which zeroes are valid values.
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = 0;
+    lock.l_len = 1;
+    // Safe because the file descriptor stays open for the duration of the call.
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) } == 0 {
+        return Ok(Some(file));
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EAGAIN) | Some(libc::EACCES) => Ok(None),
+        _ => Err(error.into()),
+    }
+}
+
+// Windows doesn't allow renaming files that other processes have open, so discarding the
+// data file fails instead when the environment is in use.
+#[cfg(not(unix))]
+fn lock_if_unused(lock_path: &Path) -> Result<Option<fs::File>, ErrorImpl> {
+    Ok(Some(fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(lock_path)?))
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum EnvironmentPathType {
     SubDir,
@@ -154,6 +337,10 @@ pub struct EnvironmentImpl {
     env_lock_type: EnvironmentLockType,
     env_db_type: EnvironmentDefaultDbType,
     lmdbenv: lmdb::Environment,
+    map_growth: Option<MapGrowth>,
+    // Counts the transactions of this process that are still open. New ones are counted
+    // while holding the lock, so that the map can't grow as they begin.
+    txns: Mutex<Arc<()>>,
 }
 
 impl EnvironmentImpl {
@@ -170,8 +357,28 @@ impl EnvironmentImpl {
             env_lock_type,
             env_db_type,
             lmdbenv,
+            map_growth: None,
+            txns: Mutex::new(Arc::new(())),
         })
     }
+
+    // Nothing can panic while holding the lock, which only guards the counting.
+    fn txns(&self) -> MutexGuard<'_, Arc<()>> {
+        self.txns.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Begins a write transaction to drop or rename databases with, unless other
+    // transactions of this process are active, since those could still be using them.
+    // New ones can't begin until the returned guard is released.
+    fn begin_exclusive_txn(&self) -> Result<(MutexGuard<'_, Arc<()>>, lmdb::RwTransaction<'_>), ErrorImpl> {
+        let txns = self.txns();
+        if Arc::strong_count(&txns) > 1 {
+            // Reported like LMDB does when opening a database during a read transaction.
+            return Err(ErrorImpl::LmdbError(LmdbError::BadRslot));
+        }
+        let txn = self.lmdbenv.begin_rw_txn().map_err(ErrorImpl::LmdbError)?;
+        Ok((txns, txn))
+    }
 }
 
 impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
@@ -208,12 +415,38 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
         self.lmdbenv.create_db(name, flags.0).map(DatabaseImpl).map_err(ErrorImpl::LmdbError)
     }
 
+    fn drop_db(&self, name: &str) -> Result<(), Self::Error> {
+        let (_txns, mut txn) = self.begin_exclusive_txn()?;
+        let db = unsafe { txn.open_db(Some(name)) }.map_err(ErrorImpl::LmdbError)?;
+        unsafe { txn.drop_db(db) }.map_err(ErrorImpl::LmdbError)?;
+        txn.commit().map_err(ErrorImpl::LmdbError)
+    }
+
+    fn rename_db(&self, name: &str, new_name: &str) -> Result<(), Self::Error> {
+        // LMDB can't rename databases, so the contents are copied to a new one, within
+        // the same transaction as dropping the old one.
+        let (_txns, mut txn) = self.begin_exclusive_txn()?;
+        let db = unsafe { txn.open_db(Some(name)) }.map_err(ErrorImpl::LmdbError)?;
+        match unsafe { txn.open_db(Some(new_name)) } {
+            Ok(_) => return Err(ErrorImpl::LmdbError(LmdbError::KeyExist)),
+            Err(LmdbError::NotFound) => {},
+            Err(error) => return Err(ErrorImpl::LmdbError(error)),
+        }
+        let flags = txn.db_flags(db).map_err(ErrorImpl::LmdbError)?;
+        let new_db = unsafe { txn.create_db(Some(new_name), flags) }.map_err(ErrorImpl::LmdbError)?;
+        copy_db(&txn, db, new_db).map_err(ErrorImpl::LmdbError)?;
+        unsafe { txn.drop_db(db) }.map_err(ErrorImpl::LmdbError)?;
+        txn.commit().map_err(ErrorImpl::LmdbError)
+    }
+
     fn begin_ro_txn(&'e self) -> Result<Self::RoTransaction, Self::Error> {
-        self.lmdbenv.begin_ro_txn().map(RoTransactionImpl).map_err(ErrorImpl::LmdbError)
+        let txn = self.txns().clone();
+        self.lmdbenv.begin_ro_txn().map(|ro_txn| RoTransactionImpl(ro_txn, txn)).map_err(ErrorImpl::LmdbError)
     }
 
     fn begin_rw_txn(&'e self) -> Result<Self::RwTransaction, Self::Error> {
-        self.lmdbenv.begin_rw_txn().map(RwTransactionImpl).map_err(ErrorImpl::LmdbError)
+        let txn = self.txns().clone();
+        self.lmdbenv.begin_rw_txn().map(|rw_txn| RwTransactionImpl::new(rw_txn, txn)).map_err(ErrorImpl::LmdbError)
     }
 
     fn sync(&self, force: bool) -> Result<(), Self::Error> {
@@ -224,6 +457,13 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
         self.lmdbenv.stat().map(StatImpl).map_err(ErrorImpl::LmdbError)
     }
 
+    fn db_stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error> {
+        // Counted like any other transaction, so that the map doesn't grow while it's read.
+        let _txn = self.txns().clone();
+        let txn = self.lmdbenv.begin_ro_txn().map_err(ErrorImpl::LmdbError)?;
+        txn.stat(db.0).map(StatImpl).map_err(ErrorImpl::LmdbError)
+    }
+
     fn info(&self) -> Result<Self::Info, Self::Error> {
         self.lmdbenv.info().map(InfoImpl).map_err(ErrorImpl::LmdbError)
     }
@@ -250,26 +490,145 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
         self.lmdbenv.set_map_size(size).map_err(ErrorImpl::LmdbError)
     }
 
-    fn get_files_on_disk(&self) -> Vec<PathBuf> {
-        let mut store = vec![];
+    fn grow_map(&self, full: bool) -> Result<bool, Self::Error> {
+        let map_growth = match self.map_growth {
+            Some(map_growth) => map_growth,
+            None => return Ok(false),
+        };
+        if !full {
+            let load_ratio = match map_growth.load_ratio {
+                Some(load_ratio) => load_ratio,
+                None => return Ok(false),
+            };
+            if self.load_ratio()?.map_or(true, |ratio| ratio < load_ratio) {
+                return Ok(false);
+            }
+        }
+        let size = match map_growth.grow(self.info()?.map_size(), self.stat()?.page_size()) {
+            Some(size) => size,
+            None => return Ok(false),
+        };
+
+        // LMDB remaps the data file when resizing, which would pull it from under the
+        // transactions of this process. Other processes pick up the new size by themselves.
+        let txns = self.txns();
+        if Arc::strong_count(&txns) > 1 {
+            return Ok(false);
+        }
+        self.set_map_size(size)?;
+        Ok(true)
+    }
+
+    fn readers(&self) -> Result<Vec<ReaderSlot>, Self::Error> {
+        let mut lines: Vec<String> = vec![];
+        let ctx = &mut lines as *mut Vec<String> as *mut c_void;
+        let result = unsafe { mdb_reader_list(self.lmdbenv.env(), Some(push_line), ctx) };
+        if result < 0 {
+            return Err(ErrorImpl::LmdbError(LmdbError::from_err_code(result)));
+        }
+        // LMDB lists the slots as `pid thread txnid`, with a header, the thread in hex,
+        // and a `-` as txnid for slots which aren't in use by a transaction.
+        let slot = |line: &str| -> Option<ReaderSlot> {
+            let mut fields = line.split_whitespace();
+            Some(ReaderSlot {
+                pid: fields.next()?.parse().ok()?,
+                thread: u64::from_str_radix(fields.next()?, 16).ok()?,
+                txnid: match fields.next()? {
+                    "-" => None,
+                    txnid => Some(txnid.parse().ok()?),
+                },
+            })
+        };
+        Ok(lines.iter().filter_map(|line| slot(line)).collect())
+    }
+
+    fn reader_check(&self) -> Result<usize, Self::Error> {
+        let mut dead: c_int = 0;
+        let result = unsafe { mdb_reader_check(self.lmdbenv.env(), &mut dead) };
+        if result != 0 {
+            return Err(ErrorImpl::LmdbError(LmdbError::from_err_code(result)));
+        }
+        if dead > 0 {
+            warn!("Cleared {} stale LMDB reader slots", dead);
+        }
+        Ok(dead as usize)
+    }
 
-        if self.env_path_type == EnvironmentPathType::NoSubDir {
-            // The option NO_SUB_DIR could change the default directory layout; therefore this should
-            // probably return the path used to create environment, along with the custom lockfile
-            // when available.
-            unimplemented!();
+    fn copy_to(&self, path: &Path, compact: bool) -> Result<(), Self::Error> {
+        let c_path = path_to_cstring(path).ok_or_else(|| ErrorImpl::UnsuitableEnvironmentPath(path.into()))?;
+        let flags = if compact {
+            MDB_CP_COMPACT
+        } else {
+            0
+        };
+        let result = unsafe { mdb_env_copy2(self.lmdbenv.env(), c_path.as_ptr(), flags) };
+        if result != 0 {
+            return Err(ErrorImpl::LmdbError(LmdbError::from_err_code(result)));
         }
+        Ok(())
+    }
+
+    fn get_files_on_disk(&self) -> Vec<PathBuf> {
+        let mut store = vec![];
 
-        let mut db_filename = self.path.clone();
-        db_filename.push("data.mdb");
+        // With NO_SUB_DIR, the environment is opened with the path of the data file itself,
+        // and LMDB names the lock file after it, by appending "-lock".
+        let (db_filename, lock_filename) = match self.env_path_type {
+            EnvironmentPathType::NoSubDir => {
+                let mut lock_filename = self.path.clone().into_os_string();
+                lock_filename.push("-lock");
+                (self.path.clone(), PathBuf::from(lock_filename))
+            },
+            EnvironmentPathType::SubDir => (self.path.join("data.mdb"), self.path.join("lock.mdb")),
+        };
         store.push(db_filename);
 
         if self.env_lock_type == EnvironmentLockType::Lockfile {
-            let mut lock_filename = self.path.clone();
-            lock_filename.push("lock.mdb");
             store.push(lock_filename);
         }
 
         store
     }
 }
+
+// Collects the lines that `mdb_reader_list` prints into the vector given as context.
+unsafe extern "C" fn push_line(msg: *const c_char, ctx: *mut c_void) -> c_int {
+    let lines = &mut *(ctx as *mut Vec<String>);
+    lines.push(CStr::from_ptr(msg).to_string_lossy().into_owned());
+    0
+}
+
+// Copies the key/value pairs of a database to another one. The cursor borrows the
+// transaction, so the pairs are put with LMDB directly, which keeps track of the cursor
+// while the transaction changes.
+fn copy_db(txn: &lmdb::RwTransaction, from: lmdb::Database, to: lmdb::Database) -> Result<(), LmdbError> {
+    let mut cursor = txn.open_ro_cursor(from)?;
+    for item in cursor.iter_start() {
+        let (key, value) = item?;
+        let mut key = MDB_val {
+            mv_size: key.len(),
+            mv_data: key.as_ptr() as *mut c_void,
+        };
+        let mut value = MDB_val {
+            mv_size: value.len(),
+            mv_data: value.as_ptr() as *mut c_void,
+        };
+        let result = unsafe { mdb_put(txn.txn(), to.dbi(), &mut key, &mut value, 0) };
+        if result != 0 {
+            return Err(LmdbError::from_err_code(result));
+        }
+    }
+    Ok(())
+}
+
+// Like the lmdb crate, passes paths to LMDB as bytes on Unix, and as UTF-8 on Windows.
+#[cfg(unix)]
+fn path_to_cstring(path: &Path) -> Option<CString> {
+    use std::os::unix::ffi::OsStrExt;
+    CString::new(path.as_os_str().as_bytes()).ok()
+}
+
+#[cfg(windows)]
+fn path_to_cstring(path: &Path) -> Option<CString> {
+    CString::new(path.to_str()?).ok()
+}
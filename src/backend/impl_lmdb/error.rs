@@ -43,6 +43,7 @@ impl Into<StoreError> for ErrorImpl {
         match self {
             ErrorImpl::LmdbError(lmdb::Error::Corrupted) => StoreError::DatabaseCorrupted,
             ErrorImpl::LmdbError(lmdb::Error::NotFound) => StoreError::KeyValuePairNotFound,
+            ErrorImpl::LmdbError(lmdb::Error::KeyExist) => StoreError::KeyExists,
             ErrorImpl::LmdbError(lmdb::Error::BadValSize) => StoreError::KeyValuePairBadSize,
             ErrorImpl::LmdbError(lmdb::Error::Invalid) => StoreError::FileInvalid,
             ErrorImpl::LmdbError(lmdb::Error::MapFull) => StoreError::MapFull,
@@ -8,12 +8,32 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use lmdb::Transaction;
+use std::{
+    cmp::Ordering,
+    mem,
+    ops::Bound,
+    sync::Arc,
+};
+
+use lmdb::{
+    Cursor,
+    Transaction,
+};
+use lmdb_sys::{
+    MDB_FIRST,
+    MDB_NEXT,
+    MDB_SET_RANGE,
+};
 
 use super::{
+    cursor::{
+        compare_keys,
+        sorts_keys_bytewise,
+    },
     DatabaseImpl,
     ErrorImpl,
     RoCursorImpl,
+    RwCursorImpl,
     WriteFlagsImpl,
 };
 use crate::backend::traits::{
@@ -23,8 +43,10 @@ use crate::backend::traits::{
     BackendRwTransaction,
 };
 
+// The second field counts the transaction as active, so that the map isn't grown
+// while it's in use.
 #[derive(Debug)]
-pub struct RoTransactionImpl<'t>(pub(crate) lmdb::RoTransaction<'t>);
+pub struct RoTransactionImpl<'t>(pub(crate) lmdb::RoTransaction<'t>, #[allow(dead_code)] pub(crate) Arc<()>);
 
 impl<'t> BackendRoTransaction for RoTransactionImpl<'t> {
     type Database = DatabaseImpl;
@@ -43,12 +65,50 @@ impl<'t> BackendRoCursorTransaction<'t> for RoTransactionImpl<'t> {
     type RoCursor = RoCursorImpl<'t>;
 
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
-        self.0.open_ro_cursor(db.0).map(RoCursorImpl).map_err(ErrorImpl::LmdbError)
+        self.0.open_ro_cursor(db.0).map(RoCursorImpl::new).map_err(ErrorImpl::LmdbError)
     }
 }
 
+// Nested transactions borrow their parent, next to which they're stored, so they're
+// declared first, for them to be dropped, and thus aborted, before it. The last field
+// counts the transaction as active, like for read transactions.
 #[derive(Debug)]
-pub struct RwTransactionImpl<'t>(pub(crate) lmdb::RwTransaction<'t>);
+pub struct RwTransactionImpl<'t> {
+    nested: NestedTransactions<'t>,
+    txn: lmdb::RwTransaction<'t>,
+    #[allow(dead_code)]
+    txns: Arc<()>,
+}
+
+// The nested transactions, innermost last, which are aborted from the innermost one
+// when dropped, since aborting a transaction frees the ones nested in it.
+#[derive(Debug)]
+struct NestedTransactions<'t>(Vec<lmdb::RwTransaction<'t>>);
+
+impl<'t> Drop for NestedTransactions<'t> {
+    fn drop(&mut self) {
+        while self.0.pop().is_some() {}
+    }
+}
+
+impl<'t> RwTransactionImpl<'t> {
+    pub(crate) fn new(txn: lmdb::RwTransaction<'t>, txns: Arc<()>) -> RwTransactionImpl<'t> {
+        RwTransactionImpl {
+            nested: NestedTransactions(vec![]),
+            txn,
+            txns,
+        }
+    }
+
+    // The innermost transaction, which LMDB expects all the operations to be made in.
+    fn current(&self) -> &lmdb::RwTransaction<'t> {
+        self.nested.0.last().unwrap_or(&self.txn)
+    }
+
+    fn current_mut(&mut self) -> &mut lmdb::RwTransaction<'t> {
+        self.nested.0.last_mut().unwrap_or(&mut self.txn)
+    }
+}
 
 impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
     type Database = DatabaseImpl;
@@ -56,40 +116,110 @@ impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
     type Flags = WriteFlagsImpl;
 
     fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
-        self.0.get(db.0, &key).map_err(ErrorImpl::LmdbError)
+        self.current().get(db.0, &key).map_err(ErrorImpl::LmdbError)
     }
 
     fn put(&mut self, db: &Self::Database, key: &[u8], value: &[u8], flags: Self::Flags) -> Result<(), Self::Error> {
-        self.0.put(db.0, &key, &value, flags.0).map_err(ErrorImpl::LmdbError)
+        self.current_mut().put(db.0, &key, &value, flags.0).map_err(ErrorImpl::LmdbError)
     }
 
     #[cfg(not(feature = "db-dup-sort"))]
     fn del(&mut self, db: &Self::Database, key: &[u8]) -> Result<(), Self::Error> {
-        self.0.del(db.0, &key, None).map_err(ErrorImpl::LmdbError)
+        self.current_mut().del(db.0, &key, None).map_err(ErrorImpl::LmdbError)
     }
 
     #[cfg(feature = "db-dup-sort")]
     fn del(&mut self, db: &Self::Database, key: &[u8], value: Option<&[u8]>) -> Result<(), Self::Error> {
-        self.0.del(db.0, &key, value).map_err(ErrorImpl::LmdbError)
+        self.current_mut().del(db.0, &key, value).map_err(ErrorImpl::LmdbError)
     }
 
     fn clear_db(&mut self, db: &Self::Database) -> Result<(), Self::Error> {
-        self.0.clear_db(db.0).map_err(ErrorImpl::LmdbError)
+        self.current_mut().clear_db(db.0).map_err(ErrorImpl::LmdbError)
+    }
+
+    // Walks over the range with a cursor, which moves to the following pair whenever
+    // the one it's on gets deleted.
+    fn del_range(&mut self, db: &Self::Database, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<usize, Self::Error> {
+        let txn = self.current_mut();
+        let (txn_ptr, dbi) = (txn.txn(), db.0.dbi());
+        let compare = |a, b| unsafe { compare_keys(txn_ptr, dbi, a, b) };
+        let mut cursor = txn.open_rw_cursor(db.0).map_err(ErrorImpl::LmdbError)?;
+        // LMDB rejects empty keys when seeking, but every key comes after them anyway.
+        let mut found = match start {
+            Bound::Included(key) | Bound::Excluded(key) if !key.is_empty() => {
+                cursor.get(Some(key), None, MDB_SET_RANGE)
+            },
+            _ => cursor.get(None, None, MDB_FIRST),
+        };
+        let mut deleted = 0;
+        loop {
+            let key = match found {
+                Ok((key, _)) => key.unwrap_or_default(),
+                Err(lmdb::Error::NotFound) => break,
+                Err(error) => return Err(ErrorImpl::LmdbError(error)),
+            };
+            let is_past_end = match end {
+                Bound::Included(end) => compare(key, end) == Ordering::Greater,
+                Bound::Excluded(end) => compare(key, end) != Ordering::Less,
+                Bound::Unbounded => false,
+            };
+            if is_past_end {
+                break;
+            }
+            if !matches!(start, Bound::Excluded(start) if compare(key, start) == Ordering::Equal) {
+                cursor.del(lmdb::WriteFlags::empty()).map_err(ErrorImpl::LmdbError)?;
+                deleted += 1;
+            }
+            found = cursor.get(None, None, MDB_NEXT);
+        }
+        Ok(deleted)
+    }
+
+    fn sorts_keys_bytewise(&self, db: &Self::Database) -> Result<bool, Self::Error> {
+        unsafe { sorts_keys_bytewise(self.current().txn(), db.0.dbi()) }
+    }
+
+    fn begin_nested(&mut self) -> Result<(), Self::Error> {
+        let txn = self.current_mut().begin_nested_txn().map_err(ErrorImpl::LmdbError)?;
+        // Sound since nested transactions are always finished before their parent, whose
+        // LMDB transaction doesn't move along with its handle.
+        let txn = unsafe { mem::transmute::<lmdb::RwTransaction<'_>, lmdb::RwTransaction<'t>>(txn) };
+        self.nested.0.push(txn);
+        Ok(())
     }
 
-    fn commit(self) -> Result<(), Self::Error> {
-        self.0.commit().map_err(ErrorImpl::LmdbError)
+    fn commit_nested(&mut self) -> Result<(), Self::Error> {
+        match self.nested.0.pop() {
+            Some(txn) => txn.commit().map_err(ErrorImpl::LmdbError),
+            None => Ok(()),
+        }
+    }
+
+    fn abort_nested(&mut self) {
+        self.nested.0.pop();
+    }
+
+    fn commit(mut self) -> Result<(), Self::Error> {
+        while !self.nested.0.is_empty() {
+            self.commit_nested()?;
+        }
+        self.txn.commit().map_err(ErrorImpl::LmdbError)
     }
 
     fn abort(self) {
-        self.0.abort()
+        // Dropping the transactions aborts them, the nested ones first.
     }
 }
 
-impl<'t> BackendRwCursorTransaction<'t> for RwTransactionImpl<'t> {
+impl<'t, 'e> BackendRwCursorTransaction<'t> for RwTransactionImpl<'e> {
     type RoCursor = RoCursorImpl<'t>;
+    type RwCursor = RwCursorImpl<'t>;
 
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
-        self.0.open_ro_cursor(db.0).map(RoCursorImpl).map_err(ErrorImpl::LmdbError)
+        self.current().open_ro_cursor(db.0).map(RoCursorImpl::new).map_err(ErrorImpl::LmdbError)
+    }
+
+    fn open_rw_cursor(&'t mut self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
+        self.current_mut().open_rw_cursor(db.0).map(RwCursorImpl).map_err(ErrorImpl::LmdbError)
     }
 }
@@ -10,14 +10,19 @@
 
 mod cursor;
 mod database;
+mod disk;
 mod environment;
 mod error;
 mod flags;
+mod format;
 mod info;
 mod iter;
+mod lock;
+mod order;
 mod snapshot;
 mod stat;
 mod transaction;
+mod wal;
 
 pub use cursor::{
     RoCursorImpl,
@@ -8,35 +8,95 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    iter,
+};
+
 use super::{
     snapshot::Snapshot,
+    wal::Change,
+    DatabaseImpl,
+    ErrorImpl,
     IterImpl,
 };
-use crate::backend::traits::BackendRoCursor;
+use crate::backend::traits::{
+    BackendPositionedCursor,
+    BackendRoCursor,
+    BackendRwCursor,
+};
+
+type PairRef<'c> = (&'c [u8], &'c [u8]);
 
 #[derive(Debug)]
-pub struct RoCursorImpl<'c>(pub(crate) &'c Snapshot);
+pub struct RoCursorImpl<'c> {
+    snapshot: &'c Snapshot,
+    // The key/value pair the cursor is on, if it was positioned.
+    current: Option<PairRef<'c>>,
+}
+
+impl<'c> RoCursorImpl<'c> {
+    pub(crate) fn new(snapshot: &'c Snapshot) -> RoCursorImpl<'c> {
+        RoCursorImpl {
+            snapshot,
+            current: None,
+        }
+    }
+
+    fn move_to(&mut self, found: Option<PairRef<'c>>) -> Option<Result<PairRef<'c>, ErrorImpl>> {
+        self.current = Some(found?);
+        found.map(Ok)
+    }
+
+    fn seek_to(&mut self, found: Option<PairRef<'c>>) -> Option<Result<PairRef<'c>, ErrorImpl>> {
+        self.current = None;
+        self.move_to(found)
+    }
+
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn dup_after(&self) -> Option<PairRef<'c>> {
+        None
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    fn dup_after(&self) -> Option<PairRef<'c>> {
+        let (key, value) = self.current?;
+        self.snapshot.dup_after(key, value)
+    }
+
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn dup_before(&self) -> Option<PairRef<'c>> {
+        None
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    fn dup_before(&self) -> Option<PairRef<'c>> {
+        let (key, value) = self.current?;
+        self.snapshot.dup_before(key, value)
+    }
+}
 
 #[cfg(not(feature = "db-dup-sort"))]
 impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
     type Iter = IterImpl<'c>;
 
     fn into_iter(self) -> Self::Iter {
-        IterImpl(Box::new(self.0.iter()))
+        IterImpl(Box::new(self.snapshot.iter()))
     }
 
     fn into_iter_from<K>(self, key: K) -> Self::Iter
     where
         K: AsRef<[u8]> + 'c,
     {
-        IterImpl(Box::new(self.0.iter().skip_while(move |&(k, _)| k < key.as_ref())))
+        IterImpl(Box::new(self.snapshot.iter_from(key.as_ref())))
     }
 
     fn into_iter_dup_of<K>(self, key: K) -> Self::Iter
     where
         K: AsRef<[u8]> + 'c,
     {
-        IterImpl(Box::new(self.0.iter().filter(move |&(k, _)| k == key.as_ref())))
+        IterImpl(Box::new(self.snapshot.iter_dup_of(key.as_ref())))
     }
 }
 
@@ -45,7 +105,7 @@ impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
     type Iter = IterImpl<'c>;
 
     fn into_iter(self) -> Self::Iter {
-        let flattened = self.0.iter().flat_map(|(key, values)| values.map(move |value| (key, value)));
+        let flattened = self.snapshot.iter().flat_map(|(key, values)| values.map(move |value| (key, value)));
         IterImpl(Box::new(flattened))
     }
 
@@ -53,8 +113,8 @@ impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
     where
         K: AsRef<[u8]> + 'c,
     {
-        let skipped = self.0.iter().skip_while(move |&(k, _)| k < key.as_ref());
-        let flattened = skipped.flat_map(|(key, values)| values.map(move |value| (key, value)));
+        let found = self.snapshot.iter_from(key.as_ref());
+        let flattened = found.flat_map(|(key, values)| values.map(move |value| (key, value)));
         IterImpl(Box::new(flattened))
     }
 
@@ -62,33 +122,200 @@ impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
     where
         K: AsRef<[u8]> + 'c,
     {
-        let filtered = self.0.iter().filter(move |&(k, _)| k == key.as_ref());
-        let flattened = filtered.flat_map(|(key, values)| values.map(move |value| (key, value)));
+        let found = self.snapshot.iter_dup_of(key.as_ref());
+        let flattened = found.flat_map(|(key, values)| values.map(move |value| (key, value)));
         IterImpl(Box::new(flattened))
     }
 }
 
+impl<'c> BackendPositionedCursor<'c> for RoCursorImpl<'c> {
+    type Error = ErrorImpl;
+
+    fn first(&mut self) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.move_to(self.snapshot.pair_after(None))
+    }
+
+    fn last(&mut self) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.move_to(self.snapshot.pair_before(None))
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.seek_to(self.snapshot.seek(key))
+    }
+
+    fn seek_exact(&mut self, key: &[u8]) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.seek_to(self.snapshot.seek(key).filter(|&(found, _)| found == key))
+    }
+
+    fn next(&mut self) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.move_to(self.snapshot.pair_after(self.current))
+    }
+
+    fn prev(&mut self) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.move_to(self.snapshot.pair_before(self.current))
+    }
+
+    fn next_dup(&mut self) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.move_to(self.dup_after())
+    }
+
+    fn prev_dup(&mut self) -> Option<Result<PairRef<'c>, Self::Error>> {
+        self.move_to(self.dup_before())
+    }
+
+    fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.snapshot.compare_keys(a, b)
+    }
+
+    fn sorts_keys_bytewise(&self) -> Result<bool, Self::Error> {
+        Ok(self.snapshot.sorts_keys_bytewise())
+    }
+}
+
+type Pair = (Box<[u8]>, Box<[u8]>);
+
 #[derive(Debug)]
-pub struct RwCursorImpl<'c>(&'c mut Snapshot);
+pub struct RwCursorImpl<'c> {
+    db: DatabaseImpl,
+    snapshot: &'c mut Snapshot,
+    changes: &'c mut HashMap<DatabaseImpl, Vec<Change>>,
+    // The key/value pair the cursor is on, which is kept around after it's deleted
+    // in order to know where to move next.
+    current: Option<Pair>,
+    deleted: bool,
+}
+
+impl<'c> RwCursorImpl<'c> {
+    pub(crate) fn new(
+        db: DatabaseImpl,
+        snapshot: &'c mut Snapshot,
+        changes: &'c mut HashMap<DatabaseImpl, Vec<Change>>,
+    ) -> RwCursorImpl<'c> {
+        RwCursorImpl {
+            db,
+            snapshot,
+            changes,
+            current: None,
+            deleted: false,
+        }
+    }
+
+    fn current(&self) -> Result<Pair, ErrorImpl> {
+        self.current.clone().filter(|_| !self.deleted).ok_or(ErrorImpl::KeyValuePairNotFound)
+    }
+
+    // Applies a change the same way it is replayed from the log, and records it to
+    // be logged once the transaction commits.
+    fn record(&mut self, change: Change) {
+        change.apply(self.snapshot);
+        self.changes.entry(self.db).or_default().push(change);
+    }
+
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn is_dup_sort(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    fn is_dup_sort(&self) -> bool {
+        use super::DatabaseFlagsImpl;
+        self.snapshot.flags().contains(DatabaseFlagsImpl::DUP_SORT)
+    }
+
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn is_dup_fixed(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    fn is_dup_fixed(&self) -> bool {
+        use super::DatabaseFlagsImpl;
+        self.snapshot.flags().contains(DatabaseFlagsImpl::DUP_FIXED)
+    }
+
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn moves_among_dups(&self, _key: &[u8], _previous: &[u8], _value: &[u8]) -> bool {
+        false
+    }
+
+    // The only value of a key can be replaced by any other, but one of several has to
+    // keep its place among them, so that the cursor still visits each of them once.
+    #[cfg(feature = "db-dup-sort")]
+    fn moves_among_dups(&self, key: &[u8], previous: &[u8], value: &[u8]) -> bool {
+        if self.snapshot.compare_values(value, previous) == Ordering::Equal {
+            return false;
+        }
+        self.snapshot.dup_after(key, previous).is_some() || self.snapshot.dup_before(key, previous).is_some()
+    }
+}
 
 impl<'c> BackendRoCursor<'c> for RwCursorImpl<'c> {
     type Iter = IterImpl<'c>;
 
+    // Like with LMDB, iterating starts after the pair the cursor is on, if any.
     fn into_iter(self) -> Self::Iter {
-        unimplemented!()
+        let snapshot: &'c Snapshot = self.snapshot;
+        let current = self.current.as_ref().map(|(key, value)| (key.as_ref(), value.as_ref()));
+        let first = snapshot.pair_after(current);
+        IterImpl(Box::new(iter::successors(first, move |&pair| snapshot.pair_after(Some(pair)))))
     }
 
-    fn into_iter_from<K>(self, _key: K) -> Self::Iter
+    fn into_iter_from<K>(self, key: K) -> Self::Iter
     where
         K: AsRef<[u8]> + 'c,
     {
-        unimplemented!()
+        RoCursorImpl::new(self.snapshot).into_iter_from(key)
     }
 
-    fn into_iter_dup_of<K>(self, _key: K) -> Self::Iter
+    fn into_iter_dup_of<K>(self, key: K) -> Self::Iter
     where
         K: AsRef<[u8]> + 'c,
     {
-        unimplemented!()
+        RoCursorImpl::new(self.snapshot).into_iter_dup_of(key)
+    }
+}
+
+impl<'c> BackendRwCursor<'c> for RwCursorImpl<'c> {
+    type Error = ErrorImpl;
+
+    #[allow(clippy::type_complexity)]
+    fn next(&mut self) -> Option<Result<(&[u8], &[u8]), Self::Error>> {
+        let current = self.current.as_ref().map(|(key, value)| (key.as_ref(), value.as_ref()));
+        let (key, value) = self.snapshot.pair_after(current)?;
+        self.current = Some((Box::from(key), Box::from(value)));
+        self.deleted = false;
+        self.current.as_ref().map(|(key, value)| Ok((key.as_ref(), value.as_ref())))
+    }
+
+    fn del_current(&mut self) -> Result<(), Self::Error> {
+        let (key, value) = self.current()?;
+        let change = if self.is_dup_sort() {
+            Change::DelExact(key, value)
+        } else {
+            Change::Del(key)
+        };
+        self.record(change);
+        self.deleted = true;
+        Ok(())
+    }
+
+    // The size of the databases is only checked when committing, whereas writing
+    // through the transaction checks it right away.
+    fn put_current(&mut self, value: &[u8]) -> Result<(), Self::Error> {
+        let (key, previous) = self.current()?;
+        if self.is_dup_sort() {
+            if self.is_dup_fixed() && value.len() != previous.len() {
+                return Err(ErrorImpl::KeyValuePairBadSize);
+            }
+            if self.moves_among_dups(&key, &previous, value) {
+                return Err(ErrorImpl::InvalidArgument);
+            }
+            self.record(Change::DelExact(key.clone(), previous));
+            self.record(Change::PutDup(key.clone(), value.into()));
+        } else {
+            self.record(Change::Put(key.clone(), value.into()));
+        }
+        self.current = Some((key, value.into()));
+        Ok(())
     }
 }
@@ -9,13 +9,9 @@
 // specific language governing permissions and limitations under the License.
 
 use id_arena::Id;
-use serde_derive::{
-    Deserialize,
-    Serialize,
-};
 
 use super::{
-    snapshot::Snapshot,
+    wal::Change,
     DatabaseFlagsImpl,
 };
 use crate::backend::traits::BackendDatabase;
@@ -25,23 +21,99 @@ pub struct DatabaseImpl(pub(crate) Id<Database>);
 
 impl BackendDatabase for DatabaseImpl {}
 
-#[derive(Debug, Serialize, Deserialize)]
+/// What the environment knows about a database without having read it. Databases
+/// are stored in files of their own, which are only read when the database is first
+/// opened. Until then, the changes made to them by the log are kept aside.
+#[derive(Debug)]
 pub struct Database {
-    snapshot: Snapshot,
+    flags: DatabaseFlagsImpl,
+    file: Option<u64>,
+    pending: Vec<Change>,
+    dirty: bool,
+    // The size of the file, which stands for the size of the database until loaded.
+    size: usize,
+    // The number of entries in the file, as recorded by the catalog.
+    entries: usize,
 }
 
 impl Database {
-    pub(crate) fn new(flags: Option<DatabaseFlagsImpl>, snapshot: Option<Snapshot>) -> Database {
+    pub(crate) fn new(flags: Option<DatabaseFlagsImpl>, file: Option<u64>) -> Database {
         Database {
-            snapshot: snapshot.unwrap_or_else(|| Snapshot::new(flags)),
+            flags: flags.unwrap_or_default(),
+            file,
+            pending: vec![],
+            // Databases which were never checkpointed need a file to be written.
+            dirty: file.is_none(),
+            size: 0,
+            entries: 0,
+        }
+    }
+
+    /// The flags the database was created with, if it isn't stored in a file yet.
+    pub(crate) fn flags(&self) -> DatabaseFlagsImpl {
+        self.flags
+    }
+
+    /// The number of the file storing the database, if it was ever checkpointed.
+    pub(crate) fn file(&self) -> Option<u64> {
+        self.file
+    }
+
+    /// The changes made to the database since the file was written, in order.
+    pub(crate) fn pending(&self) -> &[Change] {
+        &self.pending
+    }
+
+    /// The size of the database, as of the last time it was written to its file.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn set_size(&mut self, size: usize) {
+        self.size = size;
+    }
+
+    /// The number of entries of the database, as of the last time it was written to
+    /// its file.
+    pub(crate) fn entries(&self) -> usize {
+        self.entries
+    }
+
+    pub(crate) fn set_entries(&mut self, entries: usize) {
+        self.entries = entries;
+    }
+
+    /// Whether the file of the database is out of date.
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.dirty || !self.pending.is_empty()
+    }
+
+    /// Keeps changes read from the log aside, until the database is loaded.
+    pub(crate) fn log(&mut self, changes: Vec<Change>) {
+        if changes.iter().any(|change| matches!(change, Change::Clear)) {
+            self.pending.clear();
+        }
+        self.pending.extend(changes);
+    }
+
+    /// Forgets about the pending changes, once they were applied to a snapshot
+    /// of the database, which is now the one that needs to be written.
+    pub(crate) fn loaded(&mut self) {
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            self.dirty = true;
         }
     }
 
-    pub(crate) fn snapshot(&self) -> Snapshot {
-        self.snapshot.clone()
+    pub(crate) fn touch(&mut self) {
+        self.dirty = true;
     }
 
-    pub(crate) fn replace(&mut self, snapshot: Snapshot) -> Snapshot {
-        std::mem::replace(&mut self.snapshot, snapshot)
+    pub(crate) fn checkpointed(&mut self, file: u64, size: usize, entries: usize) {
+        self.file = Some(file);
+        self.size = size;
+        self.entries = entries;
+        self.pending.clear();
+        self.dirty = false;
     }
 }
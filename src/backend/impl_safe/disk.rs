@@ -0,0 +1,164 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::{
+    ffi::OsString,
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+const TEMP_FILENAME_SUFFIX: &str = ".tmp";
+
+/// The individual steps of an atomic write, in the order in which they happen.
+/// A crash in between any two of them must leave either the previous or the new
+/// contents of the file readable, never a mix of both. Appending to the log has
+/// steps of its own, after which a crash must leave the previous records readable,
+/// and the new one either complete or ignored.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum WriteStep {
+    CreateTemp,
+    WriteTemp,
+    SyncTemp,
+    Rename,
+    SyncDir,
+    WriteLog,
+    SyncLog,
+}
+
+/// Returns the path of the temporary file used while atomically writing `path`.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(TEMP_FILENAME_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Replaces the contents of the file at `path` with `bytes`, such that a crash at
+/// any point leaves either the old or the new contents on disk.
+///
+/// The data is first written to a sibling temporary file, which is flushed to disk
+/// and then renamed over the destination. Finally, the parent directory is flushed
+/// as well, so that the rename itself survives a power loss.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+
+    crash_point(WriteStep::CreateTemp, None)?;
+    let mut file = File::create(&temp)?;
+
+    crash_point(WriteStep::WriteTemp, Some((&mut file, bytes)))?;
+    file.write_all(bytes)?;
+
+    crash_point(WriteStep::SyncTemp, None)?;
+    file.sync_all()?;
+    drop(file);
+
+    crash_point(WriteStep::Rename, None)?;
+    fs::rename(&temp, path)?;
+
+    crash_point(WriteStep::SyncDir, None)?;
+    sync_dir(path)
+}
+
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    // Directories can't be opened as files on Windows, where renames are
+    // journaled by the file system anyway.
+    Ok(())
+}
+
+#[cfg(not(test))]
+#[inline(always)]
+pub(crate) fn crash_point(_step: WriteStep, _partial: Option<(&mut File, &[u8])>) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+thread_local! {
+    static CRASH_AT: std::cell::Cell<Option<WriteStep>> = std::cell::Cell::new(None);
+}
+
+/// Simulates the process dying right before `step`. When a `partial` write is in
+/// progress, half of the data is flushed first, like an interrupted `write(2)`.
+#[cfg(test)]
+pub(crate) fn crash_point(step: WriteStep, partial: Option<(&mut File, &[u8])>) -> io::Result<()> {
+    if CRASH_AT.with(|crash_at| crash_at.get()) != Some(step) {
+        return Ok(());
+    }
+    if let Some((file, bytes)) = partial {
+        file.write_all(&bytes[..bytes.len() / 2])?;
+        file.sync_all()?;
+    }
+    Err(io::Error::new(io::ErrorKind::Other, format!("simulated crash before {:?}", step)))
+}
+
+#[cfg(test)]
+pub(crate) fn crash_at(step: Option<WriteStep>) {
+    CRASH_AT.with(|crash_at| crash_at.set(step));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::Builder;
+
+    const STEPS: [WriteStep; 5] =
+        [WriteStep::CreateTemp, WriteStep::WriteTemp, WriteStep::SyncTemp, WriteStep::Rename, WriteStep::SyncDir];
+
+    #[test]
+    fn test_write_atomically() {
+        let root = Builder::new().prefix("test_write_atomically").tempdir().expect("tempdir");
+        let path = root.path().join("file");
+
+        write_atomically(&path, b"hello").expect("written");
+        assert_eq!(fs::read(&path).expect("read"), b"hello");
+        assert!(!temp_path(&path).exists());
+
+        write_atomically(&path, b"world!").expect("written");
+        assert_eq!(fs::read(&path).expect("read"), b"world!");
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn test_crash_at_each_step() {
+        for &step in STEPS.iter() {
+            let root = Builder::new().prefix("test_crash_at_each_step").tempdir().expect("tempdir");
+            let path = root.path().join("file");
+            write_atomically(&path, b"old contents").expect("written");
+
+            crash_at(Some(step));
+            write_atomically(&path, b"new contents").expect_err("crashed");
+            crash_at(None);
+
+            let expected: &[u8] = match step {
+                WriteStep::SyncDir => b"new contents",
+                _ => b"old contents",
+            };
+            assert_eq!(fs::read(&path).expect("read"), expected, "crash before {:?}", step);
+        }
+    }
+}
@@ -9,19 +9,31 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
-    borrow::Cow,
-    collections::HashMap,
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    ffi::OsString,
     fs,
-    ops::DerefMut,
+    io,
     path::{
         Path,
         PathBuf,
     },
+    process,
     sync::{
+        atomic::{
+            AtomicU64,
+            AtomicUsize,
+            Ordering,
+        },
         Arc,
+        Mutex,
+        MutexGuard,
         RwLock,
         RwLockReadGuard,
         RwLockWriteGuard,
+        Weak,
     },
 };
 
@@ -30,6 +42,19 @@ use log::warn;
 
 use super::{
     database::Database,
+    disk::write_atomically,
+    format,
+    lock::{
+        LockFile,
+        LockGuard,
+    },
+    snapshot::Snapshot,
+    wal::{
+        self,
+        Change,
+        DatabaseChanges,
+        Record,
+    },
     DatabaseFlagsImpl,
     DatabaseImpl,
     EnvironmentFlagsImpl,
@@ -39,15 +64,32 @@ use super::{
     RwTransactionImpl,
     StatImpl,
 };
-use crate::backend::traits::{
-    BackendEnvironment,
-    BackendEnvironmentBuilder,
+use crate::backend::{
+    common::{
+        MapGrowth,
+        ReaderSlot,
+    },
+    traits::{
+        BackendEnvironment,
+        BackendEnvironmentBuilder,
+    },
 };
 
 const DEFAULT_DB_FILENAME: &str = "data.safe.bin";
+const DEFAULT_LOG_FILENAME: &str = "data.safe.log";
+const DEFAULT_LOCK_FILENAME: &str = "lock.safe";
+const DATABASE_FILENAME_PREFIX: &str = "data.safe.";
+const DATABASE_FILENAME_SUFFIX: &str = ".bin";
+const NO_SUB_DIR_LOG_SUFFIX: &str = "-log";
+const NO_SUB_DIR_LOCK_SUFFIX: &str = "-lock";
+const DEFAULT_CHECKPOINT_THRESHOLD: usize = 1024 * 1024;
 
 type DatabaseArena = Arena<Database>;
 type DatabaseNameMap = HashMap<Option<String>, DatabaseImpl>;
+type DatabaseMap = HashMap<Option<String>, Database>;
+type SnapshotMap = HashMap<Option<String>, Snapshot>;
+// The number of the file of each database, along with how many entries it holds.
+type Catalog = HashMap<Option<String>, (u64, usize)>;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub struct EnvironmentBuilderImpl {
@@ -57,6 +99,18 @@ pub struct EnvironmentBuilderImpl {
     map_size: Option<usize>,
     make_dir_if_needed: bool,
     discard_if_corrupted: bool,
+    map_growth: Option<MapGrowth>,
+    checkpoint_threshold: usize,
+}
+
+impl EnvironmentBuilderImpl {
+    /// Sets the size in bytes past which the log of committed transactions is folded
+    /// back into the snapshot of the environment. Smaller values make opening an
+    /// environment faster, at the expense of rewriting the snapshot more often.
+    pub fn set_checkpoint_threshold(&mut self, checkpoint_threshold: usize) -> &mut Self {
+        self.checkpoint_threshold = checkpoint_threshold;
+        self
+    }
 }
 
 impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
@@ -72,6 +126,8 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
             map_size: None,
             make_dir_if_needed: false,
             discard_if_corrupted: false,
+            map_growth: None,
+            checkpoint_threshold: DEFAULT_CHECKPOINT_THRESHOLD,
         }
     }
 
@@ -108,17 +164,38 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
         self
     }
 
+    fn set_map_growth(&mut self, map_growth: MapGrowth) -> &mut Self {
+        self.map_growth = Some(map_growth);
+        self
+    }
+
+    fn set_reader_check_on_open(&mut self, _reader_check_on_open: bool) -> &mut Self {
+        // There are never any stale readers to clear, see `reader_check()`.
+        self
+    }
+
     fn open(&self, path: &Path) -> Result<Self::Environment, Self::Error> {
-        // Technically NO_SUB_DIR should change these checks here, but they're both currently
-        // unimplemented with this storage backend.
-        if !path.is_dir() {
+        // With NO_SUB_DIR, the path is the data file itself, which is only created by the
+        // first checkpoint. The directory containing it must exist however.
+        let dir = if self.flags.contains(EnvironmentFlagsImpl::NO_SUB_DIR) {
+            if path.is_dir() {
+                return Err(ErrorImpl::UnsuitableEnvironmentPath(path.into()));
+            }
+            path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."))
+        } else {
+            path
+        };
+        if !dir.is_dir() {
             if !self.make_dir_if_needed {
                 return Err(ErrorImpl::UnsuitableEnvironmentPath(path.into()));
             }
-            fs::create_dir_all(path)?;
+            fs::create_dir_all(dir)?;
         }
         let mut env = EnvironmentImpl::new(path, self.flags, self.max_readers, self.max_dbs, self.map_size)?;
-        env.read_from_disk(self.discard_if_corrupted)?;
+        env.checkpoint_threshold = self.checkpoint_threshold;
+        env.discard_if_corrupted = self.discard_if_corrupted;
+        env.map_growth = self.map_growth;
+        env.read_from_disk()?;
         Ok(env)
     }
 }
@@ -127,12 +204,50 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
 pub(crate) struct EnvironmentDbs {
     pub(crate) arena: DatabaseArena,
     pub(crate) name_map: DatabaseNameMap,
+    // The databases that were loaded in memory, as of the last commit. Transactions
+    // hold on to the map, and only copy the databases they change.
+    pub(crate) snapshots: Arc<HashMap<DatabaseImpl, Snapshot>>,
+    // Databases created since the last commit, which aren't on disk yet.
+    pub(crate) unlogged: HashSet<DatabaseImpl>,
+}
+
+impl EnvironmentDbs {
+    /// The size of all the databases once serialized. Databases which aren't loaded
+    /// are assumed to be the size of their file.
+    pub(crate) fn size(&self) -> usize {
+        let sizes = self.name_map.values().map(|id| {
+            match self.snapshots.get(id) {
+                Some(snapshot) => snapshot.size(),
+                None => self.arena[id.0].size(),
+            }
+        });
+        sizes.sum()
+    }
+
+    pub(crate) fn name_of(&self, id: DatabaseImpl) -> Option<&Option<String>> {
+        self.name_map.iter().find(|&(_, &other)| other == id).map(|(name, _)| name)
+    }
+
+    fn open_or_create(&mut self, name: Option<String>, flags: DatabaseFlagsImpl) -> DatabaseImpl {
+        let parts = EnvironmentDbsRefMut::from(self);
+        let arena = parts.arena;
+        let snapshots = parts.snapshots;
+        let unlogged = parts.unlogged;
+        *parts.name_map.entry(name).or_insert_with(|| {
+            let id = DatabaseImpl(arena.alloc(Database::new(Some(flags), None)));
+            Arc::make_mut(snapshots).insert(id, Snapshot::new(Some(flags)));
+            unlogged.insert(id);
+            id
+        })
+    }
 }
 
 #[derive(Debug)]
 pub(crate) struct EnvironmentDbsRefMut<'a> {
     pub(crate) arena: &'a mut DatabaseArena,
     pub(crate) name_map: &'a mut DatabaseNameMap,
+    pub(crate) snapshots: &'a mut Arc<HashMap<DatabaseImpl, Snapshot>>,
+    pub(crate) unlogged: &'a mut HashSet<DatabaseImpl>,
 }
 
 impl<'a> From<&'a mut EnvironmentDbs> for EnvironmentDbsRefMut<'a> {
@@ -140,37 +255,113 @@ impl<'a> From<&'a mut EnvironmentDbs> for EnvironmentDbsRefMut<'a> {
         EnvironmentDbsRefMut {
             arena: &mut dbs.arena,
             name_map: &mut dbs.name_map,
+            snapshots: &mut dbs.snapshots,
+            unlogged: &mut dbs.unlogged,
         }
     }
 }
 
 #[derive(Debug)]
 pub struct EnvironmentImpl {
-    path: PathBuf,
+    flags: EnvironmentFlagsImpl,
+    data_path: PathBuf,
+    log_path: PathBuf,
     max_dbs: usize,
+    max_readers: usize,
+    map_size: AtomicUsize,
     dbs: RwLock<EnvironmentDbs>,
     ro_txns: Arc<()>,
     rw_txns: Arc<()>,
+    readers: Mutex<Vec<Weak<ReaderSlot>>>,
+    writer: Mutex<()>,
+    log_len: Mutex<u64>,
+    checkpoint_threshold: usize,
+    discard_if_corrupted: bool,
+    map_growth: Option<MapGrowth>,
+    lock: Option<LockFile>,
+    // The generation of the environment on disk that the in-memory state matches.
+    generation: AtomicU64,
+}
+
+// Numbers threads in the order they first begin a read transaction, to identify them
+// in the reader table, since the ids of the standard library can't be turned into
+// numbers.
+fn thread_number() -> u64 {
+    static NEXT_THREAD_NUMBER: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static THREAD_NUMBER: u64 = NEXT_THREAD_NUMBER.fetch_add(1, Ordering::SeqCst);
+    }
+    THREAD_NUMBER.with(|number| *number)
+}
+
+// Returns the path of a file living next to the data file, when the environment
+// isn't a directory of its own, e.g. `data.bin-lock` for `data.bin`.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
 }
 
 impl EnvironmentImpl {
-    fn serialize(&self) -> Result<Vec<u8>, ErrorImpl> {
-        let dbs = self.dbs.read().map_err(|_| ErrorImpl::EnvPoisonError)?;
-        let data: HashMap<_, _> = dbs.name_map.iter().map(|(name, id)| (name, &dbs.arena[id.0])).collect();
-        Ok(bincode::serialize(&data)?)
+    fn serialize(dbs: &EnvironmentDbs) -> Result<Vec<u8>, ErrorImpl> {
+        let catalog: Catalog = dbs
+            .name_map
+            .iter()
+            .filter_map(|(name, id)| {
+                let db = &dbs.arena[id.0];
+                Some((name.clone(), (db.file()?, db.entries())))
+            })
+            .collect();
+        format::encode(&bincode::serialize(&catalog)?)
     }
 
-    fn deserialize(bytes: &[u8], discard_if_corrupted: bool) -> Result<(DatabaseArena, DatabaseNameMap), ErrorImpl> {
-        let mut arena = DatabaseArena::new();
-        let mut name_map = HashMap::new();
-        let data: HashMap<_, _> = match bincode::deserialize(&bytes) {
-            Err(_) if discard_if_corrupted => Ok(HashMap::new()),
+    // Reads the data file, which either contains the catalog of the databases, or all
+    // of them when written by older versions. In the latter case, the databases are
+    // returned as snapshots, which get written to files of their own by the next
+    // checkpoint.
+    fn deserialize(bytes: &[u8], discard_if_corrupted: bool) -> Result<(DatabaseMap, SnapshotMap), ErrorImpl> {
+        let result = format::decode(bytes).and_then(|(version, payload)| {
+            match version {
+                format::LEGACY_VERSION | format::VERSION_1 => {
+                    let snapshots: SnapshotMap = bincode::deserialize(payload)?;
+                    let dbs = snapshots.iter().map(|(name, snapshot)| {
+                        let db = Database::new(Some(*snapshot.flags()), None);
+                        (name.clone(), db)
+                    });
+                    Ok((dbs.collect(), snapshots))
+                },
+                _ => {
+                    let catalog: Catalog = bincode::deserialize(payload)?;
+                    let dbs = catalog.into_iter().map(|(name, (file, entries))| {
+                        let mut db = Database::new(None, Some(file));
+                        db.set_entries(entries);
+                        (name, db)
+                    });
+                    Ok((dbs.collect(), HashMap::new()))
+                },
+            }
+        });
+        match result {
+            Err(ErrorImpl::FileCorrupted) | Err(ErrorImpl::BincodeError(_)) if discard_if_corrupted => {
+                Ok(Default::default())
+            },
             result => result,
-        }?;
-        for (name, db) in data {
-            name_map.insert(name, DatabaseImpl(arena.alloc(db)));
         }
-        Ok((arena, name_map))
+    }
+
+    // Reads the file of a database. Those always have a header, since they were
+    // introduced after it was.
+    fn deserialize_db(bytes: &[u8], discard_if_corrupted: bool) -> Result<Option<Snapshot>, ErrorImpl> {
+        let result = format::decode(bytes).and_then(|(version, payload)| {
+            match version {
+                format::LEGACY_VERSION => Err(ErrorImpl::FileCorrupted),
+                _ => Ok(bincode::deserialize(payload)?),
+            }
+        });
+        match result {
+            Err(ErrorImpl::FileCorrupted) | Err(ErrorImpl::BincodeError(_)) if discard_if_corrupted => Ok(None),
+            result => result.map(Some),
+        }
     }
 }
 
@@ -182,53 +373,450 @@ impl EnvironmentImpl {
         max_dbs: Option<usize>,
         map_size: Option<usize>,
     ) -> Result<EnvironmentImpl, ErrorImpl> {
-        if !flags.is_empty() {
-            warn!("Ignoring `flags={:?}`", flags);
-        }
-        if let Some(max_readers) = max_readers {
-            warn!("Ignoring `max_readers={}`", max_readers);
-        }
-        if let Some(map_size) = map_size {
-            warn!("Ignoring `map_size={}`", map_size);
-        }
+        let (data_path, log_path, lock_path) = if flags.contains(EnvironmentFlagsImpl::NO_SUB_DIR) {
+            (path.to_path_buf(), sibling_path(path, NO_SUB_DIR_LOG_SUFFIX), sibling_path(path, NO_SUB_DIR_LOCK_SUFFIX))
+        } else {
+            (path.join(DEFAULT_DB_FILENAME), path.join(DEFAULT_LOG_FILENAME), path.join(DEFAULT_LOCK_FILENAME))
+        };
+        let lock = if flags.contains(EnvironmentFlagsImpl::NO_LOCK) {
+            None
+        } else if flags.contains(EnvironmentFlagsImpl::READ_ONLY) {
+            Some(LockFile::open_read_only(&lock_path))
+        } else {
+            Some(LockFile::new(&lock_path)?)
+        };
 
         Ok(EnvironmentImpl {
-            path: path.to_path_buf(),
-            max_dbs: max_dbs.unwrap_or(std::usize::MAX),
+            flags,
+            data_path,
+            log_path,
+            max_dbs: max_dbs.unwrap_or(usize::MAX),
+            max_readers: max_readers.unwrap_or(usize::MAX),
+            map_size: AtomicUsize::new(map_size.unwrap_or(usize::MAX)),
             dbs: RwLock::new(EnvironmentDbs {
                 arena: DatabaseArena::new(),
                 name_map: HashMap::new(),
+                snapshots: Arc::new(HashMap::new()),
+                unlogged: HashSet::new(),
             }),
             ro_txns: Arc::new(()),
             rw_txns: Arc::new(()),
+            readers: Mutex::new(vec![]),
+            writer: Mutex::new(()),
+            log_len: Mutex::new(0),
+            checkpoint_threshold: DEFAULT_CHECKPOINT_THRESHOLD,
+            discard_if_corrupted: false,
+            map_growth: None,
+            lock,
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.flags.contains(EnvironmentFlagsImpl::READ_ONLY)
+    }
+
+    // Returns the path of the file storing the database with the given number.
+    fn db_path(&self, file: u64) -> PathBuf {
+        if self.flags.contains(EnvironmentFlagsImpl::NO_SUB_DIR) {
+            sibling_path(&self.data_path, &format!("-{}", file))
+        } else {
+            self.data_path.with_file_name(format!("{}{}{}", DATABASE_FILENAME_PREFIX, file, DATABASE_FILENAME_SUFFIX))
+        }
+    }
+
+    // Reads the catalog of the databases and the log, without reading the databases
+    // themselves. Must be called while holding a lock, so that no other process is
+    // writing to any of those files.
+    fn load(&self, discard_if_corrupted: bool) -> Result<(DatabaseMap, SnapshotMap, u64), ErrorImpl> {
+        let (mut data, mut snapshots) = match fs::metadata(&self.data_path) {
+            Ok(_) => Self::deserialize(&fs::read(&self.data_path)?, discard_if_corrupted)?,
+            Err(_) => Default::default(),
+        };
+        for db in data.values_mut() {
+            if let Some(file) = db.file() {
+                db.set_size(fs::metadata(self.db_path(file)).map_or(0, |metadata| metadata.len() as usize));
+            }
+        }
+
+        let (records, log_len) = wal::read(&self.log_path, discard_if_corrupted)?;
+        for record in records {
+            for DatabaseChanges {
+                name,
+                flags,
+                changes,
+            } in record
+            {
+                match (changes.as_slice(), snapshots.get_mut(&name)) {
+                    ([Change::Drop], _) => {
+                        data.remove(&name);
+                        snapshots.remove(&name);
+                    },
+                    ([Change::Rename(new_name)], _) => {
+                        let new_name = Some(new_name.clone());
+                        if let Some(db) = data.remove(&name) {
+                            data.insert(new_name.clone(), db);
+                        }
+                        if let Some(snapshot) = snapshots.remove(&name) {
+                            snapshots.insert(new_name, snapshot);
+                        }
+                    },
+                    (_, Some(snapshot)) => changes.iter().for_each(|change| change.apply(snapshot)),
+                    (_, None) => data.entry(name).or_insert_with(|| Database::new(Some(flags), None)).log(changes),
+                }
+            }
+        }
+        Ok((data, snapshots, log_len))
+    }
+
+    // The catalog knows about the entries and size of the databases which weren't
+    // loaded, so they're only read when the log changed them since.
+    fn db_stat_of(&self, dbs: &EnvironmentDbs, id: DatabaseImpl) -> Result<StatImpl, ErrorImpl> {
+        let db = &dbs.arena[id.0];
+        let (entries, size) = match dbs.snapshots.get(&id) {
+            Some(snapshot) => (snapshot.entries(), snapshot.size()),
+            None if db.pending().is_empty() => (db.entries(), db.size()),
+            None => {
+                let snapshot = self.read_db(db)?;
+                (snapshot.entries(), snapshot.size())
+            },
+        };
+        Ok(StatImpl {
+            entries,
+            bytes_on_disk: size,
         })
     }
 
-    pub(crate) fn read_from_disk(&mut self, discard_if_corrupted: bool) -> Result<(), ErrorImpl> {
-        let mut path = Cow::from(&self.path);
-        if fs::metadata(&path)?.is_dir() {
-            path.to_mut().push(DEFAULT_DB_FILENAME);
+    // Reads a database from its file, if any, and applies the changes of the log
+    // which aren't in the file yet.
+    fn read_db(&self, db: &Database) -> Result<Snapshot, ErrorImpl> {
+        let snapshot = match db.file() {
+            Some(file) => Self::deserialize_db(&fs::read(self.db_path(file))?, self.discard_if_corrupted)?,
+            None => None,
         };
-        if fs::metadata(&path).is_err() {
+        let mut snapshot = snapshot.unwrap_or_else(|| Snapshot::new(Some(db.flags())));
+        for change in db.pending() {
+            change.apply(&mut snapshot);
+        }
+        Ok(snapshot)
+    }
+
+    // Loads a database in memory, unless it already is.
+    fn load_db(&self, dbs: &mut EnvironmentDbs, id: DatabaseImpl) -> Result<(), ErrorImpl> {
+        if dbs.snapshots.contains_key(&id) {
             return Ok(());
+        }
+        let snapshot = self.read_db(&dbs.arena[id.0])?;
+        dbs.arena[id.0].loaded();
+        Arc::make_mut(&mut dbs.snapshots).insert(id, snapshot);
+        Ok(())
+    }
+
+    // Replaces the state of the databases with the one on disk. Databases keep their
+    // ids, so that stores opened beforehand remain valid, and the ones which were
+    // loaded are loaded again.
+    fn reload(&self, dbs: &mut EnvironmentDbs, data: DatabaseMap, mut snapshots: SnapshotMap) -> Result<(), ErrorImpl> {
+        // Databases which were dropped or renamed go away, except for the ones created
+        // by this process which weren't committed yet.
+        let unlogged = &dbs.unlogged;
+        dbs.name_map.retain(|name, id| data.contains_key(name) || unlogged.contains(id));
+        let mut loaded = (*dbs.snapshots).clone();
+        loaded.retain(|id, _| dbs.name_map.values().any(|other| other == id));
+        for (name, db) in data {
+            let id = match dbs.name_map.get(&name).copied() {
+                Some(id) => {
+                    dbs.arena[id.0] = db;
+                    id
+                },
+                None => {
+                    let id = DatabaseImpl(dbs.arena.alloc(db));
+                    dbs.name_map.insert(name.clone(), id);
+                    id
+                },
+            };
+            let snapshot = match snapshots.remove(&name) {
+                Some(snapshot) => snapshot,
+                None if loaded.contains_key(&id) => self.read_db(&dbs.arena[id.0])?,
+                None => continue,
+            };
+            dbs.arena[id.0].loaded();
+            loaded.insert(id, snapshot);
+        }
+        dbs.snapshots = Arc::new(loaded);
+        Ok(())
+    }
+
+    pub(crate) fn read_from_disk(&mut self) -> Result<(), ErrorImpl> {
+        let mut lock = match &self.lock {
+            Some(lock) => lock.shared()?,
+            None => None,
         };
-        let (arena, name_map) = Self::deserialize(&fs::read(&path)?, discard_if_corrupted)?;
-        self.dbs = RwLock::new(EnvironmentDbs {
-            arena,
-            name_map,
-        });
+        let (data, snapshots, log_len) = self.load(self.discard_if_corrupted)?;
+        let mut dbs = EnvironmentDbs {
+            arena: DatabaseArena::new(),
+            name_map: HashMap::new(),
+            snapshots: Arc::new(HashMap::new()),
+            unlogged: HashSet::new(),
+        };
+        self.reload(&mut dbs, data, snapshots)?;
+        self.dbs = RwLock::new(dbs);
+        self.log_len = Mutex::new(log_len);
+        if let Some(lock) = lock.as_mut() {
+            self.generation = AtomicU64::new(lock.generation()?);
+        }
         Ok(())
     }
 
+    // Reloads the environment if another process committed to it since it was last
+    // read.
+    fn refresh(&self, lock: &mut LockGuard) -> Result<(), ErrorImpl> {
+        let generation = lock.generation()?;
+        if generation == self.generation.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let mut log_len = self.log_len.lock().map_err(|_| ErrorImpl::EnvPoisonError)?;
+        let (data, snapshots, len) = self.load(false)?;
+        let mut dbs = self.dbs_mut()?;
+        self.reload(&mut dbs, data, snapshots)?;
+        *log_len = len;
+        self.generation.store(generation, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Reloads the environment before starting a read transaction, if it changed on
+    /// disk. When another process is committing meanwhile, this waits for it to be
+    /// done, rather than reading from disk while it's being written to.
+    pub(crate) fn refresh_if_changed(&self) -> Result<(), ErrorImpl> {
+        let lock = match &self.lock {
+            Some(lock) => lock,
+            None => return Ok(()),
+        };
+        if lock.generation()? == self.generation.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.read_lock().map(|_| ())
+    }
+
+    /// Takes the shared lock, and brings the environment up to date with the disk.
+    /// The files of the databases must only be read while holding it, since another
+    /// process could otherwise checkpoint them meanwhile, and the changes of the log
+    /// would then be replayed on top of newer files. The lock isn't needed when the
+    /// current thread is writing, since that already keeps other processes out.
+    fn read_lock(&self) -> Result<Option<LockGuard>, ErrorImpl> {
+        let lock = match &self.lock {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+        let mut lock = lock.shared_unless_owned()?;
+        if let Some(lock) = lock.as_mut() {
+            self.refresh(lock)?;
+        }
+        Ok(lock)
+    }
+
+    /// Prevents any other write transaction from starting, in this process or any
+    /// other one, and brings the environment up to date with the disk. Without a lock
+    /// file, only the write transactions of this process are excluded.
+    pub(crate) fn writer(&self) -> Result<(MutexGuard<'_, ()>, Option<LockGuard>), ErrorImpl> {
+        if self.is_read_only() {
+            return Err(ErrorImpl::EnvIsReadOnly);
+        }
+        let writer = self.writer.lock().map_err(|_| ErrorImpl::EnvPoisonError)?;
+        let lock = match &self.lock {
+            Some(lock) => {
+                let mut lock = lock.exclusive()?;
+                self.refresh(&mut lock)?;
+                Some(lock)
+            },
+            None => None,
+        };
+        Ok((writer, lock))
+    }
+
+    /// Writes the databases which changed since they were last written to their
+    /// files, and then the catalog of all the databases.
     pub(crate) fn write_to_disk(&self) -> Result<(), ErrorImpl> {
-        let mut path = Cow::from(&self.path);
-        if fs::metadata(&path)?.is_dir() {
-            path.to_mut().push(DEFAULT_DB_FILENAME);
+        let mut dbs = self.dbs_mut()?;
+        let mut next_file = dbs.arena.iter().filter_map(|(_, db)| db.file()).max().map_or(0, |file| file + 1);
+        let ids: Vec<DatabaseImpl> = dbs.name_map.values().copied().collect();
+        for id in ids {
+            if !dbs.arena[id.0].is_dirty() {
+                continue;
+            }
+            // Databases which weren't loaded are only read for as long as needed to
+            // fold the changes of the log into their file.
+            let snapshot = match dbs.snapshots.get(&id) {
+                Some(snapshot) => snapshot.clone(),
+                None => self.read_db(&dbs.arena[id.0])?,
+            };
+            let bytes = format::encode(&bincode::serialize(&snapshot)?)?;
+            let file = dbs.arena[id.0].file().unwrap_or_else(|| {
+                next_file += 1;
+                next_file - 1
+            });
+            write_atomically(&self.db_path(file), &bytes)?;
+            dbs.arena[id.0].checkpointed(file, snapshot.size(), snapshot.entries());
+        }
+
+        // Files are only ever added to the catalog after having been written, so that
+        // the catalog never refers to a missing file. Likewise, the files of dropped
+        // databases are only deleted once they're no longer in the catalog.
+        let previous = match fs::read(&self.data_path) {
+            Ok(bytes) => Self::deserialize(&bytes, true)?.0,
+            Err(_) => Default::default(),
         };
-        fs::write(&path, self.serialize()?)?;
+        let bytes = Self::serialize(&dbs)?;
+        write_atomically(&self.data_path, &bytes)?;
+        dbs.unlogged.clear();
+
+        let files: HashSet<u64> = dbs.name_map.values().filter_map(|id| dbs.arena[id.0].file()).collect();
+        for file in previous.values().filter_map(Database::file).filter(|file| !files.contains(file)) {
+            match fs::remove_file(self.db_path(file)) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                    warn!("Failed to delete the file of a dropped database: {}", error);
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// Locks the length of the log, which has to be held while appending to it, before
+    /// locking the databases.
+    pub(crate) fn log(&self) -> Result<MutexGuard<'_, u64>, ErrorImpl> {
+        self.log_len.lock().map_err(|_| ErrorImpl::EnvPoisonError)
+    }
+
+    /// Appends the changes made by a write transaction to the log. The changes must
+    /// only be made visible to readers once this succeeded, since until then they can
+    /// still be lost.
+    pub(crate) fn write_to_log(
+        &self,
+        log_len: &mut u64,
+        lock: Option<&mut LockGuard>,
+        record: &Record,
+    ) -> Result<(), ErrorImpl> {
+        let generation = self.next_generation(lock)?;
+        let sync = !self.flags.intersects(EnvironmentFlagsImpl::NO_SYNC | EnvironmentFlagsImpl::NO_META_SYNC);
+        *log_len = wal::append(&self.log_path, *log_len, record, sync)?;
+        self.generation.store(generation, Ordering::SeqCst);
         Ok(())
     }
 
+    /// Checkpoints the log once it has grown past the configured threshold. This
+    /// locks the databases, so they must not be locked already.
+    pub(crate) fn checkpoint_if_needed(&self, log_len: &mut u64) {
+        if *log_len as usize > self.checkpoint_threshold {
+            // The transaction is already durable at this point, so failing to checkpoint
+            // must not fail the commit. The log will simply keep growing until the next
+            // checkpoint succeeds.
+            if let Err(error) = self.checkpoint(log_len) {
+                warn!("Failed to checkpoint the log: {}", error);
+            }
+        }
+    }
+
+    // Other processes only look at the files on disk after the lock is released, so
+    // the generation can be bumped before writing to them. If writing fails, this
+    // process reloads the environment as well before its next transaction.
+    fn next_generation(&self, lock: Option<&mut LockGuard>) -> Result<u64, ErrorImpl> {
+        let generation = self.generation.load(Ordering::SeqCst) + 1;
+        if let Some(lock) = lock {
+            lock.set_generation(generation)?;
+        }
+        Ok(generation)
+    }
+
+    fn checkpoint(&self, log_len: &mut u64) -> Result<(), ErrorImpl> {
+        // If a crash happens after writing the snapshot but before truncating the log,
+        // the log is replayed on top of a snapshot that already contains it, which is
+        // harmless since replaying changes is idempotent.
+        self.write_to_disk()?;
+        wal::truncate(&self.log_path)?;
+        *log_len = 0;
+        Ok(())
+    }
+
+    // The data file, the log, and the files of the databases which were written.
+    fn data_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.data_path.clone(), self.log_path.clone()];
+        if let Ok(dbs) = self.dbs() {
+            files.extend(dbs.arena.iter().filter_map(|(_, db)| db.file()).map(|file| self.db_path(file)));
+        }
+        files
+    }
+
+    // The combined size of the data files. Missing files haven't been written yet,
+    // and take up no space.
+    fn bytes_on_disk(&self) -> usize {
+        let metadata = self.data_files().into_iter().filter_map(|path| fs::metadata(path).ok());
+        metadata.map(|metadata| metadata.len() as usize).sum()
+    }
+
+    /// Drops a database, or renames it, and logs it for other processes to do the same.
+    /// Like opening databases, this can't happen while transactions are active, since
+    /// those could still be using the database.
+    fn change_db(&self, name: &str, change: Change) -> Result<(), ErrorImpl> {
+        if Arc::strong_count(&self.ro_txns) > 1 || Arc::strong_count(&self.rw_txns) > 1 {
+            return Err(ErrorImpl::DbsIllegalOpen);
+        }
+        let (_writer, mut lock) = self.writer()?;
+        let mut log_len = self.log()?;
+        let mut dbs = self.dbs_mut()?;
+        let name = Some(name.to_owned());
+        let id = *dbs.name_map.get(&name).ok_or(ErrorImpl::DbNotFoundError)?;
+        let flags = dbs.snapshots.get(&id).map_or_else(|| dbs.arena[id.0].flags(), |snapshot| *snapshot.flags());
+        let renamed = match &change {
+            Change::Rename(new_name) => Some(Some(new_name.clone())),
+            _ => None,
+        };
+        if let Some(new_name) = &renamed {
+            if dbs.name_map.contains_key(new_name) {
+                return Err(ErrorImpl::KeyExists);
+            }
+        }
+
+        // Like with commits, the databases stay locked until the change is logged.
+        let record = vec![DatabaseChanges {
+            name: name.clone(),
+            flags,
+            changes: vec![change],
+        }];
+        self.write_to_log(&mut log_len, lock.as_mut(), &record)?;
+        dbs.name_map.remove(&name);
+        match renamed {
+            Some(new_name) => {
+                dbs.name_map.insert(new_name, id);
+            },
+            None => {
+                Arc::make_mut(&mut dbs.snapshots).remove(&id);
+                dbs.unlogged.remove(&id);
+            },
+        }
+        drop(dbs);
+        self.checkpoint_if_needed(&mut log_len);
+        Ok(())
+    }
+
+    /// Lists a new reader in the reader table, until the returned slot is dropped.
+    pub(crate) fn add_reader(&self) -> Result<Arc<ReaderSlot>, ErrorImpl> {
+        let slot = Arc::new(ReaderSlot {
+            pid: process::id(),
+            thread: thread_number(),
+            txnid: Some(self.generation.load(Ordering::SeqCst) as usize),
+        });
+        let mut readers = self.readers.lock().map_err(|_| ErrorImpl::EnvPoisonError)?;
+        readers.retain(|reader| reader.strong_count() > 0);
+        readers.push(Arc::downgrade(&slot));
+        Ok(slot)
+    }
+
+    /// The maximum size of the databases once serialized.
+    pub(crate) fn map_size(&self) -> usize {
+        self.map_size.load(Ordering::SeqCst)
+    }
+
     pub(crate) fn dbs(&self) -> Result<RwLockReadGuard<EnvironmentDbs>, ErrorImpl> {
         self.dbs.read().map_err(|_| ErrorImpl::EnvPoisonError)
     }
@@ -248,6 +836,7 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
     type Stat = StatImpl;
 
     fn get_dbs(&self) -> Result<Vec<Option<String>>, Self::Error> {
+        self.refresh_if_changed()?;
         let dbs = self.dbs.read().map_err(|_| ErrorImpl::EnvPoisonError)?;
         Ok(dbs.name_map.keys().map(|key| key.to_owned()).collect())
     }
@@ -256,32 +845,52 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
         if Arc::strong_count(&self.ro_txns) > 1 {
             return Err(ErrorImpl::DbsIllegalOpen);
         }
+        let _lock = self.read_lock()?;
         // TOOD: don't reallocate `name`.
         let key = name.map(String::from);
-        let dbs = self.dbs.read().map_err(|_| ErrorImpl::EnvPoisonError)?;
-        let db = dbs.name_map.get(&key).ok_or(ErrorImpl::DbNotFoundError)?;
-        Ok(*db)
+        let mut dbs = self.dbs.write().map_err(|_| ErrorImpl::EnvPoisonError)?;
+        let id = *dbs.name_map.get(&key).ok_or(ErrorImpl::DbNotFoundError)?;
+        self.load_db(&mut dbs, id)?;
+        Ok(id)
     }
 
     fn create_db(&self, name: Option<&str>, flags: Self::Flags) -> Result<Self::Database, Self::Error> {
         if Arc::strong_count(&self.ro_txns) > 1 {
             return Err(ErrorImpl::DbsIllegalOpen);
         }
+        let _lock = self.read_lock()?;
         // TOOD: don't reallocate `name`.
         let key = name.map(String::from);
         let mut dbs = self.dbs.write().map_err(|_| ErrorImpl::EnvPoisonError)?;
-        if dbs.name_map.keys().filter_map(|k| k.as_ref()).count() >= self.max_dbs && name != None {
+        if self.is_read_only() && !dbs.name_map.contains_key(&key) {
+            return Err(ErrorImpl::EnvIsReadOnly);
+        }
+        if let Some(&id) = dbs.name_map.get(&key) {
+            self.load_db(&mut dbs, id)?;
+            return Ok(id);
+        }
+        if dbs.name_map.keys().filter_map(|k| k.as_ref()).count() >= self.max_dbs && name.is_some() {
             return Err(ErrorImpl::DbsFull);
         }
-        let parts = EnvironmentDbsRefMut::from(dbs.deref_mut());
-        let arena = parts.arena;
-        let name_map = parts.name_map;
-        let id = name_map.entry(key).or_insert_with(|| DatabaseImpl(arena.alloc(Database::new(Some(flags), None))));
-        Ok(*id)
+        Ok(dbs.open_or_create(key, flags))
+    }
+
+    fn drop_db(&self, name: &str) -> Result<(), Self::Error> {
+        self.change_db(name, Change::Drop)
+    }
+
+    fn rename_db(&self, name: &str, new_name: &str) -> Result<(), Self::Error> {
+        self.change_db(name, Change::Rename(new_name.to_owned()))
     }
 
     fn begin_ro_txn(&'e self) -> Result<Self::RoTransaction, Self::Error> {
-        RoTransactionImpl::new(self, self.ro_txns.clone())
+        // The reader is counted before checking the limit, so that concurrent readers
+        // can't all get past it.
+        let idx = self.ro_txns.clone();
+        if Arc::strong_count(&idx) - 1 > self.max_readers {
+            return Err(ErrorImpl::ReadersFull);
+        }
+        RoTransactionImpl::new(self, idx)
     }
 
     fn begin_rw_txn(&'e self) -> Result<Self::RwTransaction, Self::Error> {
@@ -290,36 +899,188 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
 
     fn sync(&self, force: bool) -> Result<(), Self::Error> {
         warn!("Ignoring `force={}`", force);
-        self.write_to_disk()
+        let (_writer, mut lock) = self.writer()?;
+        let mut log_len = self.log_len.lock().map_err(|_| ErrorImpl::EnvPoisonError)?;
+        let generation = self.next_generation(lock.as_mut())?;
+        self.checkpoint(&mut log_len)?;
+        self.generation.store(generation, Ordering::SeqCst);
+        Ok(())
     }
 
     fn stat(&self) -> Result<Self::Stat, Self::Error> {
-        Ok(StatImpl)
+        let _lock = self.read_lock()?;
+        let dbs = self.dbs()?;
+        // Like with LMDB, the environment reports the entries of its main database, which
+        // holds the named databases along with the pairs of the unnamed one.
+        let mut entries = dbs.name_map.keys().filter(|name| name.is_some()).count();
+        if let Some(id) = dbs.name_map.get(&None) {
+            entries += self.db_stat_of(&dbs, *id)?.entries;
+        }
+        Ok(StatImpl {
+            entries,
+            bytes_on_disk: self.bytes_on_disk(),
+        })
+    }
+
+    fn db_stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error> {
+        let _lock = self.read_lock()?;
+        let dbs = self.dbs()?;
+        if !dbs.snapshots.contains_key(db) {
+            dbs.name_of(*db).ok_or(ErrorImpl::DbIsForeignError)?;
+        }
+        self.db_stat_of(&dbs, *db)
     }
 
     fn info(&self) -> Result<Self::Info, Self::Error> {
-        Ok(InfoImpl)
+        self.refresh_if_changed()?;
+        Ok(InfoImpl {
+            map_size: self.map_size(),
+            bytes_on_disk: self.bytes_on_disk(),
+            last_txnid: self.generation.load(Ordering::SeqCst) as usize,
+            max_readers: self.max_readers,
+            num_readers: Arc::strong_count(&self.ro_txns) - 1,
+        })
     }
 
     fn freelist(&self) -> Result<usize, Self::Error> {
-        unimplemented!()
+        // The data files are always rewritten from scratch, so they never contain
+        // any free pages.
+        Ok(0)
     }
 
     fn load_ratio(&self) -> Result<Option<f32>, Self::Error> {
-        warn!("`load_ratio()` is irrelevant for this storage backend.");
-        Ok(None)
+        // Without a map size, the map never gets full.
+        let map_size = self.map_size();
+        if map_size == usize::MAX {
+            return Ok(None);
+        }
+        Ok(Some(self.dbs()?.size() as f32 / map_size as f32))
     }
 
     fn set_map_size(&self, size: usize) -> Result<(), Self::Error> {
-        warn!("`set_map_size({})` is ignored by this storage backend.", size);
+        self.map_size.store(size, Ordering::SeqCst);
         Ok(())
     }
 
+    fn grow_map(&self, full: bool) -> Result<bool, Self::Error> {
+        let map_growth = match self.map_growth {
+            Some(map_growth) => map_growth,
+            None => return Ok(false),
+        };
+        if !full {
+            let load_ratio = match map_growth.load_ratio {
+                Some(load_ratio) => load_ratio,
+                None => return Ok(false),
+            };
+            if self.load_ratio()?.map_or(true, |ratio| ratio < load_ratio) {
+                return Ok(false);
+            }
+        }
+
+        // There's nothing mapped in memory, so the map can grow even while transactions
+        // are active.
+        let size = map_growth.grow(self.map_size(), 1);
+        if let Some(size) = size {
+            self.set_map_size(size)?;
+        }
+        Ok(size.is_some())
+    }
+
+    fn readers(&self) -> Result<Vec<ReaderSlot>, Self::Error> {
+        let readers = self.readers.lock().map_err(|_| ErrorImpl::EnvPoisonError)?;
+        Ok(readers.iter().filter_map(Weak::upgrade).map(|slot| *slot).collect())
+    }
+
+    fn reader_check(&self) -> Result<usize, Self::Error> {
+        // Readers are only tracked within this process, and go away with it, so the
+        // table never has slots left by dead processes.
+        Ok(0)
+    }
+
+    fn copy_to(&self, path: &Path, _compact: bool) -> Result<(), Self::Error> {
+        // Other processes can't checkpoint while the lock is shared, nor can this one while
+        // the databases are borrowed, so the files of the databases which aren't loaded
+        // match the catalog for as long as they're read. A write transaction of this
+        // process already holds the lock exclusively though, and can't be waited for.
+        let mut lock = match &self.lock {
+            Some(lock) if Arc::strong_count(&self.rw_txns) == 1 => lock.shared()?,
+            _ => None,
+        };
+        if let Some(lock) = lock.as_mut() {
+            self.refresh(lock)?;
+        }
+        let dbs = self.dbs()?;
+
+        // The copy is a new environment, whose databases are all written from scratch,
+        // without a log, which makes it compact.
+        let flags = (self.flags & EnvironmentFlagsImpl::NO_SUB_DIR) | EnvironmentFlagsImpl::NO_LOCK;
+        let copy = EnvironmentImpl::new(path, flags, None, None, None)?;
+        if copy.data_path.exists() {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+        }
+        let mut copy_dbs = copy.dbs_mut()?;
+        for (name, id) in &dbs.name_map {
+            let snapshot = match dbs.snapshots.get(id) {
+                Some(snapshot) => snapshot.clone(),
+                None => self.read_db(&dbs.arena[id.0])?,
+            };
+            let copy_id = copy_dbs.open_or_create(name.clone(), *snapshot.flags());
+            Arc::make_mut(&mut copy_dbs.snapshots).insert(copy_id, snapshot);
+        }
+        drop(copy_dbs);
+        copy.write_to_disk()
+    }
+
     fn get_files_on_disk(&self) -> Vec<PathBuf> {
-        // Technically NO_SUB_DIR and NO_LOCK should change this output, but
-        // they're both currently unimplemented with this storage backend.
-        let mut db_filename = self.path.clone();
-        db_filename.push(DEFAULT_DB_FILENAME);
-        return vec![db_filename];
+        let mut files = self.data_files();
+        if let Some(lock) = &self.lock {
+            files.push(lock.path().to_path_buf());
+        }
+        files.into_iter().filter(|path| path.exists()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::disk::{
+        crash_at,
+        WriteStep,
+    };
+    use crate::*;
+
+    use tempfile::Builder;
+
+    #[test]
+    fn test_crash_during_append() {
+        // A crash while writing the record leaves it torn, and ignored. Once it's
+        // written, it survives the process dying before it's flushed to disk.
+        for &(step, survives) in &[(WriteStep::WriteLog, false), (WriteStep::SyncLog, true)] {
+            let root = Builder::new().prefix("test_crash_during_append").tempdir().expect("tempdir");
+            {
+                let k = Rkv::new::<backend::SafeMode>(root.path()).expect("new succeeded");
+                let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+                let mut writer = k.write().expect("writer");
+                sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+                writer.commit().expect("committed");
+
+                let mut writer = k.write().expect("writer");
+                sk.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+                sk.put(&mut writer, "bar", &Value::I64(3)).expect("wrote");
+                crash_at(Some(step));
+                writer.commit().expect_err("crashed");
+                crash_at(None);
+            }
+
+            let k = Rkv::new::<backend::SafeMode>(root.path()).expect("new succeeded");
+            let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+            let reader = k.read().expect("reader");
+            let expected = if survives {
+                (Some(Value::I64(2)), Some(Value::I64(3)))
+            } else {
+                (Some(Value::I64(1)), None)
+            };
+            let found = (sk.get(&reader, "foo").expect("read"), sk.get(&reader, "bar").expect("read"));
+            assert_eq!(found, expected, "crash before {:?}", step);
+        }
     }
 }
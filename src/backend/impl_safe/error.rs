@@ -24,12 +24,20 @@ use crate::{
 #[derive(Debug)]
 pub enum ErrorImpl {
     KeyValuePairNotFound,
+    KeyExists,
+    KeyValuePairBadSize,
     EnvPoisonError,
     DbsFull,
+    MapFull,
+    ReadersFull,
     DbsIllegalOpen,
     DbNotFoundError,
     DbIsForeignError,
+    EnvIsReadOnly,
+    InvalidArgument,
     UnsuitableEnvironmentPath(PathBuf),
+    FileCorrupted,
+    UnsupportedFileFormat,
     IoError(io::Error),
     BincodeError(BincodeError),
 }
@@ -40,31 +48,44 @@ impl fmt::Display for ErrorImpl {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ErrorImpl::KeyValuePairNotFound => write!(fmt, "KeyValuePairNotFound (safe mode)"),
+            ErrorImpl::KeyExists => write!(fmt, "KeyExists (safe mode)"),
+            ErrorImpl::KeyValuePairBadSize => write!(fmt, "KeyValuePairBadSize (safe mode)"),
             ErrorImpl::EnvPoisonError => write!(fmt, "EnvPoisonError (safe mode)"),
             ErrorImpl::DbsFull => write!(fmt, "DbsFull (safe mode)"),
+            ErrorImpl::MapFull => write!(fmt, "MapFull (safe mode)"),
+            ErrorImpl::ReadersFull => write!(fmt, "ReadersFull (safe mode)"),
             ErrorImpl::DbsIllegalOpen => write!(fmt, "DbIllegalOpen (safe mode)"),
             ErrorImpl::DbNotFoundError => write!(fmt, "DbNotFoundError (safe mode)"),
             ErrorImpl::DbIsForeignError => write!(fmt, "DbIsForeignError (safe mode)"),
+            ErrorImpl::EnvIsReadOnly => write!(fmt, "EnvIsReadOnly (safe mode)"),
+            ErrorImpl::InvalidArgument => write!(fmt, "InvalidArgument (safe mode)"),
             ErrorImpl::UnsuitableEnvironmentPath(_) => write!(fmt, "UnsuitableEnvironmentPath (safe mode)"),
+            ErrorImpl::FileCorrupted => write!(fmt, "FileCorrupted (safe mode)"),
+            ErrorImpl::UnsupportedFileFormat => write!(fmt, "UnsupportedFileFormat (safe mode)"),
             ErrorImpl::IoError(e) => e.fmt(fmt),
             ErrorImpl::BincodeError(e) => e.fmt(fmt),
         }
     }
 }
 
-impl Into<StoreError> for ErrorImpl {
-    fn into(self) -> StoreError {
-        // The `StoreError::KeyValuePairBadSize` error is unused, because this
-        // backend supports keys and values of arbitrary sizes.
-        // The `StoreError::MapFull` and `StoreError::ReadersFull` are
-        // unimplemented yet, but they should be in the future.
-        match self {
+impl From<ErrorImpl> for StoreError {
+    fn from(e: ErrorImpl) -> StoreError {
+        // The `StoreError::KeyValuePairBadSize` error is only used for values that
+        // don't match the size of the other values of their key in `DUP_FIXED`
+        // databases, because this backend supports keys and values of arbitrary sizes.
+        match e {
             ErrorImpl::KeyValuePairNotFound => StoreError::KeyValuePairNotFound,
+            ErrorImpl::KeyExists => StoreError::KeyExists,
+            ErrorImpl::KeyValuePairBadSize => StoreError::KeyValuePairBadSize,
             ErrorImpl::BincodeError(_) => StoreError::FileInvalid,
+            ErrorImpl::FileCorrupted => StoreError::DatabaseCorrupted,
+            ErrorImpl::UnsupportedFileFormat => StoreError::FileInvalid,
             ErrorImpl::DbsFull => StoreError::DbsFull,
+            ErrorImpl::MapFull => StoreError::MapFull,
+            ErrorImpl::ReadersFull => StoreError::ReadersFull,
             ErrorImpl::UnsuitableEnvironmentPath(path) => StoreError::UnsuitableEnvironmentPath(path),
             ErrorImpl::IoError(error) => StoreError::IoError(error),
-            _ => StoreError::SafeModeError(self),
+            _ => StoreError::SafeModeError(e),
         }
     }
 }
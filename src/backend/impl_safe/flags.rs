@@ -9,6 +9,7 @@
 // specific language governing permissions and limitations under the License.
 
 use bitflags::bitflags;
+use log::warn;
 use serde_derive::{
     Deserialize,
     Serialize,
@@ -32,6 +33,11 @@ bitflags! {
     #[derive(Default, Serialize, Deserialize)]
     pub struct EnvironmentFlagsImpl: u32 {
         const NIL = 0b0000_0000;
+        const NO_SUB_DIR = 0b0000_0001;
+        const READ_ONLY = 0b0000_0010;
+        const NO_META_SYNC = 0b0000_0100;
+        const NO_SYNC = 0b0000_1000;
+        const NO_LOCK = 0b0001_0000;
     }
 }
 
@@ -47,24 +53,33 @@ impl BackendEnvironmentFlags for EnvironmentFlagsImpl {
     }
 }
 
-impl Into<EnvironmentFlagsImpl> for EnvironmentFlags {
-    fn into(self) -> EnvironmentFlagsImpl {
-        match self {
-            EnvironmentFlags::FIXED_MAP => unimplemented!(),
-            EnvironmentFlags::NO_SUB_DIR => unimplemented!(),
-            EnvironmentFlags::WRITE_MAP => unimplemented!(),
-            EnvironmentFlags::READ_ONLY => unimplemented!(),
-            EnvironmentFlags::NO_META_SYNC => unimplemented!(),
-            EnvironmentFlags::NO_SYNC => unimplemented!(),
-            EnvironmentFlags::MAP_ASYNC => unimplemented!(),
-            EnvironmentFlags::NO_TLS => unimplemented!(),
-            EnvironmentFlags::NO_LOCK => unimplemented!(),
-            EnvironmentFlags::NO_READAHEAD => unimplemented!(),
-            EnvironmentFlags::NO_MEM_INIT => unimplemented!(),
+/// The flags controlling how LMDB maps its file into memory (`FIXED_MAP`, `WRITE_MAP`,
+/// `MAP_ASYNC`, `NO_READAHEAD` and `NO_MEM_INIT`) or ties read transactions to threads
+/// (`NO_TLS`) are irrelevant for this storage backend, which reads the whole environment
+/// into memory and has no reader table. Those are ignored, with a warning.
+impl From<EnvironmentFlags> for EnvironmentFlagsImpl {
+    fn from(flag: EnvironmentFlags) -> EnvironmentFlagsImpl {
+        match flag {
+            EnvironmentFlags::FIXED_MAP => ignored("FIXED_MAP"),
+            EnvironmentFlags::NO_SUB_DIR => EnvironmentFlagsImpl::NO_SUB_DIR,
+            EnvironmentFlags::WRITE_MAP => ignored("WRITE_MAP"),
+            EnvironmentFlags::READ_ONLY => EnvironmentFlagsImpl::READ_ONLY,
+            EnvironmentFlags::NO_META_SYNC => EnvironmentFlagsImpl::NO_META_SYNC,
+            EnvironmentFlags::NO_SYNC => EnvironmentFlagsImpl::NO_SYNC,
+            EnvironmentFlags::MAP_ASYNC => ignored("MAP_ASYNC"),
+            EnvironmentFlags::NO_TLS => ignored("NO_TLS"),
+            EnvironmentFlags::NO_LOCK => EnvironmentFlagsImpl::NO_LOCK,
+            EnvironmentFlags::NO_READAHEAD => ignored("NO_READAHEAD"),
+            EnvironmentFlags::NO_MEM_INIT => ignored("NO_MEM_INIT"),
         }
     }
 }
 
+fn ignored(flag: &str) -> EnvironmentFlagsImpl {
+    warn!("Ignoring `{}`, which is irrelevant for this storage backend.", flag);
+    EnvironmentFlagsImpl::NIL
+}
+
 bitflags! {
     #[derive(Default, Serialize, Deserialize)]
     pub struct DatabaseFlagsImpl: u32 {
@@ -73,6 +88,11 @@ bitflags! {
         const DUP_SORT = 0b0000_0001;
         #[cfg(feature = "db-int-key")]
         const INTEGER_KEY = 0b0000_0010;
+        const REVERSE_KEY = 0b0000_0100;
+        #[cfg(feature = "db-dup-sort")]
+        const DUP_FIXED = 0b0000_1000;
+        const INTEGER_DUP = 0b0001_0000;
+        const REVERSE_DUP = 0b0010_0000;
     }
 }
 
@@ -88,18 +108,18 @@ impl BackendDatabaseFlags for DatabaseFlagsImpl {
     }
 }
 
-impl Into<DatabaseFlagsImpl> for DatabaseFlags {
-    fn into(self) -> DatabaseFlagsImpl {
-        match self {
-            DatabaseFlags::REVERSE_KEY => unimplemented!(),
+impl From<DatabaseFlags> for DatabaseFlagsImpl {
+    fn from(flag: DatabaseFlags) -> DatabaseFlagsImpl {
+        match flag {
+            DatabaseFlags::REVERSE_KEY => DatabaseFlagsImpl::REVERSE_KEY,
             #[cfg(feature = "db-dup-sort")]
             DatabaseFlags::DUP_SORT => DatabaseFlagsImpl::DUP_SORT,
             #[cfg(feature = "db-dup-sort")]
-            DatabaseFlags::DUP_FIXED => unimplemented!(),
+            DatabaseFlags::DUP_FIXED => DatabaseFlagsImpl::DUP_FIXED,
             #[cfg(feature = "db-int-key")]
             DatabaseFlags::INTEGER_KEY => DatabaseFlagsImpl::INTEGER_KEY,
-            DatabaseFlags::INTEGER_DUP => unimplemented!(),
-            DatabaseFlags::REVERSE_DUP => unimplemented!(),
+            DatabaseFlags::INTEGER_DUP => DatabaseFlagsImpl::INTEGER_DUP,
+            DatabaseFlags::REVERSE_DUP => DatabaseFlagsImpl::REVERSE_DUP,
         }
     }
 }
@@ -108,6 +128,11 @@ bitflags! {
     #[derive(Default, Serialize, Deserialize)]
     pub struct WriteFlagsImpl: u32 {
         const NIL = 0b0000_0000;
+        const NO_OVERWRITE = 0b0000_0001;
+        const NO_DUP_DATA = 0b0000_0010;
+        const APPEND = 0b0000_0100;
+        const APPEND_DUP = 0b0000_1000;
+        const CURRENT = 0b0001_0000;
     }
 }
 
@@ -123,14 +148,14 @@ impl BackendWriteFlags for WriteFlagsImpl {
     }
 }
 
-impl Into<WriteFlagsImpl> for WriteFlags {
-    fn into(self) -> WriteFlagsImpl {
-        match self {
-            WriteFlags::NO_OVERWRITE => unimplemented!(),
-            WriteFlags::NO_DUP_DATA => unimplemented!(),
-            WriteFlags::CURRENT => unimplemented!(),
-            WriteFlags::APPEND => unimplemented!(),
-            WriteFlags::APPEND_DUP => unimplemented!(),
+impl From<WriteFlags> for WriteFlagsImpl {
+    fn from(flag: WriteFlags) -> WriteFlagsImpl {
+        match flag {
+            WriteFlags::NO_OVERWRITE => WriteFlagsImpl::NO_OVERWRITE,
+            WriteFlags::NO_DUP_DATA => WriteFlagsImpl::NO_DUP_DATA,
+            WriteFlags::CURRENT => WriteFlagsImpl::CURRENT,
+            WriteFlags::APPEND => WriteFlagsImpl::APPEND,
+            WriteFlags::APPEND_DUP => WriteFlagsImpl::APPEND_DUP,
         }
     }
 }
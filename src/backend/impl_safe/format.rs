@@ -0,0 +1,121 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The framing of the snapshot file. The serialized databases are preceded by a
+//! fixed size header, whose integers are all little-endian:
+//!
+//! | magic (8 bytes) | version (u32) | flags (u32) | payload length (u64) | payload crc32 (u32) |
+//!
+//! Files written before the header was introduced consist of the bare payload.
+//! Those can still be read, since a payload can't start with the magic number
+//! (it would decode as the length of an implausibly large map).
+//!
+//! Up to version 1, the data file contained every database of the environment.
+//! Since version 2, it only contains the catalog of the databases, which are each
+//! stored in a file of their own, framed by the same header.
+
+use byteorder::{
+    ByteOrder,
+    LittleEndian,
+    WriteBytesExt,
+};
+
+use super::ErrorImpl;
+
+const MAGIC: &[u8; 8] = b"RKVSAFE\0";
+pub(crate) const VERSION: u32 = 2;
+
+/// The version of the files without a header.
+pub(crate) const LEGACY_VERSION: u32 = 0;
+
+/// The last version of the data file to contain every database of the environment.
+pub(crate) const VERSION_1: u32 = 1;
+
+// No flags are defined yet. Readers refuse files with flags they don't know
+// about, since those may change how the payload needs to be interpreted.
+const KNOWN_FLAGS: u32 = 0;
+
+const HEADER_SIZE: usize = 28;
+
+/// Prepends a header to a serialized payload.
+pub(crate) fn encode(payload: &[u8]) -> Result<Vec<u8>, ErrorImpl> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.write_u32::<LittleEndian>(VERSION)?;
+    bytes.write_u32::<LittleEndian>(KNOWN_FLAGS)?;
+    bytes.write_u64::<LittleEndian>(payload.len() as u64)?;
+    bytes.write_u32::<LittleEndian>(crc32fast::hash(payload))?;
+    bytes.extend_from_slice(payload);
+    Ok(bytes)
+}
+
+/// Validates the header of a file and returns its version and payload. Files without
+/// a header are assumed to use the legacy layout, and are returned as they are.
+pub(crate) fn decode(bytes: &[u8]) -> Result<(u32, &[u8]), ErrorImpl> {
+    if !bytes.starts_with(MAGIC) {
+        return Ok((LEGACY_VERSION, bytes));
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(ErrorImpl::FileCorrupted);
+    }
+
+    let version = LittleEndian::read_u32(&bytes[8..12]);
+    let flags = LittleEndian::read_u32(&bytes[12..16]);
+    if version == LEGACY_VERSION || version > VERSION || flags & !KNOWN_FLAGS != 0 {
+        return Err(ErrorImpl::UnsupportedFileFormat);
+    }
+
+    let len = LittleEndian::read_u64(&bytes[16..24]);
+    let checksum = LittleEndian::read_u32(&bytes[24..28]);
+    let payload = &bytes[HEADER_SIZE..];
+    if payload.len() as u64 != len || crc32fast::hash(payload) != checksum {
+        return Err(ErrorImpl::FileCorrupted);
+    }
+    Ok((version, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let bytes = encode(b"hello").expect("encoded");
+        assert_eq!(bytes.len(), HEADER_SIZE + 5);
+        assert_eq!(decode(&bytes).expect("decoded"), (VERSION, &b"hello"[..]));
+
+        // Legacy files are passed through untouched.
+        assert_eq!(decode(b"hello").expect("decoded"), (LEGACY_VERSION, &b"hello"[..]));
+        assert_eq!(decode(b"").expect("decoded"), (LEGACY_VERSION, &b""[..]));
+    }
+
+    #[test]
+    fn test_decode_corrupted() {
+        let bytes = encode(b"hello").expect("encoded");
+
+        let mut flipped = bytes.clone();
+        flipped[HEADER_SIZE] ^= 1;
+        assert!(matches!(decode(&flipped), Err(ErrorImpl::FileCorrupted)));
+
+        assert!(matches!(decode(&bytes[..bytes.len() - 1]), Err(ErrorImpl::FileCorrupted)));
+        assert!(matches!(decode(&bytes[..HEADER_SIZE - 1]), Err(ErrorImpl::FileCorrupted)));
+    }
+
+    #[test]
+    fn test_decode_unsupported() {
+        let mut bytes = encode(b"hello").expect("encoded");
+        LittleEndian::write_u32(&mut bytes[8..12], VERSION + 1);
+        assert!(matches!(decode(&bytes), Err(ErrorImpl::UnsupportedFileFormat)));
+
+        let mut bytes = encode(b"hello").expect("encoded");
+        LittleEndian::write_u32(&mut bytes[12..16], 1);
+        assert!(matches!(decode(&bytes), Err(ErrorImpl::UnsupportedFileFormat)));
+    }
+}
@@ -8,28 +8,49 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+use super::stat::PAGE_SIZE;
 use crate::backend::traits::BackendInfo;
 
-pub struct InfoImpl;
+/// Information about an environment. Limits which weren't configured are reported
+/// as `usize::MAX`, since the environment isn't bounded by them.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct InfoImpl {
+    pub(crate) map_size: usize,
+    pub(crate) bytes_on_disk: usize,
+    pub(crate) last_txnid: usize,
+    pub(crate) max_readers: usize,
+    pub(crate) num_readers: usize,
+}
+
+impl InfoImpl {
+    /// The combined size of the data file, the log, and the files of the databases.
+    pub fn bytes_on_disk(&self) -> usize {
+        self.bytes_on_disk
+    }
+}
 
 impl BackendInfo for InfoImpl {
     fn map_size(&self) -> usize {
-        unimplemented!()
+        self.map_size
     }
 
+    /// The number of the last nominal page used by the data on disk.
     fn last_pgno(&self) -> usize {
-        unimplemented!()
+        ((self.bytes_on_disk + PAGE_SIZE - 1) / PAGE_SIZE).saturating_sub(1)
     }
 
+    /// The generation of the environment, which every commit increments.
     fn last_txnid(&self) -> usize {
-        unimplemented!()
+        self.last_txnid
     }
 
     fn max_readers(&self) -> usize {
-        unimplemented!()
+        self.max_readers
     }
 
+    /// The number of read transactions open in this process. Readers of other
+    /// processes don't register themselves anywhere, and aren't counted.
     fn num_readers(&self) -> usize {
-        unimplemented!()
+        self.num_readers
     }
 }
@@ -0,0 +1,240 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Coordination between processes sharing an environment. Writers hold an exclusive
+//! advisory lock on the lock file for the whole duration of a write transaction, and
+//! readers hold a shared lock while reading the data files from disk.
+//!
+//! The lock file also stores a generation counter, which is incremented by every
+//! commit. A process whose in-memory state was read at an older generation must
+//! reload the environment from disk before using it.
+
+use std::{
+    fs::{
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Read,
+        Seek,
+        SeekFrom,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+    thread::{
+        self,
+        ThreadId,
+    },
+};
+
+use byteorder::{
+    ByteOrder,
+    LittleEndian,
+    WriteBytesExt,
+};
+use fs2::FileExt;
+
+use super::ErrorImpl;
+
+// The thread of this process which holds the exclusive lock, if any.
+type Owner = Arc<Mutex<Option<ThreadId>>>;
+
+#[derive(Debug)]
+pub(crate) struct LockFile {
+    path: PathBuf,
+    read_only: bool,
+    owner: Owner,
+    // The handle that the generation is read from, which isn't used for locking.
+    reader: Mutex<Option<File>>,
+}
+
+/// A lock on the lock file, released when dropped.
+#[derive(Debug)]
+pub(crate) struct LockGuard {
+    file: File,
+    owner: Option<Owner>,
+}
+
+impl LockFile {
+    pub(crate) fn new(path: &Path) -> Result<LockFile, ErrorImpl> {
+        let mut lock = LockFile {
+            path: path.to_path_buf(),
+            read_only: false,
+            owner: Owner::default(),
+            reader: Mutex::default(),
+        };
+        lock.reader = Mutex::new(lock.open()?);
+        Ok(lock)
+    }
+
+    /// Opens the lock file without ever writing to it, for read-only environments.
+    /// Until the file exists, no writer ever used the environment, and there is nothing
+    /// to lock, so it's only opened once a writer created it.
+    pub(crate) fn open_read_only(path: &Path) -> LockFile {
+        LockFile {
+            path: path.to_path_buf(),
+            read_only: true,
+            owner: Owner::default(),
+            reader: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Locks are owned by open file descriptions, so every lock needs its own file
+    // handle, in order for threads of the same process to exclude each other too.
+    // The `FileExt` methods are called explicitly, since newer versions of the
+    // standard library have inherent methods with the same names.
+    // Returns `None` when a read-only lock file doesn't exist yet.
+    fn open(&self) -> io::Result<Option<File>> {
+        let writable = !self.read_only;
+        match OpenOptions::new().read(true).write(writable).create(writable).truncate(false).open(&self.path) {
+            Ok(file) => Ok(Some(file)),
+            Err(ref error) if self.read_only && error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Waits for all the other readers and writers to release their locks.
+    pub(crate) fn exclusive(&self) -> Result<LockGuard, ErrorImpl> {
+        let file = self.open()?.ok_or(ErrorImpl::EnvIsReadOnly)?;
+        FileExt::lock_exclusive(&file)?;
+        *self.owner.lock().map_err(|_| ErrorImpl::EnvPoisonError)? = Some(thread::current().id());
+        Ok(LockGuard {
+            file,
+            owner: Some(self.owner.clone()),
+        })
+    }
+
+    /// Waits for the current writer, if any, to release its lock. Returns `None` when
+    /// a read-only lock file doesn't exist yet.
+    pub(crate) fn shared(&self) -> Result<Option<LockGuard>, ErrorImpl> {
+        let file = match self.open()? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        FileExt::lock_shared(&file)?;
+        Ok(Some(LockGuard {
+            file,
+            owner: None,
+        }))
+    }
+
+    /// Like `shared`, but also returns `None` when the current thread holds the
+    /// exclusive lock, which already keeps other processes from writing, and which it
+    /// would otherwise wait for forever.
+    pub(crate) fn shared_unless_owned(&self) -> Result<Option<LockGuard>, ErrorImpl> {
+        if *self.owner.lock().map_err(|_| ErrorImpl::EnvPoisonError)? == Some(thread::current().id()) {
+            return Ok(None);
+        }
+        self.shared()
+    }
+
+    /// Reads the current generation without locking. The result is only a hint of
+    /// whether the environment changed, and must be confirmed while holding a lock.
+    pub(crate) fn generation(&self) -> Result<u64, ErrorImpl> {
+        let mut reader = self.reader.lock().map_err(|_| ErrorImpl::EnvPoisonError)?;
+        if reader.is_none() {
+            *reader = self.open()?;
+        }
+        match reader.as_mut() {
+            Some(file) => Ok(read_generation(file)?),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if let Some(owner) = &self.owner {
+            if let Ok(mut owner) = owner.lock() {
+                *owner = None;
+            }
+        }
+    }
+}
+
+impl LockGuard {
+    pub(crate) fn generation(&mut self) -> Result<u64, ErrorImpl> {
+        Ok(read_generation(&mut self.file)?)
+    }
+
+    pub(crate) fn set_generation(&mut self, generation: u64) -> Result<(), ErrorImpl> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_u64::<LittleEndian>(generation)?;
+        Ok(())
+    }
+}
+
+fn read_generation(file: &mut File) -> io::Result<u64> {
+    // A freshly created lock file is empty, which stands for the initial generation.
+    let mut bytes = [0; 8];
+    file.seek(SeekFrom::Start(0))?;
+    match file.read_exact(&mut bytes) {
+        Ok(()) => Ok(LittleEndian::read_u64(&bytes)),
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::Builder;
+
+    #[test]
+    fn test_lock_file() {
+        let root = Builder::new().prefix("test_lock_file").tempdir().expect("tempdir");
+        let lock = LockFile::new(&root.path().join("lock")).expect("created");
+        assert_eq!(lock.generation().expect("generation"), 0);
+
+        let mut exclusive = lock.exclusive().expect("locked");
+        assert!(lock.shared_unless_owned().expect("locked").is_none());
+        exclusive.set_generation(42).expect("set");
+        assert_eq!(lock.generation().expect("generation"), 42);
+        drop(exclusive);
+
+        let mut shared = lock.shared_unless_owned().expect("locked").expect("shared");
+        assert!(lock.shared_unless_owned().expect("locked").is_some());
+        assert_eq!(shared.generation().expect("generation"), 42);
+    }
+
+    #[test]
+    fn test_read_only_lock_file() {
+        let root = Builder::new().prefix("test_read_only_lock_file").tempdir().expect("tempdir");
+        let path = root.path().join("lock");
+
+        // Nothing is locked until a writer creates the file.
+        let read_only = LockFile::open_read_only(&path);
+        assert_eq!(read_only.generation().expect("generation"), 0);
+        assert!(read_only.shared().expect("locked").is_none());
+        assert!(!path.exists());
+
+        let lock = LockFile::new(&path).expect("created");
+        lock.exclusive().expect("locked").set_generation(42).expect("set");
+        assert_eq!(read_only.generation().expect("generation"), 42);
+        let mut shared = read_only.shared().expect("locked").expect("shared");
+        assert_eq!(shared.generation().expect("generation"), 42);
+        drop(shared);
+
+        lock.exclusive().expect("locked").set_generation(43).expect("set");
+        assert_eq!(read_only.generation().expect("generation"), 43);
+    }
+}
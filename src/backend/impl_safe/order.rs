@@ -0,0 +1,152 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::cmp::Ordering;
+
+use serde::{
+    Serialize,
+    Serializer,
+};
+
+use super::DatabaseFlagsImpl;
+
+/// How the keys of a database, or the values of a key, are sorted. These mirror
+/// the comparison functions that LMDB picks for the same database flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Order {
+    /// Byte by byte, with shorter strings sorting first on a common prefix.
+    Lexical,
+    /// Byte by byte starting from the end, like with `REVERSE_KEY` and `REVERSE_DUP`.
+    Reverse,
+    /// As native endian unsigned integers, like with `INTEGER_KEY` and `INTEGER_DUP`.
+    /// LMDB expects all of them to have the same size; here, the smaller integers
+    /// sort first, so that the order stays total.
+    Integer,
+}
+
+impl Order {
+    pub(crate) fn of_keys(flags: DatabaseFlagsImpl) -> Order {
+        if flags.contains(DatabaseFlagsImpl::REVERSE_KEY) {
+            return Order::Reverse;
+        }
+        #[cfg(feature = "db-int-key")]
+        {
+            if flags.contains(DatabaseFlagsImpl::INTEGER_KEY) {
+                return Order::Integer;
+            }
+        }
+        Order::Lexical
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    pub(crate) fn of_values(flags: DatabaseFlagsImpl) -> Order {
+        if flags.contains(DatabaseFlagsImpl::INTEGER_DUP) {
+            Order::Integer
+        } else if flags.contains(DatabaseFlagsImpl::REVERSE_DUP) {
+            Order::Reverse
+        } else {
+            Order::Lexical
+        }
+    }
+
+    pub(crate) fn compare(self, a: &[u8], b: &[u8]) -> Ordering {
+        match self {
+            Order::Lexical => a.cmp(b),
+            Order::Reverse => a.iter().rev().cmp(b.iter().rev()),
+            #[cfg(target_endian = "little")]
+            Order::Integer => a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev())),
+            #[cfg(target_endian = "big")]
+            Order::Integer => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+        }
+    }
+}
+
+/// A key or a value, sorted according to the order of the database it belongs to.
+/// It is serialized as its bytes alone, the order being implied by the flags.
+#[derive(Debug, Clone)]
+pub(crate) struct Bytes {
+    order: Order,
+    bytes: Box<[u8]>,
+}
+
+impl Bytes {
+    pub(crate) fn new(order: Order, bytes: &[u8]) -> Bytes {
+        Bytes::from_boxed(order, Box::from(bytes))
+    }
+
+    pub(crate) fn from_boxed(order: Order, bytes: Box<[u8]>) -> Bytes {
+        Bytes {
+            order,
+            bytes,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Bytes) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Bytes {}
+
+impl PartialOrd for Bytes {
+    fn partial_cmp(&self, other: &Bytes) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Bytes {
+    fn cmp(&self, other: &Bytes) -> Ordering {
+        self.order.compare(&self.bytes, &other.bytes)
+    }
+}
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.bytes.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(order: Order, items: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut items: Vec<_> = items.iter().map(|item| Bytes::new(order, item)).collect();
+        items.sort();
+        items.iter().map(|item| item.as_ref().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_orders() {
+        let items: &[&[u8]] = &[b"ba", b"ab", b"b", b"a", b"aab"];
+        assert_eq!(sorted(Order::Lexical, items), vec![&b"a"[..], b"aab", b"ab", b"b", b"ba"]);
+        assert_eq!(sorted(Order::Reverse, items), vec![&b"a"[..], b"ba", b"b", b"ab", b"aab"]);
+
+        let items = [256u32, 1, 65536, 2].iter().map(|i| i.to_ne_bytes()).collect::<Vec<_>>();
+        let items: Vec<&[u8]> = items.iter().map(|item| &item[..]).collect();
+        let expected = [1u32, 2, 256, 65536].iter().map(|i| i.to_ne_bytes().to_vec()).collect::<Vec<_>>();
+        assert_eq!(sorted(Order::Integer, &items), expected);
+
+        let items: &[&[u8]] = &[&2u64.to_ne_bytes(), &3u32.to_ne_bytes()];
+        assert_eq!(sorted(Order::Integer, items), vec![3u32.to_ne_bytes().to_vec(), 2u64.to_ne_bytes().to_vec()]);
+    }
+}
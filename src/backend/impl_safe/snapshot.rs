@@ -9,132 +9,460 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
-    collections::{
-        BTreeMap,
-        BTreeSet,
-    },
+    cmp::Ordering,
+    collections::BTreeMap,
+    ops::Bound,
     sync::Arc,
 };
 
+#[cfg(feature = "db-dup-sort")]
+use std::collections::BTreeSet;
+
 use serde_derive::{
     Deserialize,
     Serialize,
 };
 
-use super::DatabaseFlagsImpl;
+use super::{
+    order::{
+        Bytes,
+        Order,
+    },
+    DatabaseFlagsImpl,
+};
 
-type Key = Box<[u8]>;
+type Key = Bytes;
+#[cfg(not(feature = "db-dup-sort"))]
 type Value = Box<[u8]>;
+#[cfg(feature = "db-dup-sort")]
+type Value = Bytes;
+
+// Keys, values and collections are serialized with a length prefix.
+const LEN_SIZE: usize = std::mem::size_of::<u64>();
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SnapshotData")]
 pub struct Snapshot {
     flags: DatabaseFlagsImpl,
     #[cfg(not(feature = "db-dup-sort"))]
     map: Arc<BTreeMap<Key, Value>>,
     #[cfg(feature = "db-dup-sort")]
     map: Arc<BTreeMap<Key, BTreeSet<Value>>>,
+    // The size of the snapshot once serialized, kept up to date by every change.
+    #[serde(skip)]
+    size: usize,
+}
+
+// The serialized fields of a snapshot, out of which the size is computed once, and
+// the keys and values are then sorted in the order implied by the flags.
+#[derive(Serialize, Deserialize)]
+struct SnapshotData {
+    flags: DatabaseFlagsImpl,
+    #[cfg(not(feature = "db-dup-sort"))]
+    map: BTreeMap<Box<[u8]>, Box<[u8]>>,
+    #[cfg(feature = "db-dup-sort")]
+    map: BTreeMap<Box<[u8]>, BTreeSet<Box<[u8]>>>,
+}
+
+impl From<SnapshotData> for Snapshot {
+    fn from(data: SnapshotData) -> Snapshot {
+        let size = bincode::serialized_size(&data).map_or(0, |size| size as usize);
+        let keys = Order::of_keys(data.flags);
+        #[cfg(not(feature = "db-dup-sort"))]
+        let map = data.map.into_iter().map(|(key, value)| (Bytes::from_boxed(keys, key), value)).collect();
+        #[cfg(feature = "db-dup-sort")]
+        let map = {
+            let values = Order::of_values(data.flags);
+            let sort = |set: BTreeSet<Box<[u8]>>| set.into_iter().map(|v| Bytes::from_boxed(values, v)).collect();
+            data.map.into_iter().map(|(key, set)| (Bytes::from_boxed(keys, key), sort(set))).collect()
+        };
+        Snapshot {
+            flags: data.flags,
+            map: Arc::new(map),
+            size,
+        }
+    }
 }
 
 impl Snapshot {
     pub(crate) fn new(flags: Option<DatabaseFlagsImpl>) -> Snapshot {
-        Snapshot {
+        Snapshot::from(SnapshotData {
             flags: flags.unwrap_or_else(DatabaseFlagsImpl::default),
             map: Default::default(),
-        }
+        })
     }
 
     pub(crate) fn flags(&self) -> &DatabaseFlagsImpl {
         &self.flags
     }
 
+    /// The number of bytes the snapshot takes up once serialized.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
     pub(crate) fn clear(&mut self) {
-        self.map = Default::default();
+        *self = Snapshot::new(Some(self.flags));
+    }
+
+    /// Compares two keys the way they are sorted in this database.
+    pub(crate) fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        Order::of_keys(self.flags).compare(a, b)
+    }
+
+    pub(crate) fn sorts_keys_bytewise(&self) -> bool {
+        matches!(Order::of_keys(self.flags), Order::Lexical)
+    }
+
+    fn key(&self, key: &[u8]) -> Key {
+        Bytes::new(Order::of_keys(self.flags), key)
+    }
+
+    fn key_bound(&self, bound: Bound<&[u8]>) -> Bound<Key> {
+        match bound {
+            Bound::Included(key) => Bound::Included(self.key(key)),
+            Bound::Excluded(key) => Bound::Excluded(self.key(key)),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+
+    fn keys_within(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Key> {
+        let (start, end) = (self.key_bound(start), self.key_bound(end));
+        // `BTreeMap::range` panics on ranges that end before they start.
+        let is_empty = match (&start, &end) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end))
+            | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+            _ => false,
+        };
+        if is_empty {
+            return vec![];
+        }
+        self.map.range((start, end)).map(|(key, _)| key.clone()).collect()
     }
 }
 
 #[cfg(not(feature = "db-dup-sort"))]
 impl Snapshot {
     pub(crate) fn get(&self, key: &[u8]) -> Option<&[u8]> {
-        self.map.get(key).map(|value| value.as_ref())
+        self.map.get(&self.key(key)).map(|value| value.as_ref())
     }
 
     pub(crate) fn put(&mut self, key: &[u8], value: &[u8]) {
+        let key = self.key(key);
+        let key_len = key.len();
         let map = Arc::make_mut(&mut self.map);
-        map.insert(Box::from(key), Box::from(value));
+        match map.insert(key, Box::from(value)) {
+            Some(previous) => self.size = self.size - previous.len() + value.len(),
+            None => self.size += LEN_SIZE + key_len + LEN_SIZE + value.len(),
+        }
     }
 
     pub(crate) fn del(&mut self, key: &[u8]) -> Option<()> {
+        let key = self.key(key);
         let map = Arc::make_mut(&mut self.map);
-        map.remove(key).map(|_| ())
+        let previous = map.remove(&key)?;
+        self.size -= LEN_SIZE + key.len() + LEN_SIZE + previous.len();
+        Some(())
+    }
+
+    /// Deletes the key/value pairs whose keys are within the range, and returns how
+    /// many there were.
+    pub(crate) fn del_range(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        let keys = self.keys_within(start, end);
+        for key in &keys {
+            self.del(key.as_ref());
+        }
+        keys.len()
     }
 
     pub(crate) fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
         self.map.iter().map(|(key, value)| (key.as_ref(), value.as_ref()))
     }
+
+    pub(crate) fn iter_from(&self, key: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let range = (Bound::Included(self.key(key)), Bound::Unbounded);
+        self.map.range(range).map(|(key, value)| (key.as_ref(), value.as_ref()))
+    }
+
+    pub(crate) fn iter_dup_of(&self, key: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let key = self.key(key);
+        let found = self.map.get_key_value(&key);
+        found.map(|(key, value)| (key.as_ref(), value.as_ref())).into_iter()
+    }
+
+    pub(crate) fn last_key(&self) -> Option<&[u8]> {
+        self.map.keys().next_back().map(|key| key.as_ref())
+    }
+
+    /// The first key/value pair that comes after the given one, or the very first
+    /// key/value pair when none is given.
+    pub(crate) fn pair_after(&self, pair: Option<(&[u8], &[u8])>) -> Option<(&[u8], &[u8])> {
+        let lower = pair.map_or(Bound::Unbounded, |(key, _)| Bound::Excluded(self.key(key)));
+        self.map.range((lower, Bound::Unbounded)).next().map(|(key, value)| (key.as_ref(), value.as_ref()))
+    }
+
+    /// The last key/value pair that comes before the given one, or the very last
+    /// key/value pair when none is given.
+    pub(crate) fn pair_before(&self, pair: Option<(&[u8], &[u8])>) -> Option<(&[u8], &[u8])> {
+        let upper = pair.map_or(Bound::Unbounded, |(key, _)| Bound::Excluded(self.key(key)));
+        self.map.range((Bound::Unbounded, upper)).next_back().map(|(key, value)| (key.as_ref(), value.as_ref()))
+    }
+
+    /// The first key/value pair whose key is equal to or greater than the given one.
+    pub(crate) fn seek(&self, key: &[u8]) -> Option<(&[u8], &[u8])> {
+        self.iter_from(key).next()
+    }
+
+    pub(crate) fn entries(&self) -> usize {
+        self.map.len()
+    }
 }
 
 #[cfg(feature = "db-dup-sort")]
 impl Snapshot {
     pub(crate) fn get(&self, key: &[u8]) -> Option<&[u8]> {
-        self.map.get(key).and_then(|v| v.iter().next()).map(|v| v.as_ref())
+        self.map.get(&self.key(key)).and_then(|v| v.iter().next()).map(|v| v.as_ref())
     }
 
     pub(crate) fn put(&mut self, key: &[u8], value: &[u8]) {
+        let (key, value) = (self.key(key), self.value(value));
+        let value_len = value.len();
         let map = Arc::make_mut(&mut self.map);
-        match map.get_mut(key) {
+        match map.get_mut(&key) {
             None => {
+                self.size += LEN_SIZE + key.len() + LEN_SIZE;
                 let mut values = BTreeSet::new();
-                values.insert(Box::from(value));
-                map.insert(Box::from(key), values);
+                values.insert(value);
+                map.insert(key, values);
             },
             Some(values) => {
+                self.size -= values_size(values);
                 values.clear();
-                values.insert(Box::from(value));
+                values.insert(value);
             },
         }
+        self.size += LEN_SIZE + value_len;
     }
 
     pub(crate) fn del(&mut self, key: &[u8]) -> Option<()> {
+        let key = self.key(key);
         let map = Arc::make_mut(&mut self.map);
-        match map.get_mut(key) {
+        match map.get_mut(&key) {
             None => None,
             Some(values) => {
                 let was_empty = values.is_empty();
+                self.size -= values_size(values);
                 values.clear();
                 Some(()).filter(|_| !was_empty)
             },
         }
     }
 
+    /// Deletes the key/value pairs whose keys are within the range, and returns how
+    /// many there were, counting each value of a key.
+    pub(crate) fn del_range(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        let keys = self.keys_within(start, end);
+        let deleted = keys.iter().map(|key| self.map[key].len()).sum();
+        for key in &keys {
+            self.del(key.as_ref());
+        }
+        deleted
+    }
+
     pub(crate) fn iter(&self) -> impl Iterator<Item = (&[u8], impl Iterator<Item = &[u8]>)> {
         self.map.iter().map(|(key, values)| (key.as_ref(), values.iter().map(|value| value.as_ref())))
     }
+
+    pub(crate) fn iter_from(&self, key: &[u8]) -> impl Iterator<Item = (&[u8], impl Iterator<Item = &[u8]>)> {
+        let range = (Bound::Included(self.key(key)), Bound::Unbounded);
+        self.map.range(range).map(|(key, values)| (key.as_ref(), values.iter().map(|value| value.as_ref())))
+    }
+
+    pub(crate) fn iter_dup_of(&self, key: &[u8]) -> impl Iterator<Item = (&[u8], impl Iterator<Item = &[u8]>)> {
+        let key = self.key(key);
+        let found = self.map.get_key_value(&key).into_iter();
+        found.map(|(key, values)| (key.as_ref(), values.iter().map(|value| value.as_ref())))
+    }
+
+    pub(crate) fn last_key(&self) -> Option<&[u8]> {
+        self.map.iter().rev().find(|(_, values)| !values.is_empty()).map(|(key, _)| key.as_ref())
+    }
+
+    /// The first key/value pair that comes after the given one, either as another
+    /// value of the same key or as the first value of a following key, or the very
+    /// first key/value pair when none is given.
+    pub(crate) fn pair_after(&self, pair: Option<(&[u8], &[u8])>) -> Option<(&[u8], &[u8])> {
+        let (key, value) = match pair {
+            None => return self.map.iter().find_map(first_pair),
+            Some(pair) => pair,
+        };
+        let range = (Bound::Excluded(self.key(key)), Bound::Unbounded);
+        self.dup_after(key, value).or_else(|| self.map.range(range).find_map(first_pair))
+    }
+
+    /// The last key/value pair that comes before the given one, either as another
+    /// value of the same key or as the last value of a preceding key, or the very
+    /// last key/value pair when none is given.
+    pub(crate) fn pair_before(&self, pair: Option<(&[u8], &[u8])>) -> Option<(&[u8], &[u8])> {
+        let (key, value) = match pair {
+            None => return self.map.iter().rev().find_map(last_pair),
+            Some(pair) => pair,
+        };
+        let range = (Bound::Unbounded, Bound::Excluded(self.key(key)));
+        self.dup_before(key, value).or_else(|| self.map.range(range).rev().find_map(last_pair))
+    }
+
+    /// The first key/value pair whose key is equal to or greater than the given one.
+    pub(crate) fn seek(&self, key: &[u8]) -> Option<(&[u8], &[u8])> {
+        self.map.range((Bound::Included(self.key(key)), Bound::Unbounded)).find_map(first_pair)
+    }
+
+    /// The value of the given key that comes after the given one.
+    pub(crate) fn dup_after(&self, key: &[u8], value: &[u8]) -> Option<(&[u8], &[u8])> {
+        let (key, values) = self.map.get_key_value(&self.key(key))?;
+        let found = values.range((Bound::Excluded(self.value(value)), Bound::Unbounded)).next();
+        found.map(|value| (key.as_ref(), value.as_ref()))
+    }
+
+    /// The value of the given key that comes before the given one.
+    pub(crate) fn dup_before(&self, key: &[u8], value: &[u8]) -> Option<(&[u8], &[u8])> {
+        let (key, values) = self.map.get_key_value(&self.key(key))?;
+        let found = values.range((Bound::Unbounded, Bound::Excluded(self.value(value)))).next_back();
+        found.map(|value| (key.as_ref(), value.as_ref()))
+    }
+
+    pub(crate) fn entries(&self) -> usize {
+        self.map.values().map(|values| values.len()).sum()
+    }
 }
 
 #[cfg(feature = "db-dup-sort")]
 impl Snapshot {
     pub(crate) fn put_dup(&mut self, key: &[u8], value: &[u8]) {
+        let (key, value) = (self.key(key), self.value(value));
+        let value_len = value.len();
         let map = Arc::make_mut(&mut self.map);
-        match map.get_mut(key) {
+        match map.get_mut(&key) {
             None => {
+                self.size += LEN_SIZE + key.len() + LEN_SIZE;
                 let mut values = BTreeSet::new();
-                values.insert(Box::from(value));
-                map.insert(Box::from(key), values);
+                values.insert(value);
+                map.insert(key, values);
             },
             Some(values) => {
-                values.insert(Box::from(value));
+                if !values.insert(value) {
+                    return;
+                }
             },
         }
+        self.size += LEN_SIZE + value_len;
+    }
+
+    pub(crate) fn contains_dup(&self, key: &[u8], value: &[u8]) -> bool {
+        self.map.get(&self.key(key)).map_or(false, |values| values.contains(&self.value(value)))
+    }
+
+    pub(crate) fn last_dup(&self, key: &[u8]) -> Option<&[u8]> {
+        self.map.get(&self.key(key)).and_then(|values| values.iter().next_back()).map(|value| value.as_ref())
     }
 
     pub(crate) fn del_exact(&mut self, key: &[u8], value: &[u8]) -> Option<()> {
+        let (key, value) = (self.key(key), self.value(value));
         let map = Arc::make_mut(&mut self.map);
-        match map.get_mut(key) {
+        match map.get_mut(&key) {
             None => None,
             Some(values) => {
-                let was_removed = values.remove(value);
+                let was_removed = values.remove(&value);
+                if was_removed {
+                    self.size -= LEN_SIZE + value.len();
+                }
                 Some(()).filter(|_| was_removed)
             },
         }
     }
+
+    /// Compares two values of a key the way they are sorted in this database.
+    pub(crate) fn compare_values(&self, a: &[u8], b: &[u8]) -> Ordering {
+        Order::of_values(self.flags).compare(a, b)
+    }
+
+    fn value(&self, value: &[u8]) -> Value {
+        Bytes::new(Order::of_values(self.flags), value)
+    }
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn first_pair<'s>((key, values): (&'s Key, &'s BTreeSet<Value>)) -> Option<(&'s [u8], &'s [u8])> {
+    values.iter().next().map(|value| (key.as_ref(), value.as_ref()))
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn last_pair<'s>((key, values): (&'s Key, &'s BTreeSet<Value>)) -> Option<(&'s [u8], &'s [u8])> {
+    values.iter().next_back().map(|value| (key.as_ref(), value.as_ref()))
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn values_size(values: &BTreeSet<Value>) -> usize {
+    values.iter().map(|value| LEN_SIZE + value.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_size(snapshot: &Snapshot) {
+        assert_eq!(snapshot.size(), bincode::serialized_size(snapshot).expect("measured") as usize);
+    }
+
+    #[test]
+    fn test_size() {
+        let mut snapshot = Snapshot::new(None);
+        assert_size(&snapshot);
+        snapshot.put(b"foo", b"bar");
+        assert_size(&snapshot);
+        snapshot.put(b"foo", b"bazqux");
+        assert_size(&snapshot);
+        snapshot.put(b"hello", b"");
+        assert_size(&snapshot);
+        snapshot.del(b"foo").expect("deleted");
+        assert_size(&snapshot);
+        assert!(snapshot.del(b"foo").is_none());
+        assert_size(&snapshot);
+        snapshot.put(b"foo", b"bar");
+        assert_eq!(snapshot.del_range(Bound::Excluded(b"foo"), Bound::Unbounded), 1);
+        assert_size(&snapshot);
+
+        let bytes = bincode::serialize(&snapshot).expect("serialized");
+        let deserialized: Snapshot = bincode::deserialize(&bytes).expect("deserialized");
+        assert_eq!(deserialized.size(), bytes.len());
+
+        snapshot.clear();
+        assert_size(&snapshot);
+    }
+
+    #[test]
+    #[cfg(feature = "db-dup-sort")]
+    fn test_size_dup() {
+        let mut snapshot = Snapshot::new(Some(DatabaseFlagsImpl::DUP_SORT));
+        snapshot.put_dup(b"foo", b"bar");
+        assert_size(&snapshot);
+        snapshot.put_dup(b"foo", b"baz");
+        assert_size(&snapshot);
+        snapshot.put_dup(b"foo", b"baz");
+        assert_size(&snapshot);
+        snapshot.del_exact(b"foo", b"bar").expect("deleted");
+        assert_size(&snapshot);
+        assert!(snapshot.del_exact(b"foo", b"bar").is_none());
+        assert_size(&snapshot);
+        snapshot.put(b"foo", b"qux");
+        assert_size(&snapshot);
+        snapshot.del(b"foo").expect("deleted");
+        assert_size(&snapshot);
+        snapshot.put_dup(b"bar", b"baz");
+        snapshot.put_dup(b"bar", b"qux");
+        assert_eq!(snapshot.del_range(Bound::Unbounded, Bound::Included(b"foo")), 2);
+        assert_size(&snapshot);
+    }
 }
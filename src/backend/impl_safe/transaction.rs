@@ -9,39 +9,65 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
-    collections::HashMap,
-    sync::Arc,
+    cmp::Ordering,
+    collections::{
+        hash_map::Entry,
+        HashMap,
+    },
+    ops::Bound,
+    sync::{
+        Arc,
+        MutexGuard,
+    },
 };
 
 use super::{
+    lock::LockGuard,
     snapshot::Snapshot,
+    wal::{
+        Change,
+        DatabaseChanges,
+    },
     DatabaseImpl,
     EnvironmentImpl,
     ErrorImpl,
     RoCursorImpl,
+    RwCursorImpl,
     WriteFlagsImpl,
 };
-use crate::backend::traits::{
-    BackendRoCursorTransaction,
-    BackendRoTransaction,
-    BackendRwCursorTransaction,
-    BackendRwTransaction,
+use crate::backend::{
+    common::ReaderSlot,
+    traits::{
+        BackendRoCursorTransaction,
+        BackendRoTransaction,
+        BackendRwCursorTransaction,
+        BackendRwTransaction,
+    },
 };
 
+type Snapshots = HashMap<DatabaseImpl, Snapshot>;
+type Changes = HashMap<DatabaseImpl, Vec<Change>>;
+
 #[derive(Debug)]
 pub struct RoTransactionImpl<'t> {
     env: &'t EnvironmentImpl,
-    snapshots: HashMap<DatabaseImpl, Snapshot>,
+    snapshots: Arc<HashMap<DatabaseImpl, Snapshot>>,
     idx: Arc<()>,
+    // Keeps the transaction listed in the reader table of the environment.
+    #[allow(dead_code)]
+    slot: Arc<ReaderSlot>,
 }
 
 impl<'t> RoTransactionImpl<'t> {
     pub(crate) fn new(env: &'t EnvironmentImpl, idx: Arc<()>) -> Result<RoTransactionImpl<'t>, ErrorImpl> {
-        let snapshots = env.dbs()?.arena.iter().map(|(id, db)| (DatabaseImpl(id), db.snapshot())).collect();
+        env.refresh_if_changed()?;
+        let snapshots = env.dbs()?.snapshots.clone();
+        let slot = env.add_reader()?;
         Ok(RoTransactionImpl {
             env,
             snapshots,
             idx,
+            slot,
         })
     }
 }
@@ -65,26 +91,134 @@ impl<'t> BackendRoCursorTransaction<'t> for RoTransactionImpl<'t> {
 
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
         let snapshot = self.snapshots.get(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
-        Ok(RoCursorImpl(snapshot))
+        Ok(RoCursorImpl::new(snapshot))
     }
 }
 
 #[derive(Debug)]
 pub struct RwTransactionImpl<'t> {
     env: &'t EnvironmentImpl,
-    snapshots: HashMap<DatabaseImpl, Snapshot>,
+    // The databases as of the beginning of the transaction, and copies of the ones
+    // that were changed since.
+    base: Arc<HashMap<DatabaseImpl, Snapshot>>,
+    base_size: usize,
+    snapshots: Snapshots,
+    changes: Changes,
+    // The snapshots and changes of the parents of nested transactions, innermost last.
+    // Nested transactions start from clones of the snapshots, which only copy the
+    // databases they change, and record their own changes.
+    parents: Vec<(Snapshots, Changes)>,
     idx: Arc<()>,
+    // Like with LMDB, there can only be one write transaction at any given time,
+    // across all the processes sharing the environment, so that commits are applied
+    // in the same order in memory and in the log.
+    writer: MutexGuard<'t, ()>,
+    lock: Option<LockGuard>,
 }
 
 impl<'t> RwTransactionImpl<'t> {
     pub(crate) fn new(env: &'t EnvironmentImpl, idx: Arc<()>) -> Result<RwTransactionImpl<'t>, ErrorImpl> {
-        let snapshots = env.dbs()?.arena.iter().map(|(id, db)| (DatabaseImpl(id), db.snapshot())).collect();
+        let (writer, lock) = env.writer()?;
+        let dbs = env.dbs()?;
+        let base = dbs.snapshots.clone();
+        let base_size = dbs.size();
+        drop(dbs);
         Ok(RwTransactionImpl {
             env,
-            snapshots,
+            base,
+            base_size,
+            snapshots: HashMap::new(),
+            changes: HashMap::new(),
+            parents: vec![],
             idx,
+            writer,
+            lock,
         })
     }
+
+    fn snapshot(&self, db: &DatabaseImpl) -> Result<&Snapshot, ErrorImpl> {
+        self.snapshots.get(db).or_else(|| self.base.get(db)).ok_or(ErrorImpl::DbIsForeignError)
+    }
+
+    fn snapshot_mut(&mut self, db: &DatabaseImpl) -> Result<&mut Snapshot, ErrorImpl> {
+        match self.snapshots.entry(*db) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let snapshot = self.base.get(db).ok_or(ErrorImpl::DbIsForeignError)?;
+                Ok(entry.insert(snapshot.clone()))
+            },
+        }
+    }
+
+    // Fails once the databases have grown past the map size. Like with LMDB, the
+    // transaction should then be aborted, and committing it fails for as long as
+    // it doesn't delete enough to get back under the limit.
+    fn check_size(&self) -> Result<(), ErrorImpl> {
+        let size = self.snapshots.iter().fold(self.base_size, |size, (id, snapshot)| {
+            size - self.base.get(id).map_or(0, Snapshot::size) + snapshot.size()
+        });
+        if size > self.env.map_size() {
+            return Err(ErrorImpl::MapFull);
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, db: &DatabaseImpl, change: Change) {
+        let changes = self.changes.entry(*db).or_default();
+        if let Change::Clear = change {
+            changes.clear();
+        }
+        changes.push(change);
+    }
+}
+
+// Checks whether a key/value pair can be written with the given flags, failing the
+// same way LMDB does when it can't. Like with LMDB, `NO_DUP_DATA` and `APPEND_DUP`
+// have no effect on databases that don't support duplicate values. Keys and values
+// are compared in the order of the database, and the values of a key must all have
+// the same size in `DUP_FIXED` databases.
+#[cfg_attr(not(feature = "db-dup-sort"), allow(unused_variables))]
+fn check_write_flags(snapshot: &Snapshot, key: &[u8], value: &[u8], flags: WriteFlagsImpl) -> Result<(), ErrorImpl> {
+    // Like with LMDB, replacing the current pair is only possible through a cursor.
+    if flags.contains(WriteFlagsImpl::CURRENT) {
+        return Err(ErrorImpl::InvalidArgument);
+    }
+    if flags.contains(WriteFlagsImpl::NO_OVERWRITE) && snapshot.get(key).is_some() {
+        return Err(ErrorImpl::KeyExists);
+    }
+    if flags.contains(WriteFlagsImpl::APPEND)
+        && snapshot.last_key().map_or(false, |last| snapshot.compare_keys(key, last) != Ordering::Greater)
+    {
+        return Err(ErrorImpl::KeyExists);
+    }
+    #[cfg(feature = "db-dup-sort")]
+    {
+        use super::DatabaseFlagsImpl;
+        if snapshot.flags().contains(DatabaseFlagsImpl::DUP_SORT) {
+            if flags.contains(WriteFlagsImpl::NO_DUP_DATA) && snapshot.contains_dup(key, value) {
+                return Err(ErrorImpl::KeyExists);
+            }
+            let last = snapshot.last_dup(key);
+            let fixed = snapshot.flags().contains(DatabaseFlagsImpl::DUP_FIXED);
+            if fixed && last.map_or(false, |last| last.len() != value.len()) {
+                return Err(ErrorImpl::KeyValuePairBadSize);
+            }
+            if flags.contains(WriteFlagsImpl::APPEND_DUP)
+                && last.map_or(false, |last| snapshot.compare_values(value, last) != Ordering::Greater)
+            {
+                return Err(ErrorImpl::KeyExists);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn boxed_bound(bound: Bound<&[u8]>) -> Bound<Box<[u8]>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(Box::from(key)),
+        Bound::Excluded(key) => Bound::Excluded(Box::from(key)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
 }
 
 impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
@@ -93,63 +227,153 @@ impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
     type Flags = WriteFlagsImpl;
 
     fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
-        let snapshot = self.snapshots.get(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
+        let snapshot = self.snapshot(db)?;
         snapshot.get(key).ok_or_else(|| ErrorImpl::KeyValuePairNotFound)
     }
 
     #[cfg(not(feature = "db-dup-sort"))]
-    fn put(&mut self, db: &Self::Database, key: &[u8], value: &[u8], _flags: Self::Flags) -> Result<(), Self::Error> {
-        let snapshot = self.snapshots.get_mut(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
+    fn put(&mut self, db: &Self::Database, key: &[u8], value: &[u8], flags: Self::Flags) -> Result<(), Self::Error> {
+        let snapshot = self.snapshot_mut(db)?;
+        check_write_flags(snapshot, key, value, flags)?;
         snapshot.put(key, value);
-        Ok(())
+        self.record(db, Change::Put(key.into(), value.into()));
+        self.check_size()
     }
 
     #[cfg(feature = "db-dup-sort")]
-    fn put(&mut self, db: &Self::Database, key: &[u8], value: &[u8], _flags: Self::Flags) -> Result<(), Self::Error> {
+    fn put(&mut self, db: &Self::Database, key: &[u8], value: &[u8], flags: Self::Flags) -> Result<(), Self::Error> {
         use super::DatabaseFlagsImpl;
-        let snapshot = self.snapshots.get_mut(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
-        if snapshot.flags().contains(DatabaseFlagsImpl::DUP_SORT) {
+        let snapshot = self.snapshot_mut(db)?;
+        check_write_flags(snapshot, key, value, flags)?;
+        let change = if snapshot.flags().contains(DatabaseFlagsImpl::DUP_SORT) {
             snapshot.put_dup(key, value);
+            Change::PutDup(key.into(), value.into())
         } else {
             snapshot.put(key, value);
-        }
-        Ok(())
+            Change::Put(key.into(), value.into())
+        };
+        self.record(db, change);
+        self.check_size()
     }
 
     #[cfg(not(feature = "db-dup-sort"))]
     fn del(&mut self, db: &Self::Database, key: &[u8]) -> Result<(), Self::Error> {
-        let snapshot = self.snapshots.get_mut(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
+        let snapshot = self.snapshot_mut(db)?;
         let deleted = snapshot.del(key);
-        Ok(deleted.ok_or_else(|| ErrorImpl::KeyValuePairNotFound)?)
+        deleted.ok_or_else(|| ErrorImpl::KeyValuePairNotFound)?;
+        self.record(db, Change::Del(key.into()));
+        Ok(())
     }
 
     #[cfg(feature = "db-dup-sort")]
     fn del(&mut self, db: &Self::Database, key: &[u8], value: Option<&[u8]>) -> Result<(), Self::Error> {
         use super::DatabaseFlagsImpl;
-        let snapshot = self.snapshots.get_mut(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
-        let deleted = match (value, snapshot.flags()) {
-            (Some(value), flags) if flags.contains(DatabaseFlagsImpl::DUP_SORT) => snapshot.del_exact(key, value),
-            _ => snapshot.del(key),
+        let snapshot = self.snapshot_mut(db)?;
+        let (deleted, change) = match (value, snapshot.flags()) {
+            (Some(value), flags) if flags.contains(DatabaseFlagsImpl::DUP_SORT) => {
+                (snapshot.del_exact(key, value), Change::DelExact(key.into(), value.into()))
+            },
+            _ => (snapshot.del(key), Change::Del(key.into())),
         };
-        Ok(deleted.ok_or_else(|| ErrorImpl::KeyValuePairNotFound)?)
+        deleted.ok_or_else(|| ErrorImpl::KeyValuePairNotFound)?;
+        self.record(db, change);
+        Ok(())
     }
 
     fn clear_db(&mut self, db: &Self::Database) -> Result<(), Self::Error> {
-        let snapshot = self.snapshots.get_mut(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
+        let snapshot = self.snapshot_mut(db)?;
         snapshot.clear();
+        self.record(db, Change::Clear);
+        Ok(())
+    }
+
+    fn del_range(&mut self, db: &Self::Database, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<usize, Self::Error> {
+        let snapshot = self.snapshot_mut(db)?;
+        let deleted = snapshot.del_range(start, end);
+        if deleted > 0 {
+            self.record(db, Change::DelRange(boxed_bound(start), boxed_bound(end)));
+        }
+        Ok(deleted)
+    }
+
+    fn sorts_keys_bytewise(&self, db: &Self::Database) -> Result<bool, Self::Error> {
+        Ok(self.snapshot(db)?.sorts_keys_bytewise())
+    }
+
+    fn begin_nested(&mut self) -> Result<(), Self::Error> {
+        let changes = std::mem::take(&mut self.changes);
+        self.parents.push((self.snapshots.clone(), changes));
+        Ok(())
+    }
+
+    fn commit_nested(&mut self) -> Result<(), Self::Error> {
+        if let Some((_, changes)) = self.parents.pop() {
+            let nested = std::mem::replace(&mut self.changes, changes);
+            for (id, changes) in nested {
+                changes.into_iter().for_each(|change| self.record(&id, change));
+            }
+        }
         Ok(())
     }
 
-    fn commit(self) -> Result<(), Self::Error> {
-        let mut dbs = self.env.dbs_mut()?;
+    fn abort_nested(&mut self) {
+        if let Some((snapshots, changes)) = self.parents.pop() {
+            self.snapshots = snapshots;
+            self.changes = changes;
+        }
+    }
 
-        for (id, snapshot) in self.snapshots {
-            let db = dbs.arena.get_mut(id.0).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
-            db.replace(snapshot);
+    fn commit(mut self) -> Result<(), Self::Error> {
+        while !self.parents.is_empty() {
+            self.commit_nested()?;
         }
+        self.check_size()?;
+        let RwTransactionImpl {
+            env,
+            mut snapshots,
+            changes,
+            writer: _writer,
+            mut lock,
+            ..
+        } = self;
 
+        // The databases stay locked until the changes are logged, so that readers never
+        // observe a commit which could still fail, or be lost in a crash.
+        let mut log_len = env.log()?;
+        let mut dbs = env.dbs_mut()?;
+        let mut record = Vec::with_capacity(changes.len());
+        let mut updated = Vec::with_capacity(changes.len());
+        for (id, changes) in changes {
+            let snapshot = snapshots.remove(&id).ok_or(ErrorImpl::DbIsForeignError)?;
+            let name = dbs.name_of(id).ok_or(ErrorImpl::DbIsForeignError)?.clone();
+            record.push(DatabaseChanges {
+                name,
+                flags: *snapshot.flags(),
+                changes,
+            });
+            updated.push((id, snapshot));
+        }
+        for &id in dbs.unlogged.iter().filter(|id| updated.iter().all(|(other, _)| other != *id)) {
+            let name = dbs.name_of(id).ok_or(ErrorImpl::DbIsForeignError)?.clone();
+            record.push(DatabaseChanges {
+                name,
+                flags: dbs.arena[id.0].flags(),
+                changes: vec![],
+            });
+        }
+        if record.is_empty() {
+            return Ok(());
+        }
+
+        env.write_to_log(&mut log_len, lock.as_mut(), &record)?;
+        for (id, snapshot) in updated {
+            dbs.arena[id.0].touch();
+            Arc::make_mut(&mut dbs.snapshots).insert(id, snapshot);
+        }
+        dbs.unlogged.clear();
         drop(dbs);
-        self.env.write_to_disk()
+        env.checkpoint_if_needed(&mut log_len);
+        Ok(())
     }
 
     fn abort(self) {
@@ -157,11 +381,18 @@ impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
     }
 }
 
-impl<'t> BackendRwCursorTransaction<'t> for RwTransactionImpl<'t> {
+impl<'t, 'e> BackendRwCursorTransaction<'t> for RwTransactionImpl<'e> {
     type RoCursor = RoCursorImpl<'t>;
+    type RwCursor = RwCursorImpl<'t>;
 
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
-        let snapshot = self.snapshots.get(db).ok_or_else(|| ErrorImpl::DbIsForeignError)?;
-        Ok(RoCursorImpl(snapshot))
+        let snapshot = self.snapshot(db)?;
+        Ok(RoCursorImpl::new(snapshot))
+    }
+
+    fn open_rw_cursor(&'t mut self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
+        self.snapshot_mut(db)?;
+        let snapshot = self.snapshots.get_mut(db).ok_or(ErrorImpl::DbIsForeignError)?;
+        Ok(RwCursorImpl::new(*db, snapshot, &mut self.changes))
     }
 }
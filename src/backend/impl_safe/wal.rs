@@ -0,0 +1,295 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! An append-only log of committed write transactions. Each commit appends a single
+//! record containing the changes it made, so that its cost is proportional to the size
+//! of the change instead of to the size of the whole environment. The log is replayed
+//! on top of the last snapshot when opening an environment, and is periodically folded
+//! back into that snapshot (checkpointed) once it grows past a configurable threshold.
+
+use std::{
+    fs::{
+        self,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    ops::Bound,
+    path::Path,
+};
+
+use byteorder::{
+    ByteOrder,
+    LittleEndian,
+    WriteBytesExt,
+};
+use serde_derive::{
+    Deserialize,
+    Serialize,
+};
+
+use super::{
+    disk::{
+        crash_point,
+        WriteStep,
+    },
+    snapshot::Snapshot,
+    DatabaseFlagsImpl,
+    ErrorImpl,
+};
+
+type Key = Box<[u8]>;
+type Value = Box<[u8]>;
+
+// Each record is preceded by its length and its crc32, both as little-endian u32.
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// A single modification made to a database by a write transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum Change {
+    Put(Key, Value),
+    PutDup(Key, Value),
+    Del(Key),
+    DelExact(Key, Value),
+    Clear,
+    // Changes to the database itself rather than to its contents, which the environment
+    // applies to the catalog of the databases when replaying the log.
+    Drop,
+    Rename(String),
+    // Variants are logged by index, so new ones go last.
+    DelRange(Bound<Key>, Bound<Key>),
+}
+
+impl Change {
+    /// Replays this change on top of a snapshot. Every change sets the state of the
+    /// key/value pairs it affects regardless of their previous state, so replaying a
+    /// record more than once yields the same result as replaying it once.
+    pub(crate) fn apply(&self, snapshot: &mut Snapshot) {
+        match self {
+            Change::Put(key, value) => snapshot.put(key, value),
+            #[cfg(feature = "db-dup-sort")]
+            Change::PutDup(key, value) => snapshot.put_dup(key, value),
+            #[cfg(not(feature = "db-dup-sort"))]
+            Change::PutDup(key, value) => snapshot.put(key, value),
+            Change::Del(key) => {
+                snapshot.del(key);
+            },
+            #[cfg(feature = "db-dup-sort")]
+            Change::DelExact(key, value) => {
+                snapshot.del_exact(key, value);
+            },
+            #[cfg(not(feature = "db-dup-sort"))]
+            Change::DelExact(key, _) => {
+                snapshot.del(key);
+            },
+            Change::Clear => snapshot.clear(),
+            Change::Drop | Change::Rename(_) => {},
+            Change::DelRange(start, end) => {
+                snapshot.del_range(borrowed_bound(start), borrowed_bound(end));
+            },
+        }
+    }
+}
+
+fn borrowed_bound(bound: &Bound<Key>) -> Bound<&[u8]> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key),
+        Bound::Excluded(key) => Bound::Excluded(key),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// All the changes made to a single database by a committed write transaction.
+/// The database flags are recorded as well, so that databases created after the
+/// last checkpoint can be recreated when replaying the log.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DatabaseChanges {
+    pub(crate) name: Option<String>,
+    pub(crate) flags: DatabaseFlagsImpl,
+    pub(crate) changes: Vec<Change>,
+}
+
+/// A committed write transaction, as stored in the log.
+pub(crate) type Record = Vec<DatabaseChanges>;
+
+/// Reads all the complete records from the log at `path`, along with the length of
+/// the log up to and including the last of them. A last record which is cut short or
+/// fails its checksum is the remainder of an append that was interrupted by a crash,
+/// and is ignored. Any other record that fails to be read means that the log has been
+/// corrupted, in which case the records following it are only dropped if
+/// `discard_if_corrupted` is set.
+pub(crate) fn read(path: &Path, discard_if_corrupted: bool) -> Result<(Vec<Record>, u64), ErrorImpl> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok((vec![], 0)),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut records = vec![];
+    let mut offset = 0;
+    while bytes.len() - offset >= FRAME_HEADER_SIZE {
+        let start = offset + FRAME_HEADER_SIZE;
+        let end = start + LittleEndian::read_u32(&bytes[offset..offset + 4]) as usize;
+        if end > bytes.len() {
+            break;
+        }
+        let checksum = LittleEndian::read_u32(&bytes[offset + 4..start]);
+        let record = if crc32fast::hash(&bytes[start..end]) == checksum {
+            bincode::deserialize(&bytes[start..end]).map_err(ErrorImpl::from)
+        } else {
+            Err(ErrorImpl::FileCorrupted)
+        };
+        match record {
+            Ok(record) => records.push(record),
+            Err(_) if end == bytes.len() || discard_if_corrupted => break,
+            Err(_) => return Err(ErrorImpl::FileCorrupted),
+        }
+        offset = end;
+    }
+    Ok((records, offset as u64))
+}
+
+/// Appends a record to the log at `path`, whose valid contents end at `len`, and
+/// returns the new length of the log. Any leftovers from a previously interrupted
+/// append are overwritten. Unless `sync` is false, the record is flushed to disk
+/// before returning. Records of 4 GiB or more can't be framed, and are rejected
+/// without writing anything.
+pub(crate) fn append(path: &Path, len: u64, record: &Record, sync: bool) -> Result<u64, ErrorImpl> {
+    let payload = bincode::serialize(record)?;
+    if payload.len() > u32::MAX as usize {
+        return Err(ErrorImpl::InvalidArgument);
+    }
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.write_u32::<LittleEndian>(payload.len() as u32)?;
+    frame.write_u32::<LittleEndian>(crc32fast::hash(&payload))?;
+    frame.extend_from_slice(&payload);
+
+    let mut file = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+    if file.metadata()?.len() != len {
+        file.set_len(len)?;
+    }
+    file.seek(SeekFrom::Start(len))?;
+    crash_point(WriteStep::WriteLog, Some((&mut file, &frame)))?;
+    file.write_all(&frame)?;
+    crash_point(WriteStep::SyncLog, None)?;
+    if sync {
+        file.sync_data()?;
+    }
+    Ok(len + frame.len() as u64)
+}
+
+/// Empties the log at `path`, once all its records have been checkpointed.
+pub(crate) fn truncate(path: &Path) -> Result<(), ErrorImpl> {
+    match OpenOptions::new().write(true).open(path) {
+        Ok(file) => {
+            file.set_len(0)?;
+            file.sync_data()?;
+            Ok(())
+        },
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::Builder;
+
+    fn record(key: &str, value: &str) -> Record {
+        vec![DatabaseChanges {
+            name: Some("db".into()),
+            flags: DatabaseFlagsImpl::empty(),
+            changes: vec![Change::Put(key.as_bytes().into(), value.as_bytes().into())],
+        }]
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let root = Builder::new().prefix("test_wal_append_and_read").tempdir().expect("tempdir");
+        let path = root.path().join("log");
+
+        assert_eq!(read(&path, false).expect("read").1, 0);
+        let len = append(&path, 0, &record("foo", "bar"), true).expect("appended");
+        let len = append(&path, len, &record("baz", "qux"), true).expect("appended");
+
+        let (records, read_len) = read(&path, false).expect("read");
+        assert_eq!(records.len(), 2);
+        assert_eq!(read_len, len);
+
+        truncate(&path).expect("truncated");
+        assert_eq!(read(&path, false).expect("read").0.len(), 0);
+    }
+
+    #[test]
+    fn test_torn_append() {
+        let root = Builder::new().prefix("test_wal_torn_append").tempdir().expect("tempdir");
+        let path = root.path().join("log");
+
+        let len = append(&path, 0, &record("foo", "bar"), true).expect("appended");
+        let full = append(&path, len, &record("baz", "qux"), true).expect("appended");
+
+        // Cut the second record short, as if the process died while appending it.
+        OpenOptions::new().write(true).open(&path).expect("opened").set_len(full - 3).expect("truncated");
+        let (records, read_len) = read(&path, false).expect("read");
+        assert_eq!(records.len(), 1);
+        assert_eq!(read_len, len);
+
+        // The next append overwrites the leftovers.
+        let len = append(&path, read_len, &record("abc", "def"), true).expect("appended");
+        let (records, read_len) = read(&path, false).expect("read");
+        assert_eq!(records.len(), 2);
+        assert_eq!(read_len, len);
+    }
+
+    #[test]
+    fn test_corrupted_record() {
+        let root = Builder::new().prefix("test_wal_corrupted_record").tempdir().expect("tempdir");
+        let path = root.path().join("log");
+
+        let len = append(&path, 0, &record("foo", "bar"), true).expect("appended");
+        append(&path, len, &record("baz", "qux"), true).expect("appended");
+
+        // Flip a bit in the second record. Its payload still decodes, but its
+        // checksum no longer matches, so it's dropped.
+        let mut bytes = fs::read(&path).expect("read");
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(&path, &bytes).expect("written");
+
+        let (records, read_len) = read(&path, false).expect("read");
+        assert_eq!(records.len(), 1);
+        assert_eq!(read_len, len);
+    }
+    #[test]
+    fn test_corrupted_record_before_others() {
+        let root = Builder::new().prefix("test_wal_corrupted_record_before_others").tempdir().expect("tempdir");
+        let path = root.path().join("log");
+
+        let len = append(&path, 0, &record("foo", "bar"), true).expect("appended");
+        append(&path, len, &record("baz", "qux"), true).expect("appended");
+
+        // Flip a bit in the first record. It can't be the remainder of an interrupted
+        // append, since another record follows it, so the log is corrupted.
+        let mut bytes = fs::read(&path).expect("read");
+        bytes[len as usize - 1] ^= 1;
+        fs::write(&path, &bytes).expect("written");
+
+        assert!(matches!(read(&path, false), Err(ErrorImpl::FileCorrupted)));
+        let (records, read_len) = read(&path, true).expect("read");
+        assert_eq!(records.len(), 0);
+        assert_eq!(read_len, 0);
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::{
+    cmp::Ordering,
+    ops::{
+        Bound,
+        RangeBounds,
+    },
+};
+
+use crate::{
+    backend::traits::{
+        BackendIter,
+        BackendPositionedCursor,
+    },
+    error::StoreError,
+    helpers::prefix_range,
+};
+
+type Found<'i, C> = Option<Result<(&'i [u8], &'i [u8]), <C as BackendPositionedCursor<'i>>::Error>>;
+
+/// Iterates over the key/value pairs of a database from the last one to the first,
+/// with the values of each key in reverse order too, by moving a cursor backwards.
+pub struct RevIter<'i, C>
+where
+    C: BackendPositionedCursor<'i>,
+{
+    cursor: C,
+    // The pair that the cursor is on, which is yielded next.
+    found: Found<'i, C>,
+}
+
+impl<'i, C> RevIter<'i, C>
+where
+    C: BackendPositionedCursor<'i>,
+{
+    pub(crate) fn new(mut cursor: C) -> RevIter<'i, C> {
+        let found = cursor.last();
+        RevIter {
+            cursor,
+            found,
+        }
+    }
+
+    /// Starts from the last value of the given key, or from the last key/value pair
+    /// that comes before it when the key isn't there.
+    pub(crate) fn new_from<K>(mut cursor: C, key: K) -> RevIter<'i, C>
+    where
+        K: AsRef<[u8]>,
+    {
+        let found = match cursor.seek(key.as_ref()) {
+            Some(Ok((found, mut value))) if found == key.as_ref() => {
+                loop {
+                    match cursor.next_dup() {
+                        Some(Ok((_, next))) => value = next,
+                        Some(Err(error)) => break Some(Err(error)),
+                        None => break Some(Ok((found, value))),
+                    }
+                }
+            },
+            Some(Ok(_)) => cursor.prev(),
+            Some(Err(error)) => Some(Err(error)),
+            None => cursor.last(),
+        };
+        RevIter {
+            cursor,
+            found,
+        }
+    }
+}
+
+impl<'i, C> BackendIter<'i> for RevIter<'i, C>
+where
+    C: BackendPositionedCursor<'i>,
+{
+    type Error = C::Error;
+
+    #[allow(clippy::type_complexity)]
+    fn next(&mut self) -> Option<Result<(&'i [u8], &'i [u8]), Self::Error>> {
+        let found = self.found.take()?;
+        if found.is_ok() {
+            self.found = self.cursor.prev();
+        }
+        Some(found)
+    }
+}
+
+/// Iterates over the key/value pairs of a database whose keys are within a range,
+/// by moving a cursor forwards until it goes past the end of the range.
+pub struct RangeIter<'i, C>
+where
+    C: BackendPositionedCursor<'i>,
+{
+    cursor: C,
+    end: Bound<Box<[u8]>>,
+    // The pair that the cursor is on, which is yielded next if it's within the range.
+    found: Found<'i, C>,
+}
+
+impl<'i, C> RangeIter<'i, C>
+where
+    C: BackendPositionedCursor<'i>,
+{
+    pub(crate) fn new<K, B>(mut cursor: C, range: B) -> RangeIter<'i, C>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        B: RangeBounds<K>,
+    {
+        let found = match range.start_bound() {
+            Bound::Included(start) => cursor.seek(start.as_ref()),
+            Bound::Excluded(start) => {
+                let mut found = cursor.seek(start.as_ref());
+                while let Some(Ok((key, _))) = found {
+                    if cursor.compare_keys(key, start.as_ref()) != Ordering::Equal {
+                        break;
+                    }
+                    found = cursor.next();
+                }
+                found
+            },
+            Bound::Unbounded => cursor.first(),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => Bound::Included(Box::from(end.as_ref())),
+            Bound::Excluded(end) => Bound::Excluded(Box::from(end.as_ref())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        RangeIter {
+            cursor,
+            end,
+            found,
+        }
+    }
+
+    /// Iterates over the keys that start with the given prefix, which only follow
+    /// each other in databases that sort their keys byte by byte. Other databases
+    /// fail with `StoreError::PrefixNotSupported`.
+    pub(crate) fn new_prefix<K>(cursor: C, prefix: K) -> Result<RangeIter<'i, C>, StoreError>
+    where
+        K: AsRef<[u8]>,
+    {
+        if !cursor.sorts_keys_bytewise().map_err(|e| e.into())? {
+            return Err(StoreError::PrefixNotSupported);
+        }
+        Ok(RangeIter::new(cursor, prefix_range(prefix.as_ref())))
+    }
+
+    fn is_within(&self, key: &[u8]) -> bool {
+        match &self.end {
+            Bound::Included(end) => self.cursor.compare_keys(key, end) != Ordering::Greater,
+            Bound::Excluded(end) => self.cursor.compare_keys(key, end) == Ordering::Less,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<'i, C> BackendIter<'i> for RangeIter<'i, C>
+where
+    C: BackendPositionedCursor<'i>,
+{
+    type Error = C::Error;
+
+    #[allow(clippy::type_complexity)]
+    fn next(&mut self) -> Option<Result<(&'i [u8], &'i [u8]), Self::Error>> {
+        let found = self.found.take()?;
+        match found {
+            Ok((key, _)) if !self.is_within(key) => return None,
+            Ok(_) => self.found = self.cursor.next(),
+            Err(_) => {},
+        }
+        Some(found)
+    }
+}
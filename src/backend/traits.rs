@@ -9,10 +9,12 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
+    cmp::Ordering,
     fmt::{
         Debug,
         Display,
     },
+    ops::Bound,
     path::{
         Path,
         PathBuf,
@@ -23,6 +25,8 @@ use crate::{
     backend::common::{
         DatabaseFlags,
         EnvironmentFlags,
+        MapGrowth,
+        ReaderSlot,
         WriteFlags,
     },
     error::StoreError,
@@ -95,6 +99,12 @@ pub trait BackendEnvironmentBuilder<'b>: Debug + Eq + PartialEq + Copy + Clone {
 
     fn set_discard_if_corrupted(&mut self, discard_if_corrupted: bool) -> &mut Self;
 
+    // Sets the policy that `Rkv::write_batch` grows the map by. Other write transactions
+    // still fail with `MapFull` once the map is full.
+    fn set_map_growth(&mut self, map_growth: MapGrowth) -> &mut Self;
+
+    fn set_reader_check_on_open(&mut self, reader_check_on_open: bool) -> &mut Self;
+
     fn open(&self, path: &Path) -> Result<Self::Environment, Self::Error>;
 }
 
@@ -113,6 +123,15 @@ pub trait BackendEnvironment<'e>: Debug {
 
     fn create_db(&self, name: Option<&str>, flags: Self::Flags) -> Result<Self::Database, Self::Error>;
 
+    // Deletes a named database and its contents. Like opening databases, this fails
+    // while transactions are active in this process, and the database mustn't be used
+    // anymore afterwards.
+    fn drop_db(&self, name: &str) -> Result<(), Self::Error>;
+
+    // Gives a named database another name, which mustn't be taken, under the same
+    // conditions as `drop_db`.
+    fn rename_db(&self, name: &str, new_name: &str) -> Result<(), Self::Error>;
+
     fn begin_ro_txn(&'e self) -> Result<Self::RoTransaction, Self::Error>;
 
     fn begin_rw_txn(&'e self) -> Result<Self::RwTransaction, Self::Error>;
@@ -121,6 +140,8 @@ pub trait BackendEnvironment<'e>: Debug {
 
     fn stat(&self) -> Result<Self::Stat, Self::Error>;
 
+    fn db_stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error>;
+
     fn info(&self) -> Result<Self::Info, Self::Error>;
 
     fn freelist(&self) -> Result<usize, Self::Error>;
@@ -129,6 +150,17 @@ pub trait BackendEnvironment<'e>: Debug {
 
     fn set_map_size(&self, size: usize) -> Result<(), Self::Error>;
 
+    // Grows the map according to the policy set with `set_map_growth`, if any. Unless
+    // the map is `full`, it only grows once the policy's load ratio is reached. Returns
+    // whether the map grew, which never happens while transactions are active.
+    fn grow_map(&self, full: bool) -> Result<bool, Self::Error>;
+
+    fn readers(&self) -> Result<Vec<ReaderSlot>, Self::Error>;
+
+    fn reader_check(&self) -> Result<usize, Self::Error>;
+
+    fn copy_to(&self, path: &Path, compact: bool) -> Result<(), Self::Error>;
+
     fn get_files_on_disk(&self) -> Vec<PathBuf>;
 }
 
@@ -158,21 +190,41 @@ pub trait BackendRwTransaction: Debug {
 
     fn clear_db(&mut self, db: &Self::Database) -> Result<(), Self::Error>;
 
+    // Deletes the key/value pairs whose keys are within the range, compared the way the
+    // database sorts them, and returns how many there were.
+    fn del_range(&mut self, db: &Self::Database, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<usize, Self::Error>;
+
+    // Whether the database sorts its keys byte by byte, which isn't the case with
+    // `REVERSE_KEY` and `INTEGER_KEY`.
+    fn sorts_keys_bytewise(&self, db: &Self::Database) -> Result<bool, Self::Error>;
+
+    // Begins a transaction nested in this one, or in the innermost nested one, which
+    // the following operations apply to until it's committed into its parent or aborted.
+    fn begin_nested(&mut self) -> Result<(), Self::Error>;
+
+    fn commit_nested(&mut self) -> Result<(), Self::Error>;
+
+    fn abort_nested(&mut self);
+
+    // Like with LMDB, committing a transaction commits its nested transactions too.
     fn commit(self) -> Result<(), Self::Error>;
 
     fn abort(self);
 }
 
 pub trait BackendRoCursorTransaction<'t>: BackendRoTransaction {
-    type RoCursor: BackendRoCursor<'t>;
+    type RoCursor: BackendRoCursor<'t> + BackendPositionedCursor<'t, Error = Self::Error>;
 
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error>;
 }
 
 pub trait BackendRwCursorTransaction<'t>: BackendRwTransaction {
-    type RoCursor: BackendRoCursor<'t>;
+    type RoCursor: BackendRoCursor<'t> + BackendPositionedCursor<'t, Error = Self::Error>;
+    type RwCursor: BackendRwCursor<'t, Error = Self::Error>;
 
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error>;
+
+    fn open_rw_cursor(&'t mut self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error>;
 }
 
 pub trait BackendRoCursor<'c>: Debug {
@@ -189,6 +241,58 @@ pub trait BackendRoCursor<'c>: Debug {
         K: AsRef<[u8]> + 'c;
 }
 
+// A cursor that moves over the key/value pairs of a database in both directions.
+// `next` and `prev` start from the first and last pair if the cursor wasn't
+// positioned yet. `seek` finds the first pair whose key is equal to or greater than
+// the given one, and `seek_exact` the first pair of exactly that key. Whenever a
+// method returns `None`, the cursor stays where it was, except after a failed seek,
+// which leaves it unpositioned. `next_dup` and `prev_dup` only move between the
+// values of the current key, so they return `None` on unpositioned cursors and on
+// databases without `DUP_SORT`. `compare_keys` sorts keys the way the database does,
+// which `sorts_keys_bytewise` tells is byte by byte, unlike with `REVERSE_KEY` and
+// `INTEGER_KEY`.
+#[allow(clippy::type_complexity)]
+pub trait BackendPositionedCursor<'c>: Debug {
+    type Error: BackendError;
+
+    fn first(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn last(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn seek(&mut self, key: &[u8]) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn seek_exact(&mut self, key: &[u8]) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn next(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn prev(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn next_dup(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn prev_dup(&mut self) -> Option<Result<(&'c [u8], &'c [u8]), Self::Error>>;
+
+    fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+    fn sorts_keys_bytewise(&self) -> Result<bool, Self::Error>;
+}
+
+// A cursor that walks over the key/value pairs of a database while changing them.
+// Each call to `next` moves the cursor to the following pair, or to the first one
+// if it wasn't positioned yet, and the `*_current` methods then apply to that pair.
+// After `del_current`, the next call to `next` returns the pair that followed the
+// deleted one. On databases with `DUP_SORT`, the value given to `put_current` has
+// to sort into the same place as the one it replaces, like with LMDB.
+pub trait BackendRwCursor<'c>: BackendRoCursor<'c> {
+    type Error: BackendError;
+
+    #[allow(clippy::type_complexity)]
+    fn next(&mut self) -> Option<Result<(&[u8], &[u8]), Self::Error>>;
+
+    fn del_current(&mut self) -> Result<(), Self::Error>;
+
+    fn put_current(&mut self, value: &[u8]) -> Result<(), Self::Error>;
+}
+
 pub trait BackendIter<'i> {
     type Error: BackendError;
 
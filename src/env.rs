@@ -28,6 +28,7 @@ use crate::{
         BackendEnvironmentBuilder,
         BackendRoCursorTransaction,
         BackendRwCursorTransaction,
+        ReaderSlot,
         SafeModeError,
     },
     error::{
@@ -41,7 +42,9 @@ use crate::{
     store::{
         single::SingleStore,
         CloseOptions,
+        CopyOptions,
         Options as StoreOptions,
+        Store,
     },
 };
 
@@ -147,7 +150,7 @@ where
         K: PrimitiveInt,
         T: Into<Option<&'s str>>,
     {
-        opts.flags.set(DatabaseFlags::INTEGER_KEY, true);
+        opts.flags.set(DatabaseFlags::INTEGER_KEY, K::INTEGER_KEY);
         self.open(name, opts).map(IntegerStore::new)
     }
 
@@ -180,7 +183,7 @@ where
         K: PrimitiveInt,
         T: Into<Option<&'s str>>,
     {
-        opts.flags.set(DatabaseFlags::INTEGER_KEY, true);
+        opts.flags.set(DatabaseFlags::INTEGER_KEY, K::INTEGER_KEY);
         opts.flags.set(DatabaseFlags::DUP_SORT, true);
         self.open(name, opts).map(MultiIntegerStore::new)
     }
@@ -190,23 +193,40 @@ where
         T: Into<Option<&'s str>>,
     {
         if opts.create {
-            self.env.create_db(name.into(), opts.flags).map_err(|e| {
-                match e.into() {
-                    StoreError::LmdbError(lmdb::Error::BadRslot) => StoreError::open_during_transaction(),
-                    StoreError::SafeModeError(SafeModeError::DbsIllegalOpen) => StoreError::open_during_transaction(),
-                    e => e,
-                }
-            })
+            self.env.create_db(name.into(), opts.flags).map_err(during_transaction)
         } else {
-            self.env.open_db(name.into()).map_err(|e| {
-                match e.into() {
-                    StoreError::LmdbError(lmdb::Error::BadRslot) => StoreError::open_during_transaction(),
-                    StoreError::SafeModeError(SafeModeError::DbsIllegalOpen) => StoreError::open_during_transaction(),
-                    e => e,
-                }
-            })
+            self.env.open_db(name.into()).map_err(during_transaction)
         }
     }
+
+    /// Delete the database named `name` along with its contents, unlike `clear()` on
+    /// its store, which leaves the database behind, counting towards `set_max_dbs()`.
+    /// Stores opened on the database must not be used anymore afterwards.
+    /// Note: like opening a store, this fails with `OpenAttemptedDuringTransaction`
+    /// while transactions are active.
+    pub fn drop_db(&self, name: &str) -> Result<(), StoreError> {
+        self.env.drop_db(name).map_err(during_transaction)
+    }
+
+    /// Rename the database named `name` to `new_name`, which must not be taken, so that
+    /// it can be opened under its new name. Stores opened on the database must not be
+    /// used anymore afterwards. LMDB can't rename databases, so it copies the contents
+    /// to a new database instead, which takes as long as writing them.
+    /// Note: like opening a store, this fails with `OpenAttemptedDuringTransaction`
+    /// while transactions are active.
+    pub fn rename_db(&self, name: &str, new_name: &str) -> Result<(), StoreError> {
+        self.env.rename_db(name, new_name).map_err(during_transaction)
+    }
+}
+
+// The backends fail to open, drop or rename databases while transactions are active,
+// each in their own way.
+fn during_transaction<E: Into<StoreError>>(error: E) -> StoreError {
+    match error.into() {
+        StoreError::LmdbError(lmdb::Error::BadRslot) => StoreError::open_during_transaction(),
+        StoreError::SafeModeError(SafeModeError::DbsIllegalOpen) => StoreError::open_during_transaction(),
+        error => error,
+    }
 }
 
 /// Read and write accessors.
@@ -235,6 +255,42 @@ where
     {
         Ok(Writer::new(self.env.begin_rw_txn().map_err(|e| e.into())?))
     }
+
+    /// Run `f` in a write transaction, and commit it unless `f` fails.
+    ///
+    /// When a `MapGrowth` policy was set with `set_map_growth()` on the environment
+    /// builder, this grows the map instead of failing with `StoreError::MapFull`: the
+    /// transaction is aborted, the map grown, and `f` replayed in a new transaction, so
+    /// `f` should only write to the store through the transaction it's given. With a
+    /// load ratio set in the policy, the map also grows before starting the transaction
+    /// when it's that full.
+    ///
+    /// `StoreError::MapFull` is still returned once the map reaches the maximum size of
+    /// the policy, and with LMDB, while other transactions are active in this process,
+    /// since the map can't grow under them. The policy doesn't apply to transactions
+    /// created with `write()`, whose writes and commit still fail with `MapFull`.
+    pub fn write_batch<T, F, R>(&'e self, mut f: F) -> Result<R, StoreError>
+    where
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+        F: FnMut(&mut Writer<T>) -> Result<R, StoreError>,
+    {
+        self.env.grow_map(false).map_err(|e| e.into())?;
+        loop {
+            let mut writer = self.write()?;
+            let result = match f(&mut writer) {
+                Ok(value) => writer.commit().map(|()| value),
+                Err(e) => {
+                    writer.abort();
+                    Err(e)
+                },
+            };
+            match result {
+                Err(StoreError::MapFull) if self.env.grow_map(true).map_err(|e| e.into())? => continue,
+                result => return result,
+            }
+        }
+    }
 }
 
 /// Other environment methods.
@@ -271,6 +327,16 @@ where
         self.env.stat().map_err(|e| e.into())
     }
 
+    /// Retrieve statistics about the database of `store`, like `stat()` does about the
+    /// whole environment. With SafeMode, the pages stand for the size of the database
+    /// once written to its file.
+    pub fn stat_db<S>(&self, store: &S) -> Result<E::Stat, StoreError>
+    where
+        S: Store<Database = E::Database>,
+    {
+        self.env.db_stat(store.database()).map_err(|e| e.into())
+    }
+
     /// Retrieve information about this environment.
     ///
     /// It includes:
@@ -283,6 +349,24 @@ where
         self.env.info().map_err(|e| e.into())
     }
 
+    /// Retrieve the slots of the reader table of this environment, with the process and
+    /// thread owning each slot, and the transaction that its reader sees, if any.
+    ///
+    /// With LMDB, the table is shared by all the processes using the environment, and the
+    /// slots of processes that died while reading pin the pages of old transactions until
+    /// they're cleared with `reader_check()`. In safe mode, only the readers of this
+    /// process are listed.
+    pub fn readers(&self) -> Result<Vec<ReaderSlot>, StoreError> {
+        self.env.readers().map_err(|e| e.into())
+    }
+
+    /// Clear the slots of the reader table owned by processes that no longer exist, and
+    /// return how many were cleared. This can also be done when opening the environment,
+    /// with `set_reader_check_on_open()` on the environment builder.
+    pub fn reader_check(&self) -> Result<usize, StoreError> {
+        self.env.reader_check().map_err(|e| e.into())
+    }
+
     /// Retrieve the load ratio (# of used pages / total pages) about this environment.
     ///
     /// With the formular: (last_page_no - freelist_pages) / total_pages.
@@ -314,6 +398,20 @@ where
         self.env.set_map_size(size).map_err(Into::into)
     }
 
+    /// Copies this environment to `path`, which is laid out like the path this
+    /// environment was opened with: a directory which must exist, or with
+    /// `NO_SUB_DIR`, the data file of the copy, which must not. The copy is
+    /// consistent, and can be made while the environment is being written to. With
+    /// LMDB however, it first waits for the write transaction in progress, if any, so
+    /// it must not be made from a thread that has one.
+    ///
+    /// With `compact`, the copy leaves out free pages, and renumbers the others
+    /// sequentially, which is slower, but makes for a smaller copy. SafeMode copies
+    /// are always compact.
+    pub fn copy_to(&self, path: &Path, options: CopyOptions) -> Result<(), StoreError> {
+        self.env.copy_to(path, options.compact).map_err(|e| e.into())
+    }
+
     /// Closes this environment and optionally deletes all its files from disk. Doesn't
     /// delete the folder used when opening the environment.
     pub fn close(self, options: CloseOptions) -> Result<(), CloseError> {
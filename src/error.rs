@@ -46,6 +46,9 @@ pub enum DataError {
 
     #[error("invalid uuid bytes")]
     InvalidUuid,
+
+    #[error("invalid key bytes")]
+    InvalidKey,
 }
 
 #[derive(Debug, Error)]
@@ -59,6 +62,12 @@ pub enum StoreError {
     #[error("key/value pair not found")]
     KeyValuePairNotFound,
 
+    #[error("key/value pair already exists")]
+    KeyExists,
+
+    #[error("keys aren't sorted byte by byte, so they can't be matched by prefix")]
+    PrefixNotSupported,
+
     #[error("unsupported size of key/DB name/data")]
     KeyValuePairBadSize,
 
@@ -10,6 +10,7 @@
 
 use std::{
     io,
+    ops::Bound,
     path::{
         Path,
         PathBuf,
@@ -30,6 +31,23 @@ pub(crate) fn read_transform(value: Result<&[u8], StoreError>) -> Result<Value,
     }
 }
 
+// The range of the keys that start with the prefix when sorted byte by byte, which
+// ends before the shortest key that's greater than all of them, if there's any.
+pub(crate) fn prefix_range(prefix: &[u8]) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let mut end = prefix.to_vec();
+    let end = loop {
+        match end.pop() {
+            Some(u8::MAX) => continue,
+            Some(last) => {
+                end.push(last + 1);
+                break Bound::Excluded(end);
+            },
+            None => break Bound::Unbounded,
+        }
+    };
+    (Bound::Included(prefix.to_vec()), end)
+}
+
 // Workaround the UNC path on Windows, see https://github.com/rust-lang/rust/issues/42869.
 // Otherwise, `Env::from_builder()` will panic with error_no(123).
 pub(crate) fn canonicalize_path<'p, P>(path: P) -> io::Result<PathBuf>
@@ -215,6 +215,8 @@ pub mod value;
 pub use backend::{
     DatabaseFlags,
     EnvironmentFlags,
+    MapGrowth,
+    ReaderSlot,
     WriteFlags,
 };
 pub use env::Rkv;
@@ -226,15 +228,21 @@ pub use error::{
 pub use manager::Manager;
 pub use migrator::Migrator;
 pub use readwrite::{
+    NestedWriter,
     Readable,
     Reader,
     Writer,
 };
 pub use store::{
-    keys::EncodableKey,
+    keys::{
+        DecodableKey,
+        EncodableKey,
+    },
     single::SingleStore,
     CloseOptions,
+    CopyOptions,
     Options as StoreOptions,
+    Store,
 };
 pub use value::{
     OwnedValue,
@@ -8,6 +8,13 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+use std::ops::{
+    Bound,
+    Deref,
+    DerefMut,
+    RangeBounds,
+};
+
 use crate::{
     backend::{
         BackendDatabase,
@@ -18,13 +25,27 @@ use crate::{
         BackendRwTransaction,
     },
     error::StoreError,
-    helpers::read_transform,
+    helpers::{
+        prefix_range,
+        read_transform,
+    },
     value::Value,
 };
 
 pub struct Reader<T>(T);
 pub struct Writer<T>(T);
 
+/// A write transaction nested in another one, returned by `Writer::begin_nested()`.
+/// It derefs to the `Writer` of its parent, so that stores can write to it the same
+/// way, and is aborted when dropped without having been committed.
+pub struct NestedWriter<'w, T>
+where
+    T: BackendRwTransaction,
+{
+    writer: &'w mut Writer<T>,
+    finished: bool,
+}
+
 pub trait Readable<'r> {
     type Database: BackendDatabase;
     type RoCursor: BackendRoCursor<'r>;
@@ -115,6 +136,18 @@ where
         self.0.abort();
     }
 
+    /// Begin a write transaction nested in this one, which sees its changes, and whose
+    /// own changes can be rolled back by aborting it, without losing the other ones.
+    /// This transaction can't be used until then, or until the nested one is committed
+    /// into it, which makes its changes part of this transaction.
+    pub fn begin_nested(&mut self) -> Result<NestedWriter<'_, T>, StoreError> {
+        self.0.begin_nested().map_err(|e| e.into())?;
+        Ok(NestedWriter {
+            writer: self,
+            finished: false,
+        })
+    }
+
     pub(crate) fn put<K>(&mut self, db: &T::Database, k: &K, v: &Value, flags: T::Flags) -> Result<(), StoreError>
     where
         K: AsRef<[u8]>,
@@ -142,4 +175,100 @@ where
     pub(crate) fn clear(&mut self, db: &T::Database) -> Result<(), StoreError> {
         self.0.clear_db(db).map_err(|e| e.into())
     }
+
+    pub(crate) fn delete_range<K, B>(&mut self, db: &T::Database, range: B) -> Result<usize, StoreError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        B: RangeBounds<K>,
+    {
+        let (start, end) = (bytes_bound(range.start_bound()), bytes_bound(range.end_bound()));
+        self.0.del_range(db, start, end).map_err(|e| e.into())
+    }
+
+    // The keys that start with the prefix only follow each other in databases that
+    // sort them byte by byte, and the others can't be deleted by range.
+    pub(crate) fn delete_prefix(&mut self, db: &T::Database, prefix: &[u8]) -> Result<usize, StoreError> {
+        if !self.0.sorts_keys_bytewise(db).map_err(|e| e.into())? {
+            return Err(StoreError::PrefixNotSupported);
+        }
+        self.delete_range(db, prefix_range(prefix))
+    }
+}
+
+fn bytes_bound<K>(bound: Bound<&K>) -> Bound<&[u8]>
+where
+    K: AsRef<[u8]> + ?Sized,
+{
+    match bound {
+        Bound::Included(k) => Bound::Included(k.as_ref()),
+        Bound::Excluded(k) => Bound::Excluded(k.as_ref()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl<'w, T> NestedWriter<'w, T>
+where
+    T: BackendRwTransaction,
+{
+    /// Commit the changes of this transaction into its parent, which still has to be
+    /// committed for them to be written.
+    pub fn commit(mut self) -> Result<(), StoreError> {
+        self.finished = true;
+        self.writer.0.commit_nested().map_err(|e| e.into())
+    }
+
+    pub fn abort(mut self) {
+        self.finished = true;
+        self.writer.0.abort_nested();
+    }
+}
+
+impl<'w, T> Drop for NestedWriter<'w, T>
+where
+    T: BackendRwTransaction,
+{
+    fn drop(&mut self) {
+        if !self.finished {
+            self.writer.0.abort_nested();
+        }
+    }
+}
+
+impl<'w, T> Deref for NestedWriter<'w, T>
+where
+    T: BackendRwTransaction,
+{
+    type Target = Writer<T>;
+
+    fn deref(&self) -> &Writer<T> {
+        self.writer
+    }
+}
+
+impl<'w, T> DerefMut for NestedWriter<'w, T>
+where
+    T: BackendRwTransaction,
+{
+    fn deref_mut(&mut self) -> &mut Writer<T> {
+        self.writer
+    }
+}
+
+impl<'r, 'w, T> Readable<'r> for NestedWriter<'w, T>
+where
+    T: BackendRwCursorTransaction<'r>,
+{
+    type Database = T::Database;
+    type RoCursor = T::RoCursor;
+
+    fn get<K>(&'r self, db: &T::Database, k: &K) -> Result<Option<Value<'r>>, StoreError>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.writer.get(db, k)
+    }
+
+    fn open_ro_cursor(&'r self, db: &T::Database) -> Result<T::RoCursor, StoreError> {
+        self.writer.open_ro_cursor(db)
+    }
 }
@@ -20,7 +20,17 @@ pub mod integer;
 #[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
 pub mod integermulti;
 
-use crate::backend::BackendDatabaseFlags;
+use crate::backend::{
+    BackendDatabase,
+    BackendDatabaseFlags,
+};
+
+/// A store of any kind, whose database can be managed as a whole through `Rkv`.
+pub trait Store {
+    type Database: BackendDatabase;
+
+    fn database(&self) -> &Self::Database;
+}
 
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Options<F> {
@@ -52,3 +62,16 @@ impl CloseOptions {
         }
     }
 }
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct CopyOptions {
+    pub compact: bool,
+}
+
+impl CopyOptions {
+    pub fn compact() -> CopyOptions {
+        CopyOptions {
+            compact: true,
+        }
+    }
+}
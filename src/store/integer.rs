@@ -8,12 +8,19 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    ops::RangeBounds,
+};
 
 use crate::{
     backend::{
         BackendDatabase,
+        BackendIter,
+        BackendPositionedCursor,
+        BackendRoCursor,
         BackendRwTransaction,
+        RangeIter,
     },
     error::StoreError,
     readwrite::{
@@ -22,10 +29,15 @@ use crate::{
     },
     store::{
         keys::{
+            DecodableKey,
             Key,
             PrimitiveInt,
         },
-        single::SingleStore,
+        single::{
+            self,
+            SingleStore,
+        },
+        Store,
     },
     value::Value,
 };
@@ -38,6 +50,22 @@ pub struct IntegerStore<D, K> {
     phantom: PhantomData<K>,
 }
 
+/// Iterates over the key/value pairs of an integer store, with the keys decoded back
+/// into integers.
+pub struct Iter<'i, K, I> {
+    iter: I,
+    phantom: PhantomData<(&'i (), K)>,
+}
+
+impl<'i, K, I> Iter<'i, K, I> {
+    pub(crate) fn new(iter: I) -> Iter<'i, K, I> {
+        Iter {
+            iter,
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl<D, K> IntegerStore<D, K>
 where
     D: BackendDatabase,
@@ -57,6 +85,60 @@ where
         self.inner.get(reader, Key::new(&k)?)
     }
 
+    pub fn iter_start<'r, R, I, C>(&self, reader: &'r R) -> Result<Iter<'r, K, single::Iter<'r, I>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: DecodableKey,
+    {
+        self.inner.iter_start(reader).map(Iter::new)
+    }
+
+    pub fn iter_from<'r, R, I, C>(&self, reader: &'r R, k: K) -> Result<Iter<'r, K, single::Iter<'r, I>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: DecodableKey + 'r,
+    {
+        self.inner.iter_from(reader, Key::new(&k)?).map(Iter::new)
+    }
+
+    pub fn iter_range<'r, R, C, B>(
+        &self,
+        reader: &'r R,
+        range: B,
+    ) -> Result<Iter<'r, K, single::Iter<'r, RangeIter<'r, C>>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        B: RangeBounds<K>,
+        K: DecodableKey,
+    {
+        let range = (Key::new_bound(range.start_bound())?, Key::new_bound(range.end_bound())?);
+        self.inner.iter_range(reader, range).map(Iter::new)
+    }
+
+    /// Provides a cursor to the key/value pairs whose keys have the same `bits` most
+    /// significant bits as `prefix`, in the order of their keys.
+    /// The keys aren't sorted byte by byte on little-endian platforms, so unlike with
+    /// other stores, prefixes are made of bits rather than bytes.
+    pub fn iter_prefix<'r, R, C>(
+        &self,
+        reader: &'r R,
+        prefix: K,
+        bits: u32,
+    ) -> Result<Iter<'r, K, single::Iter<'r, RangeIter<'r, C>>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: DecodableKey,
+    {
+        let (start, end) = Key::prefix_bounds(&prefix, bits)?;
+        self.inner.iter_range(reader, start..=end).map(Iter::new)
+    }
+
     pub fn put<T>(&self, writer: &mut Writer<T>, k: K, v: &Value) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
@@ -71,6 +153,15 @@ where
         self.inner.delete(writer, Key::new(&k)?)
     }
 
+    pub fn delete_range<T, B>(&self, writer: &mut Writer<T>, range: B) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        B: RangeBounds<K>,
+    {
+        let range = (Key::new_bound(range.start_bound())?, Key::new_bound(range.end_bound())?);
+        self.inner.delete_range(writer, range)
+    }
+
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
@@ -79,6 +170,39 @@ where
     }
 }
 
+impl<D, K> Store for IntegerStore<D, K>
+where
+    D: BackendDatabase,
+    K: PrimitiveInt,
+{
+    type Database = D;
+
+    fn database(&self) -> &D {
+        self.inner.database()
+    }
+}
+
+impl<'i, K, I> Iterator for Iter<'i, K, I>
+where
+    K: PrimitiveInt + DecodableKey,
+    I: Iterator<Item = Result<(&'i [u8], Value<'i>), StoreError>>,
+{
+    type Item = Result<(K, Value<'i>), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            None => None,
+            Some(Ok((key, val))) => {
+                match K::from_key_bytes(key) {
+                    Ok(key) => Some(Ok((key, val))),
+                    Err(err) => Some(Err(err.into())),
+                }
+            },
+            Some(Err(err)) => Some(Err(err)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
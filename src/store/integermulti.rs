@@ -8,14 +8,19 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    ops::RangeBounds,
+};
 
 use crate::{
     backend::{
         BackendDatabase,
         BackendIter,
+        BackendPositionedCursor,
         BackendRoCursor,
         BackendRwTransaction,
+        RangeIter,
     },
     error::StoreError,
     readwrite::{
@@ -23,7 +28,9 @@ use crate::{
         Writer,
     },
     store::{
+        integer,
         keys::{
+            DecodableKey,
             Key,
             PrimitiveInt,
         },
@@ -31,6 +38,7 @@ use crate::{
             Iter,
             MultiStore,
         },
+        Store,
     },
     value::Value,
 };
@@ -65,6 +73,62 @@ where
         self.inner.get(reader, Key::new(&k)?)
     }
 
+    /// Provides a cursor to all of the key/value pairs, with the keys decoded back into
+    /// integers
+    pub fn iter_start<'r, R, I, C>(&self, reader: &'r R) -> Result<integer::Iter<'r, K, Iter<'r, I>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: DecodableKey,
+    {
+        self.inner.iter_start(reader).map(integer::Iter::new)
+    }
+
+    pub fn iter_from<'r, R, I, C>(&self, reader: &'r R, k: K) -> Result<integer::Iter<'r, K, Iter<'r, I>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: DecodableKey + 'r,
+    {
+        self.inner.iter_from(reader, Key::new(&k)?).map(integer::Iter::new)
+    }
+
+    pub fn iter_range<'r, R, C, B>(
+        &self,
+        reader: &'r R,
+        range: B,
+    ) -> Result<integer::Iter<'r, K, Iter<'r, RangeIter<'r, C>>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        B: RangeBounds<K>,
+        K: DecodableKey,
+    {
+        let range = (Key::new_bound(range.start_bound())?, Key::new_bound(range.end_bound())?);
+        self.inner.iter_range(reader, range).map(integer::Iter::new)
+    }
+
+    /// Provides a cursor to the key/value pairs whose keys have the same `bits` most
+    /// significant bits as `prefix`, in the order of their keys and values.
+    /// The keys aren't sorted byte by byte on little-endian platforms, so unlike with
+    /// other stores, prefixes are made of bits rather than bytes.
+    pub fn iter_prefix<'r, R, C>(
+        &self,
+        reader: &'r R,
+        prefix: K,
+        bits: u32,
+    ) -> Result<integer::Iter<'r, K, Iter<'r, RangeIter<'r, C>>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: DecodableKey,
+    {
+        let (start, end) = Key::prefix_bounds(&prefix, bits)?;
+        self.inner.iter_range(reader, start..=end).map(integer::Iter::new)
+    }
+
     pub fn get_first<'r, R>(&self, reader: &'r R, k: K) -> Result<Option<Value<'r>>, StoreError>
     where
         R: Readable<'r, Database = D>,
@@ -100,6 +164,15 @@ where
         self.inner.delete(writer, Key::new(&k)?, v)
     }
 
+    pub fn delete_range<T, B>(&self, writer: &mut Writer<T>, range: B) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        B: RangeBounds<K>,
+    {
+        let range = (Key::new_bound(range.start_bound())?, Key::new_bound(range.end_bound())?);
+        self.inner.delete_range(writer, range)
+    }
+
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
@@ -108,6 +181,18 @@ where
     }
 }
 
+impl<D, K> Store for MultiIntegerStore<D, K>
+where
+    D: BackendDatabase,
+    K: PrimitiveInt,
+{
+    type Database = D;
+
+    fn database(&self) -> &D {
+        self.inner.database()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
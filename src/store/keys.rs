@@ -11,7 +11,11 @@
 mod encodables;
 mod primitives;
 
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    mem,
+    ops::Bound,
+};
 
 use crate::error::DataError;
 
@@ -25,7 +29,7 @@ pub(crate) struct Key<K> {
 
 impl<K> AsRef<[u8]> for Key<K>
 where
-    K: EncodableKey,
+    K: PrimitiveInt,
 {
     fn as_ref(&self) -> &[u8] {
         self.bytes.as_ref()
@@ -34,13 +38,54 @@ where
 
 impl<K> Key<K>
 where
-    K: EncodableKey,
+    K: PrimitiveInt,
 {
     #[allow(clippy::new_ret_no_self)]
     pub fn new(k: &K) -> Result<Key<K>, DataError> {
         Ok(Key {
-            bytes: k.to_bytes()?,
+            bytes: k.to_key_bytes()?,
             phantom: PhantomData,
         })
     }
+
+    pub fn new_bound(bound: Bound<&K>) -> Result<Bound<Key<K>>, DataError> {
+        Ok(match bound {
+            Bound::Included(k) => Bound::Included(Key::new(k)?),
+            Bound::Excluded(k) => Bound::Excluded(Key::new(k)?),
+            Bound::Unbounded => Bound::Unbounded,
+        })
+    }
+
+    /// The first and the last keys whose `bits` most significant bits are the same
+    /// as those of `prefix`, which delimit a contiguous range of the store.
+    pub fn prefix_bounds(prefix: &K, bits: u32) -> Result<(Key<K>, Key<K>), DataError> {
+        // Integer keys are sorted as native endian integers, and the others byte by
+        // byte, most significant first. Widened integers have extra leading bytes.
+        let native = K::INTEGER_KEY && cfg!(target_endian = "little");
+        let mut start = prefix.to_key_bytes()?;
+        if native {
+            start.reverse();
+        }
+        let width = mem::size_of::<K>().min(start.len());
+        let kept = (start.len() - width) * 8 + bits.min(width as u32 * 8) as usize;
+
+        let mut end = start.clone();
+        for (i, (first, last)) in start.iter_mut().zip(end.iter_mut()).enumerate() {
+            let mask = (0xff00u16 >> kept.saturating_sub(i * 8).min(8)) as u8;
+            *first &= mask;
+            *last |= !mask;
+        }
+        if native {
+            start.reverse();
+            end.reverse();
+        }
+        Ok((Key::from_bytes(start), Key::from_bytes(end)))
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Key<K> {
+        Key {
+            bytes,
+            phantom: PhantomData,
+        }
+    }
 }
@@ -8,8 +8,15 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use bincode::serialize;
-use serde::Serialize;
+use bincode::{
+    serialize,
+    DefaultOptions,
+    Options,
+};
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
 
 use crate::error::DataError;
 
@@ -25,3 +32,18 @@ where
         serialize(self).map_err(|e| e.into())
     }
 }
+
+pub trait DecodableKey: Sized {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DataError>;
+}
+
+impl<T> DecodableKey for T
+where
+    T: DeserializeOwned,
+{
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DataError> {
+        // The same options as `serialize`, except that the key has to be all there is.
+        let options = DefaultOptions::new().with_fixint_encoding().reject_trailing_bytes();
+        options.deserialize(bytes).map_err(|_| DataError::InvalidKey)
+    }
+}
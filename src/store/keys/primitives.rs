@@ -8,8 +8,134 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use crate::store::keys::EncodableKey;
+use std::convert::{
+    TryFrom,
+    TryInto,
+};
 
-pub trait PrimitiveInt: EncodableKey {}
+use crate::{
+    error::DataError,
+    store::keys::{
+        DecodableKey,
+        EncodableKey,
+    },
+};
 
-impl PrimitiveInt for u32 {}
+/// An integer that can key an `IntegerStore` or a `MultiIntegerStore`.
+///
+/// By default, keys are encoded like any other `EncodableKey`, and stored in an
+/// `INTEGER_KEY` database, which sorts them as native endian unsigned integers of
+/// the same size. The implementations for the primitive integers override this to
+/// keep the keys sorted by value.
+pub trait PrimitiveInt: EncodableKey {
+    /// Whether the store is opened with `INTEGER_KEY`. When it isn't, the keys are
+    /// sorted byte by byte, so their encoding has to preserve their order.
+    const INTEGER_KEY: bool = true;
+
+    fn to_key_bytes(&self) -> Result<Vec<u8>, DataError> {
+        self.to_bytes()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Result<Self, DataError>
+    where
+        Self: DecodableKey,
+    {
+        Self::from_bytes(bytes)
+    }
+}
+
+// LMDB only knows how to sort unsigned ints and size_t's in native byte order.
+macro_rules! native_int {
+    ($int:ty) => {
+        impl PrimitiveInt for $int {
+            fn to_key_bytes(&self) -> Result<Vec<u8>, DataError> {
+                Ok(self.to_ne_bytes().to_vec())
+            }
+
+            fn from_key_bytes(bytes: &[u8]) -> Result<Self, DataError> {
+                bytes.try_into().map(<$int>::from_ne_bytes).map_err(|_| DataError::InvalidKey)
+            }
+        }
+    };
+}
+
+// Smaller unsigned integers are widened to an unsigned int.
+macro_rules! widened_int {
+    ($int:ty) => {
+        impl PrimitiveInt for $int {
+            fn to_key_bytes(&self) -> Result<Vec<u8>, DataError> {
+                u32::from(*self).to_key_bytes()
+            }
+
+            fn from_key_bytes(bytes: &[u8]) -> Result<Self, DataError> {
+                <$int>::try_from(u32::from_key_bytes(bytes)?).map_err(|_| DataError::InvalidKey)
+            }
+        }
+    };
+}
+
+// Integers that LMDB can't sort go big endian into a database sorted byte by byte,
+// with the sign bit of the signed ones flipped.
+macro_rules! big_endian_int {
+    ($int:ty, $unsigned:ty) => {
+        impl PrimitiveInt for $int {
+            const INTEGER_KEY: bool = false;
+
+            fn to_key_bytes(&self) -> Result<Vec<u8>, DataError> {
+                Ok((*self as $unsigned ^ <$int>::MIN as $unsigned).to_be_bytes().to_vec())
+            }
+
+            fn from_key_bytes(bytes: &[u8]) -> Result<Self, DataError> {
+                let bytes = bytes.try_into().map_err(|_| DataError::InvalidKey)?;
+                Ok((<$unsigned>::from_be_bytes(bytes) ^ <$int>::MIN as $unsigned) as $int)
+            }
+        }
+    };
+}
+
+native_int!(u32);
+native_int!(usize);
+widened_int!(u8);
+widened_int!(u16);
+big_endian_int!(i32, u32);
+big_endian_int!(i64, u64);
+
+// A u64 is only a size_t on 64-bit targets.
+#[cfg(target_pointer_width = "64")]
+native_int!(u64);
+#[cfg(not(target_pointer_width = "64"))]
+big_endian_int!(u64, u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fmt::Debug;
+
+    // Checks that the keys decode back into the same integers, and that they sort
+    // byte by byte like the integers do when they aren't integer keys.
+    fn check_keys<T>(mut ints: Vec<T>)
+    where
+        T: PrimitiveInt + DecodableKey + Ord + Debug,
+    {
+        ints.sort();
+        let keys: Vec<_> = ints.iter().map(|int| int.to_key_bytes().expect("encoded")).collect();
+        for (int, key) in ints.iter().zip(keys.iter()) {
+            assert_eq!(&T::from_key_bytes(key).expect("decoded"), int);
+        }
+        if !T::INTEGER_KEY {
+            let mut sorted = keys.clone();
+            sorted.sort();
+            assert_eq!(sorted, keys);
+        }
+    }
+
+    #[test]
+    fn test_big_endian_keys() {
+        check_keys(vec![-1i32, 1, i32::MAX, 0, i32::MIN, -256, 256]);
+        check_keys(vec![-1i64, 1, i64::MAX, 0, i64::MIN, -(1 << 32), 1 << 32]);
+        check_keys(vec![1u64 << 32, 1, u64::MAX, 0, 256, 1 << 56]);
+        assert_eq!(u64::INTEGER_KEY, cfg!(target_pointer_width = "64"));
+        assert!(matches!(i64::from_key_bytes(&[0; 4]), Err(DataError::InvalidKey)));
+    }
+}
@@ -8,15 +8,21 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    ops::RangeBounds,
+};
 
 use crate::{
     backend::{
         BackendDatabase,
         BackendFlags,
         BackendIter,
+        BackendPositionedCursor,
         BackendRoCursor,
         BackendRwTransaction,
+        RangeIter,
+        RevIter,
     },
     error::StoreError,
     helpers::read_transform,
@@ -24,6 +30,7 @@ use crate::{
         Readable,
         Writer,
     },
+    store::Store,
     value::Value,
 };
 
@@ -67,6 +74,110 @@ where
         })
     }
 
+    /// Provides a cursor to all of the key/value pairs, with all of the values of
+    /// each key
+    pub fn iter_start<'r, R, I, C>(&self, reader: &'r R) -> Result<Iter<'r, I>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = cursor.into_iter();
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Like `iter_start`, but starting from the first value of this key, or from the
+    /// first key that comes after it when the key isn't there
+    pub fn iter_from<'r, R, I, C, K>(&self, reader: &'r R, k: K) -> Result<Iter<'r, I>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: AsRef<[u8]> + 'r,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = cursor.into_iter_from(k);
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Provides a cursor to the key/value pairs whose keys are within the range,
+    /// with all of the values of each key
+    pub fn iter_range<'r, R, C, K, B>(&self, reader: &'r R, range: B) -> Result<Iter<'r, RangeIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: AsRef<[u8]> + ?Sized,
+        B: RangeBounds<K>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RangeIter::new(cursor, range);
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Provides a cursor to the key/value pairs whose keys start with the prefix,
+    /// which requires the keys to be sorted byte by byte, as they are by default.
+    /// Otherwise, this fails with `StoreError::PrefixNotSupported`
+    pub fn iter_prefix<'r, R, C, K>(&self, reader: &'r R, prefix: K) -> Result<Iter<'r, RangeIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: AsRef<[u8]>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RangeIter::new_prefix(cursor, prefix)?;
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Provides a cursor to all of the key/value pairs, from the last key to the first
+    /// and from the last value of each key to its first
+    pub fn iter_rev<'r, R, C>(&self, reader: &'r R) -> Result<Iter<'r, RevIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RevIter::new(cursor);
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Like `iter_rev`, but starting from the last value of this key, or from the
+    /// last key that comes before it when the key isn't there
+    pub fn iter_rev_from<'r, R, C, K>(&self, reader: &'r R, k: K) -> Result<Iter<'r, RevIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: AsRef<[u8]>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RevIter::new_from(cursor, k);
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
     /// Provides the first value that matches this key
     pub fn get_first<'r, R, K>(&self, reader: &'r R, k: K) -> Result<Option<Value<'r>>, StoreError>
     where
@@ -111,6 +222,28 @@ where
         writer.delete(&self.db, &k, Some(&v.to_bytes()?))
     }
 
+    /// Deletes all of the values of the keys within the range, and returns how many
+    /// there were
+    pub fn delete_range<T, K, B>(&self, writer: &mut Writer<T>, range: B) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]> + ?Sized,
+        B: RangeBounds<K>,
+    {
+        writer.delete_range(&self.db, range)
+    }
+
+    /// Deletes all of the values of the keys that start with the prefix, which requires
+    /// the keys to be sorted byte by byte, and returns how many there were. Otherwise,
+    /// this fails with `StoreError::PrefixNotSupported`, without deleting anything
+    pub fn delete_prefix<T, K>(&self, writer: &mut Writer<T>, prefix: K) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        writer.delete_prefix(&self.db, prefix.as_ref())
+    }
+
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
@@ -119,6 +252,17 @@ where
     }
 }
 
+impl<D> Store for MultiStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> &D {
+        &self.db
+    }
+}
+
 impl<'i, I> Iterator for Iter<'i, I>
 where
     I: BackendIter<'i>,
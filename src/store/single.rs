@@ -8,15 +8,21 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    ops::RangeBounds,
+};
 
 use crate::{
     backend::{
         BackendDatabase,
         BackendFlags,
         BackendIter,
+        BackendPositionedCursor,
         BackendRoCursor,
         BackendRwTransaction,
+        RangeIter,
+        RevIter,
     },
     error::StoreError,
     helpers::read_transform,
@@ -24,6 +30,7 @@ use crate::{
         Readable,
         Writer,
     },
+    store::Store,
     value::Value,
 };
 
@@ -115,6 +122,83 @@ where
         })
     }
 
+    pub fn iter_range<'r, R, C, K, B>(&self, reader: &'r R, range: B) -> Result<Iter<'r, RangeIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: AsRef<[u8]> + ?Sized,
+        B: RangeBounds<K>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RangeIter::new(cursor, range);
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn iter_prefix<'r, R, C, K>(&self, reader: &'r R, prefix: K) -> Result<Iter<'r, RangeIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: AsRef<[u8]>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RangeIter::new_prefix(cursor, prefix)?;
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn iter_rev<'r, R, C>(&self, reader: &'r R) -> Result<Iter<'r, RevIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RevIter::new(cursor);
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn iter_rev_from<'r, R, C, K>(&self, reader: &'r R, k: K) -> Result<Iter<'r, RevIter<'r, C>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        C: BackendPositionedCursor<'r>,
+        K: AsRef<[u8]>,
+    {
+        let cursor = reader.open_ro_cursor(&self.db)?;
+        let iter = RevIter::new_from(cursor, k);
+
+        Ok(Iter {
+            iter,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn delete_range<T, K, B>(&self, writer: &mut Writer<T>, range: B) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]> + ?Sized,
+        B: RangeBounds<K>,
+    {
+        writer.delete_range(&self.db, range)
+    }
+
+    pub fn delete_prefix<T, K>(&self, writer: &mut Writer<T>, prefix: K) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        writer.delete_prefix(&self.db, prefix.as_ref())
+    }
+
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
     where
         D: BackendDatabase,
@@ -124,6 +208,17 @@ where
     }
 }
 
+impl<D> Store for SingleStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> &D {
+        &self.db
+    }
+}
+
 impl<'i, I> Iterator for Iter<'i, I>
 where
     I: BackendIter<'i>,
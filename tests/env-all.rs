@@ -14,14 +14,33 @@ use tempfile::Builder;
 
 use rkv::{
     backend::{
+        BackendDatabaseFlags,
+        BackendEnvironment,
+        BackendEnvironmentBuilder,
+        BackendFlags,
+        BackendIter,
+        BackendRoCursor,
+        BackendRoCursorTransaction,
+        BackendRwTransaction,
+        DatabaseFlags,
         Lmdb,
+        LmdbDatabaseFlags,
         SafeMode,
+        SafeModeDatabaseFlags,
     },
     Rkv,
     StoreOptions,
     Value,
 };
 
+fn database_flags<F: BackendDatabaseFlags>(flags: Vec<DatabaseFlags>) -> F {
+    let mut result = F::empty();
+    for flag in flags {
+        result.set(flag, true);
+    }
+    result
+}
+
 #[test]
 fn test_open_safe_same_dir_as_lmdb() {
     let root = Builder::new().prefix("test_open_safe_same_dir_as_lmdb").tempdir().expect("tempdir");
@@ -185,3 +204,106 @@ fn test_open_lmdb_same_dir_as_safe() {
         assert_eq!(sk.get(&reader, "baz").expect("read"), Some(Value::Str("héllo, yöu")));
     }
 }
+
+/// Writes raw key/value pairs into an LMDB database created with the given flags,
+/// and returns them in the order in which a cursor iterates over them.
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn sorted_entries_lmdb(flags: LmdbDatabaseFlags, entries: &[(Vec<u8>, Vec<u8>)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let root = Builder::new().prefix("test_database_order_lmdb").tempdir().expect("tempdir");
+    let mut builder = Lmdb::new();
+    builder.set_max_dbs(1);
+    let env = builder.open(root.path()).expect("opened");
+    let db = env.create_db(Some("db"), flags).expect("created");
+
+    let mut txn = env.begin_rw_txn().expect("began");
+    for (key, value) in entries {
+        txn.put(&db, key, value, BackendFlags::empty()).expect("wrote");
+    }
+    txn.commit().expect("committed");
+
+    let txn = env.begin_ro_txn().expect("began");
+    let mut iter = txn.open_ro_cursor(&db).expect("opened").into_iter();
+    let mut sorted = vec![];
+    while let Some(result) = iter.next() {
+        let (key, value) = result.expect("read");
+        sorted.push((key.to_vec(), value.to_vec()));
+    }
+    sorted
+}
+
+/// Same as `sorted_entries_lmdb`, with a SafeMode database.
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn sorted_entries_safe(flags: SafeModeDatabaseFlags, entries: &[(Vec<u8>, Vec<u8>)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let root = Builder::new().prefix("test_database_order_safe").tempdir().expect("tempdir");
+    let mut builder = SafeMode::new();
+    builder.set_max_dbs(1);
+    let env = builder.open(root.path()).expect("opened");
+    let db = env.create_db(Some("db"), flags).expect("created");
+
+    let mut txn = env.begin_rw_txn().expect("began");
+    for (key, value) in entries {
+        txn.put(&db, key, value, BackendFlags::empty()).expect("wrote");
+    }
+    txn.commit().expect("committed");
+
+    let txn = env.begin_ro_txn().expect("began");
+    let mut iter = txn.open_ro_cursor(&db).expect("opened").into_iter();
+    let mut sorted = vec![];
+    while let Some(result) = iter.next() {
+        let (key, value) = result.expect("read");
+        sorted.push((key.to_vec(), value.to_vec()));
+    }
+    sorted
+}
+
+/// Both backends must iterate in the same order over databases with the given flags.
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn assert_same_order(entries: &[(Vec<u8>, Vec<u8>)], flags: fn() -> Vec<DatabaseFlags>) {
+    let lmdb_flags: LmdbDatabaseFlags = database_flags(flags());
+    let lmdb = sorted_entries_lmdb(lmdb_flags, entries);
+    let safe = sorted_entries_safe(database_flags(flags()), entries);
+    assert_eq!(lmdb, safe, "different orders with {:?}", lmdb_flags);
+}
+
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_database_order_lmdb_and_safe() {
+    use DatabaseFlags::*;
+
+    let strings: Vec<(Vec<u8>, Vec<u8>)> = vec![
+        (b"ba".to_vec(), b"ba".to_vec()),
+        (b"ab".to_vec(), b"ab".to_vec()),
+        (b"b".to_vec(), b"b".to_vec()),
+        (b"a".to_vec(), b"b".to_vec()),
+        (b"a".to_vec(), b"ba".to_vec()),
+        (b"a".to_vec(), b"ab".to_vec()),
+        (b"aab".to_vec(), b"cab".to_vec()),
+    ];
+    let fixed: Vec<(Vec<u8>, Vec<u8>)> = vec![
+        (b"a".to_vec(), b"zb".to_vec()),
+        (b"a".to_vec(), b"az".to_vec()),
+        (b"b".to_vec(), b"abc".to_vec()),
+        (b"b".to_vec(), b"aaa".to_vec()),
+    ];
+    let integers: Vec<u32> = vec![256, 1, 65536, 2, u32::MAX, 0, 257];
+    let u32s: Vec<_> = integers.iter().map(|i| i.to_ne_bytes().to_vec()).collect();
+    let u64s: Vec<_> = integers.iter().map(|&i| (u64::from(i) << 16).to_ne_bytes().to_vec()).collect();
+    let u32_keys: Vec<_> = u32s.iter().map(|i| (i.clone(), b"v".to_vec())).collect();
+    let u64_keys: Vec<_> = u64s.iter().map(|i| (i.clone(), b"v".to_vec())).collect();
+    let u32_values: Vec<_> = u32s.iter().map(|i| (b"k".to_vec(), i.clone())).collect();
+    let u64_values: Vec<_> = u64s.iter().map(|i| (b"k".to_vec(), i.clone())).collect();
+
+    assert_same_order(&strings, Vec::new);
+    assert_same_order(&strings, || vec![REVERSE_KEY]);
+    assert_same_order(&u32_keys, || vec![INTEGER_KEY]);
+    assert_same_order(&u64_keys, || vec![INTEGER_KEY]);
+    assert_same_order(&strings, || vec![DUP_SORT]);
+    assert_same_order(&strings, || vec![DUP_SORT, REVERSE_DUP]);
+    assert_same_order(&strings, || vec![DUP_SORT, REVERSE_KEY, REVERSE_DUP]);
+    assert_same_order(&fixed, || vec![DUP_SORT, DUP_FIXED]);
+    assert_same_order(&fixed, || vec![DUP_SORT, DUP_FIXED, REVERSE_DUP]);
+    assert_same_order(&u32_values, || vec![DUP_SORT, INTEGER_DUP]);
+    assert_same_order(&u64_values, || vec![DUP_SORT, INTEGER_DUP]);
+    assert_same_order(&u32_values, || vec![DUP_SORT, DUP_FIXED, INTEGER_DUP]);
+    assert_same_order(&u64_values, || vec![DUP_SORT, DUP_FIXED, INTEGER_DUP]);
+}
@@ -13,8 +13,19 @@
 #![allow(clippy::complexity)]
 
 use std::{
+    env,
     fs,
+    io::{
+        self,
+        BufRead,
+    },
+    ops::Bound,
     path::Path,
+    process::{
+        self,
+        Command,
+        Stdio,
+    },
     str,
     sync::{
         Arc,
@@ -31,15 +42,35 @@ use tempfile::Builder;
 
 use rkv::{
     backend::{
+        BackendDatabaseFlags,
+        BackendEnvironment,
         BackendEnvironmentBuilder,
+        BackendFlags,
         BackendInfo,
+        BackendIter,
+        BackendPositionedCursor,
+        BackendRoCursor,
+        BackendRoCursorTransaction,
+        BackendRwCursor,
+        BackendRwCursorTransaction,
+        BackendRwTransaction,
         BackendStat,
+        BackendWriteFlags,
+        DatabaseFlags,
         Lmdb,
         LmdbDatabase,
+        LmdbDatabaseFlags,
         LmdbEnvironment,
         LmdbRwTransaction,
+        WriteFlags,
     },
+    CloseOptions,
+    CopyOptions,
+    DecodableKey,
     EnvironmentFlags,
+    IntegerStore,
+    MapGrowth,
+    PrimitiveInt,
     Rkv,
     SingleStore,
     StoreError,
@@ -131,6 +162,28 @@ fn test_open_from_builder_with_no_subdir_1() {
     }
 }
 
+#[test]
+fn test_close_and_delete_with_no_subdir() {
+    let root = Builder::new().prefix("test_close_and_delete_with_no_subdir").tempdir().expect("tempdir");
+    let datamdb = root.path().join("env.mdb");
+    fs::write(&datamdb, "").expect("data file created");
+
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+    builder.set_max_dbs(2);
+    let k = Rkv::from_builder(&datamdb, builder).expect("rkv");
+    check_rkv(&k);
+
+    let mut names: Vec<_> = fs::read_dir(root.path()).expect("listed").map(|e| e.expect("entry").file_name()).collect();
+    names.sort();
+    assert_eq!(names, vec!["env.mdb", "env.mdb-lock"]);
+
+    // Both the data file and its lock file are deleted, but not the folder they're in.
+    k.close(CloseOptions::delete_files_on_disk()).expect("closed");
+    assert!(root.path().is_dir());
+    assert_eq!(fs::read_dir(root.path()).expect("listed").count(), 0);
+}
+
 #[test]
 #[should_panic(expected = "rkv: UnsuitableEnvironmentPath")]
 fn test_open_from_builder_with_no_subdir_2() {
@@ -852,6 +905,40 @@ fn test_stat() {
     assert_eq!(k.stat().expect("stat").leaf_pages(), 1);
 }
 
+#[test]
+fn test_stat_db() {
+    let root = Builder::new().prefix("test_stat_db").tempdir().expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let ik = k.open_integer::<&str, u32>("ik", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let empty = k.open_single("empty", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str("bar")).expect("wrote");
+    sk.put(&mut writer, "big", &Value::Str(&"x".repeat(10000))).expect("wrote");
+    ik.put(&mut writer, 1, &Value::I64(1)).expect("wrote");
+    mk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    mk.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    mk.put(&mut writer, "bar", &Value::I64(3)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Each store only accounts for its own entries, while the environment counts the
+    // stores themselves.
+    let stat = k.stat_db(&sk).expect("stat");
+    assert_eq!(stat.entries(), 2);
+    assert_eq!(stat.depth(), 1);
+    assert_eq!(stat.leaf_pages(), 1);
+    assert!(stat.overflow_pages() > 0);
+    assert_eq!(k.stat_db(&ik).expect("stat").entries(), 1);
+    assert_eq!(k.stat_db(&mk).expect("stat").entries(), 3);
+    assert_eq!(k.stat_db(&empty).expect("stat").entries(), 0);
+    assert_eq!(k.stat_db(&empty).expect("stat").depth(), 0);
+    assert_eq!(k.stat().expect("stat").entries(), 4);
+}
+
 #[test]
 fn test_info() {
     let root = Builder::new().prefix("test_info").tempdir().expect("tempdir");
@@ -883,6 +970,86 @@ fn test_info() {
     assert_eq!(info.num_readers(), 1);
 }
 
+// Spawns a copy of this test binary that opens the environment at `path`, and exits
+// while reading, without releasing its slot in the reader table. Returns the number of
+// slots the copy found in the table once it opened the environment.
+fn exit_while_reading(path: &Path, reader_check_on_open: bool) -> i32 {
+    let mut command = Command::new(env::current_exe().expect("test binary"));
+    command.args(["--exact", "exit_while_reading_helper", "--ignored"]).env("RKV_EXIT_WHILE_READING", path);
+    if reader_check_on_open {
+        command.env("RKV_READER_CHECK_ON_OPEN", "1");
+    }
+    command.stdout(Stdio::null()).status().expect("spawned").code().expect("exited")
+}
+
+#[test]
+#[ignore]
+fn exit_while_reading_helper() {
+    if let Some(path) = env::var_os("RKV_EXIT_WHILE_READING") {
+        let mut builder = Rkv::environment_builder::<Lmdb>();
+        builder.set_reader_check_on_open(env::var_os("RKV_READER_CHECK_ON_OPEN").is_some());
+        let k = Rkv::from_builder(Path::new(&path), builder).expect("rkv");
+        let readers = k.readers().expect("readers").len();
+        let _reader = k.read().expect("reader");
+        process::exit(readers as i32);
+    }
+}
+
+#[test]
+fn test_readers() {
+    let root = Builder::new().prefix("test_readers").tempdir().expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let _ = k.open_single("sk", StoreOptions::create()).expect("opened");
+    assert_eq!(k.readers().expect("readers"), vec![]);
+
+    let reader = k.read().expect("reader");
+    let readers = k.readers().expect("readers");
+    assert_eq!(readers.len(), 1);
+    assert_eq!(readers[0].pid, process::id());
+    assert_eq!(readers[0].txnid, Some(k.info().expect("info").last_txnid()));
+
+    // The thread keeps its slot once done reading, but the slot no longer pins a transaction.
+    reader.abort();
+    let readers = k.readers().expect("readers");
+    assert!(readers.iter().all(|slot| slot.pid == process::id() && slot.txnid.is_none()));
+    assert_eq!(k.reader_check().expect("checked"), 0);
+}
+
+#[test]
+fn test_reader_check() {
+    let root = Builder::new().prefix("test_reader_check").tempdir().expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let _ = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    assert_eq!(exit_while_reading(root.path(), false), 0);
+    let readers = k.readers().expect("readers");
+    assert_eq!(readers.len(), 1);
+    assert_ne!(readers[0].pid, process::id());
+    assert!(readers[0].txnid.is_some());
+
+    assert_eq!(k.reader_check().expect("checked"), 1);
+    assert_eq!(k.readers().expect("readers"), vec![]);
+    assert_eq!(k.reader_check().expect("checked"), 0);
+}
+
+#[test]
+fn test_reader_check_on_open() {
+    let root = Builder::new().prefix("test_reader_check_on_open").tempdir().expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    // The environment stays open here, otherwise the next process to open it would
+    // reset the reader table.
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    assert_eq!(exit_while_reading(root.path(), false), 0);
+    assert_eq!(exit_while_reading(root.path(), false), 1);
+    assert_eq!(exit_while_reading(root.path(), true), 0);
+    assert_eq!(k.readers().expect("readers").len(), 1);
+}
+
 #[test]
 fn test_load_ratio() {
     let root = Builder::new().prefix("test_load_ratio").tempdir().expect("tempdir");
@@ -933,6 +1100,165 @@ fn test_set_map_size() {
     assert_eq!(k.info().expect("info").map_size(), 2 * DEFAULT_SIZE);
 }
 
+#[test]
+fn test_write_batch_grows_map() {
+    let root = Builder::new().prefix("test_write_batch_grows_map").tempdir().expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_max_dbs(2);
+    builder.set_map_growth(MapGrowth::new(2.0, 8 * DEFAULT_SIZE));
+    let k = Rkv::from_builder(root.path(), builder).expect("rkv");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    // The value doesn't fit in twice the default size along with the metadata, so the
+    // map grows twice, and the transaction is replayed each time.
+    let val = "x".repeat(2 * DEFAULT_SIZE);
+    let mut calls = 0;
+    k.write_batch(|writer| {
+        calls += 1;
+        sk.put(writer, "foo", &Value::Str(&val))
+    })
+    .expect("wrote");
+    assert_eq!(calls, 3);
+    assert_eq!(k.info().expect("info").map_size(), 4 * DEFAULT_SIZE);
+
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::Str(&val)));
+
+    // The map doesn't grow while a transaction is active.
+    let bar = "x".repeat(3 * DEFAULT_SIZE);
+    match k.write_batch(|writer| sk.put(writer, "bar", &Value::Str(&bar))) {
+        Err(StoreError::MapFull) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(k.info().expect("info").map_size(), 4 * DEFAULT_SIZE);
+    reader.abort();
+    k.write_batch(|writer| sk.put(writer, "bar", &Value::Str(&bar))).expect("wrote");
+    assert_eq!(k.info().expect("info").map_size(), 8 * DEFAULT_SIZE);
+
+    // Nor past the maximum size.
+    match k.write_batch(|writer| sk.put(writer, "baz", &Value::Str(&bar))) {
+        Err(StoreError::MapFull) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(k.info().expect("info").map_size(), 8 * DEFAULT_SIZE);
+
+    // Failures other than `MapFull` abort the transaction.
+    match k.write_batch(|writer| {
+        sk.delete(writer, "foo")?;
+        sk.delete(writer, "foo")
+    }) {
+        Err(StoreError::KeyValuePairNotFound) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::Str(&val)));
+}
+
+#[test]
+fn test_write_batch_grows_map_by_load_ratio() {
+    let root = Builder::new().prefix("test_write_batch_grows_map_by_load_ratio").tempdir().expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_max_dbs(2);
+    builder.set_map_growth(MapGrowth {
+        load_ratio: Some(0.5),
+        ..MapGrowth::new(2.0, 8 * DEFAULT_SIZE)
+    });
+    let k = Rkv::from_builder(root.path(), builder).expect("rkv");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    k.write_batch(|writer| sk.put(writer, "foo", &Value::Str(&"x".repeat(DEFAULT_SIZE / 2)))).expect("wrote");
+    assert_eq!(k.info().expect("info").map_size(), DEFAULT_SIZE);
+    assert!(k.load_ratio().expect("ratio").unwrap() > 0.5);
+
+    // The map is more than half full, so it grows before the next transaction.
+    let mut calls = 0;
+    k.write_batch(|writer| {
+        calls += 1;
+        sk.put(writer, "bar", &Value::Str("baz"))
+    })
+    .expect("wrote");
+    assert_eq!(calls, 1);
+    assert_eq!(k.info().expect("info").map_size(), 2 * DEFAULT_SIZE);
+}
+
+#[test]
+fn test_write_batch_without_map_growth() {
+    let root = Builder::new().prefix("test_write_batch_without_map_growth").tempdir().expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let val = "x".repeat(DEFAULT_SIZE);
+    match k.write_batch(|writer| sk.put(writer, "foo", &Value::Str(&val))) {
+        Err(StoreError::MapFull) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(k.info().expect("info").map_size(), DEFAULT_SIZE);
+}
+
+#[test]
+fn test_copy_to_compact() {
+    let root = Builder::new().prefix("test_copy_to_compact").tempdir().expect("tempdir");
+    let copy = Builder::new().prefix("test_copy_to_compact_copy").tempdir().expect("tempdir");
+    let compact = Builder::new().prefix("test_copy_to_compact_compact").tempdir().expect("tempdir");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    for i in 0..100 {
+        sk.put(&mut writer, format!("key{}", i), &Value::Str(&"x".repeat(1000))).expect("wrote");
+    }
+    writer.commit().expect("committed");
+    let mut writer = k.write().expect("writer");
+    sk.clear(&mut writer).expect("cleared");
+    sk.put(&mut writer, "foo", &Value::Str("bar")).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Compacting leaves out the pages freed by clearing the store.
+    k.copy_to(copy.path(), CopyOptions::default()).expect("copied");
+    k.copy_to(compact.path(), CopyOptions::compact()).expect("copied");
+    let size = |dir: &Path| fs::metadata(dir.join("data.mdb")).expect("metadata").len();
+    assert!(size(compact.path()) < size(copy.path()) / 10);
+
+    let k = Rkv::new::<Lmdb>(compact.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::Str("bar")));
+    assert_eq!(sk.get(&reader, "key0").expect("read"), None);
+}
+
+#[test]
+fn test_copy_to_with_no_subdir() {
+    let root = Builder::new().prefix("test_copy_to_with_no_subdir").tempdir().expect("tempdir");
+    let datamdb = root.path().join("env.mdb");
+    fs::write(&datamdb, "").expect("data file created");
+
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+    builder.set_max_dbs(2);
+    let k = Rkv::from_builder(&datamdb, builder).expect("rkv");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str("bar")).expect("wrote");
+    writer.commit().expect("committed");
+
+    // The copy is a data file of its own, without a lock file.
+    let copymdb = root.path().join("copy.mdb");
+    k.copy_to(&copymdb, CopyOptions::compact()).expect("copied");
+    assert!(copymdb.is_file());
+    assert!(!root.path().join("copy.mdb-lock").exists());
+
+    let k = Rkv::from_builder(&copymdb, builder).expect("rkv");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::Str("bar")));
+}
+
 #[test]
 fn test_iter() {
     let root = Builder::new().prefix("test_iter").tempdir().expect("tempdir");
@@ -1218,11 +1544,10 @@ fn test_store_multiple_thread() {
         read_handles.push(thread::spawn(move || {
             let rkv = rkv_arc.read().expect("rkv");
             let reader = rkv.read().expect("reader");
-            let value = match store.get(&reader, i.to_string()) {
-                Ok(Some(Value::U64(value))) => value,
-                Ok(Some(_)) => panic!("value type unexpected"),
-                Ok(None) => panic!("value not found"),
-                Err(err) => panic!(err),
+            let value = match store.get(&reader, i.to_string()).expect("read") {
+                Some(Value::U64(value)) => value,
+                Some(_) => panic!("value type unexpected"),
+                None => panic!("value not found"),
             };
             assert_eq!(value, i);
             value
@@ -1317,3 +1642,758 @@ fn test_use_value_as_key() {
         writer.commit().expect("committed");
     }
 }
+
+#[test]
+fn test_discard_if_corrupted() {
+    let root = Builder::new().prefix("test_discard_if_corrupted").tempdir().expect("tempdir");
+    let data = root.path().join("data.mdb");
+    let discarded = root.path().join("data.mdb.corrupted");
+    {
+        let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+        let sk = k.open_single(None, StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        writer.commit().expect("committed");
+    }
+
+    // Without the flag, a corrupted data file can't be opened.
+    let mut bytes = fs::read(&data).expect("read");
+    fs::write(&data, "bogus").expect("corrupted");
+    Rkv::new::<Lmdb>(root.path()).expect_err("not opened");
+
+    // With it, the data file is moved aside and replaced with an empty one.
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_discard_if_corrupted(true);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single(None, StoreOptions::create()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), None);
+    assert_eq!(fs::read(&discarded).expect("read"), b"bogus");
+    drop(reader);
+    drop(k);
+
+    // Corrupted meta pages are detected as well, and earlier backups are kept.
+    for meta in 0..2 {
+        bytes[meta * 4096 + 16..meta * 4096 + 20].copy_from_slice(b"nope");
+    }
+    fs::write(&data, &bytes).expect("corrupted");
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single(None, StoreOptions::create()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), None);
+    assert_eq!(fs::read(&discarded).expect("read"), b"bogus");
+    assert_eq!(fs::read(root.path().join("data.mdb.corrupted.1")).expect("read"), bytes);
+}
+
+#[test]
+#[ignore]
+fn hold_open_helper() {
+    if let Some(path) = env::var_os("RKV_HOLD_OPEN") {
+        let k = Rkv::new::<Lmdb>(Path::new(&path)).expect("new succeeded");
+        println!("opened");
+        io::stdin().read_line(&mut String::new()).expect("read");
+        drop(k);
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_discard_if_corrupted_in_use() {
+    let root = Builder::new().prefix("test_discard_if_corrupted_in_use").tempdir().expect("tempdir");
+    let data = root.path().join("data.mdb");
+    Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+
+    // Another process has the environment open, and may still be writing to the data
+    // file, so it isn't discarded.
+    let mut child = Command::new(env::current_exe().expect("test binary"))
+        .args(["--exact", "hold_open_helper", "--ignored", "--nocapture"])
+        .env("RKV_HOLD_OPEN", root.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawned");
+    let mut stdout = io::BufReader::new(child.stdout.take().expect("stdout"));
+    let mut line = String::new();
+    while !line.trim_end().ends_with("opened") {
+        line.clear();
+        assert_ne!(stdout.read_line(&mut line).expect("read"), 0, "helper exited");
+    }
+
+    fs::write(&data, "bogus").expect("corrupted");
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_discard_if_corrupted(true);
+    Rkv::from_builder(root.path(), builder).expect_err("not opened");
+    assert_eq!(fs::read(&data).expect("read"), b"bogus");
+    assert!(!root.path().join("data.mdb.corrupted").exists());
+
+    // Once it's closed, the data file can be discarded.
+    drop(child.stdin.take());
+    assert!(child.wait().expect("exited").success());
+    Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    assert_eq!(fs::read(root.path().join("data.mdb.corrupted")).expect("read"), b"bogus");
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_discard_if_corrupted_other_bit_depth() {
+    let root = Builder::new().prefix("test_discard_if_corrupted_other_bit_depth").tempdir().expect("tempdir");
+    let data = root.path().join("data.mdb");
+    fs::copy(Path::new("tests/envs/ref_env_32/data.mdb"), &data).expect("copied");
+
+    // Environments created with another bit depth can be migrated, so they're kept.
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_discard_if_corrupted(true);
+    Rkv::from_builder(root.path(), builder).expect_err("not opened");
+    assert_eq!(fs::read(&data).expect("read"), fs::read("tests/envs/ref_env_32/data.mdb").expect("read"));
+    assert!(!root.path().join("data.mdb.corrupted").exists());
+}
+
+fn write_flags<F: BackendWriteFlags>(flag: WriteFlags) -> F {
+    let mut flags = F::empty();
+    flags.set(flag, true);
+    flags
+}
+
+/// Write flags make writes fail when they would break the rules of the flags.
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_write_flags() {
+    let root = Builder::new().prefix("test_write_flags").tempdir().expect("tempdir");
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    mk.put(&mut writer, "b", &Value::Str("2")).expect("wrote");
+
+    // NO_OVERWRITE rejects keys that already exist.
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("3"), write_flags(WriteFlags::NO_OVERWRITE));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "c", &Value::Str("1"), write_flags(WriteFlags::NO_OVERWRITE)).expect("wrote");
+
+    // NO_DUP_DATA only rejects key/value pairs that already exist.
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("2"), write_flags(WriteFlags::NO_DUP_DATA));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "b", &Value::Str("3"), write_flags(WriteFlags::NO_DUP_DATA)).expect("wrote");
+
+    // APPEND requires keys to be greater than the last one.
+    let result = mk.put_with_flags(&mut writer, "a", &Value::Str("1"), write_flags(WriteFlags::APPEND));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    let result = mk.put_with_flags(&mut writer, "c", &Value::Str("2"), write_flags(WriteFlags::APPEND));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "d", &Value::Str("1"), write_flags(WriteFlags::APPEND)).expect("wrote");
+
+    // APPEND_DUP requires values to be greater than the last one of their key.
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("1"), write_flags(WriteFlags::APPEND_DUP));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("3"), write_flags(WriteFlags::APPEND_DUP));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "b", &Value::Str("4"), write_flags(WriteFlags::APPEND_DUP)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let values: Vec<_> = mk.get(&reader, "b").expect("read").map(|result| result.expect("value").1).collect();
+    assert_eq!(values, vec![Value::Str("2"), Value::Str("3"), Value::Str("4")]);
+    let values: Vec<_> = mk.get(&reader, "c").expect("read").map(|result| result.expect("value").1).collect();
+    assert_eq!(values, vec![Value::Str("1")]);
+    let values: Vec<_> = mk.get(&reader, "d").expect("read").map(|result| result.expect("value").1).collect();
+    assert_eq!(values, vec![Value::Str("1")]);
+}
+
+/// Deletes and replaces key/value pairs while walking over them with a read-write
+/// cursor, which must visit every pair exactly once.
+#[cfg(feature = "db-dup-sort")]
+fn check_rw_cursor(
+    flags: LmdbDatabaseFlags,
+    expected_visits: Vec<(Vec<u8>, Vec<u8>)>,
+    expected_entries: Vec<(Vec<u8>, Vec<u8>)>,
+) {
+    let root = Builder::new().prefix("test_rw_cursor").tempdir().expect("tempdir");
+    let mut builder = Lmdb::new();
+    builder.set_max_dbs(1);
+    let env = builder.open(root.path()).expect("opened");
+    let db = env.create_db(Some("db"), flags).expect("created");
+
+    let mut txn = env.begin_rw_txn().expect("began");
+    for (key, value) in &[("a", "1"), ("b", "2"), ("c", "3"), ("c", "4"), ("d", "6"), ("e", "7")] {
+        txn.put(&db, key.as_bytes(), value.as_bytes(), BackendFlags::empty()).expect("wrote");
+    }
+
+    // Even values are deleted and the value of "e" is replaced, while the cursor
+    // keeps visiting every pair exactly once.
+    let mut visited = vec![];
+    let mut cursor = txn.open_rw_cursor(&db).expect("opened");
+    while let Some(result) = cursor.next() {
+        let (key, value) = result.expect("read");
+        let (key, value) = (key.to_vec(), value.to_vec());
+        if value[0] % 2 == 0 {
+            cursor.del_current().expect("deleted");
+        } else if key == b"e" {
+            cursor.put_current(b"8").expect("replaced");
+        }
+        visited.push((key, value));
+    }
+    drop(cursor);
+    assert_eq!(visited, expected_visits);
+    txn.commit().expect("committed");
+    drop(env);
+
+    let env = builder.open(root.path()).expect("opened");
+    let db = env.open_db(Some("db")).expect("opened");
+    let txn = env.begin_ro_txn().expect("began");
+    let mut iter = txn.open_ro_cursor(&db).expect("opened").into_iter();
+    let mut entries = vec![];
+    while let Some(result) = iter.next() {
+        let (key, value) = result.expect("read");
+        entries.push((key.to_vec(), value.to_vec()));
+    }
+    assert_eq!(entries, expected_entries);
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    pairs.iter().map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec())).collect()
+}
+
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_rw_cursor() {
+    let visited = pairs(&[("a", "1"), ("b", "2"), ("c", "4"), ("d", "6"), ("e", "7")]);
+    check_rw_cursor(LmdbDatabaseFlags::empty(), visited, pairs(&[("a", "1"), ("e", "8")]));
+    let mut flags = LmdbDatabaseFlags::empty();
+    flags.set(DatabaseFlags::DUP_SORT, true);
+    let visited = pairs(&[("a", "1"), ("b", "2"), ("c", "3"), ("c", "4"), ("d", "6"), ("e", "7")]);
+    check_rw_cursor(flags, visited, pairs(&[("a", "1"), ("c", "3"), ("e", "8")]));
+}
+
+/// Copies an environment while one of its databases isn't loaded, then checks that the
+/// copy has all the data, and is left alone by later commits.
+fn check_copy_to(options: CopyOptions) {
+    let root = Builder::new().prefix("test_copy_to").tempdir().expect("tempdir");
+    let copy = Builder::new().prefix("test_copy_to_copy").tempdir().expect("tempdir");
+    {
+        let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+        let a = k.open_single("a", StoreOptions::create()).expect("opened");
+        let b = k.open_single("b", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        a.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        b.put(&mut writer, "bar", &Value::Str("baz")).expect("wrote");
+        writer.commit().expect("committed");
+        k.sync(true).expect("synced");
+    }
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let a = k.open_single("a", StoreOptions::create()).expect("opened");
+    k.copy_to(copy.path(), options).expect("copied");
+    k.copy_to(copy.path(), options).expect_err("copied twice");
+    let mut writer = k.write().expect("writer");
+    a.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let k = Rkv::new::<Lmdb>(copy.path()).expect("new succeeded");
+    let a = k.open_single("a", StoreOptions::default()).expect("opened");
+    let b = k.open_single("b", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(a.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    assert_eq!(b.get(&reader, "bar").expect("read"), Some(Value::Str("baz")));
+}
+
+#[test]
+fn test_copy_to() {
+    check_copy_to(CopyOptions::default());
+    check_copy_to(CopyOptions::compact());
+}
+
+#[test]
+fn test_drop_and_rename_db() {
+    let root = Builder::new().prefix("test_drop_and_rename_db").tempdir().expect("tempdir");
+    {
+        let k = Rkv::with_capacity::<Lmdb>(root.path(), 3).expect("new succeeded");
+        let a = k.open_single("a", StoreOptions::create()).expect("opened");
+        let b = k.open_single("b", StoreOptions::create()).expect("opened");
+        let m = k.open_multi("m", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        a.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        b.put(&mut writer, "bar", &Value::Str("baz")).expect("wrote");
+        m.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+        m.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+        writer.commit().expect("committed");
+
+        let reader = k.read().expect("reader");
+        match k.drop_db("a") {
+            Err(StoreError::OpenAttemptedDuringTransaction(_)) => {},
+            result => panic!("expected OpenAttemptedDuringTransaction, got {:?}", result),
+        }
+        drop(reader);
+
+        k.drop_db("a").expect("dropped");
+        k.drop_db("a").expect_err("dropped twice");
+        k.open_single("a", StoreOptions::default()).expect_err("opened");
+        match k.rename_db("m", "b") {
+            Err(StoreError::KeyExists) => {},
+            result => panic!("expected KeyExists, got {:?}", result),
+        }
+        k.rename_db("m", "n").expect("renamed");
+        k.open_multi("m", StoreOptions::default()).expect_err("opened");
+        k.sync(true).expect("synced");
+    }
+
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 3).expect("new succeeded");
+    let mut dbs = k.get_dbs().expect("dbs");
+    dbs.sort();
+    assert_eq!(dbs, [Some("b".to_owned()), Some("n".to_owned())]);
+
+    // The renamed database keeps its contents and flags.
+    let b = k.open_single("b", StoreOptions::default()).expect("opened");
+    let n = k.open_multi("n", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(b.get(&reader, "bar").expect("read"), Some(Value::Str("baz")));
+    let values: Vec<_> = n.get(&reader, "foo").expect("read").map(|item| item.expect("value").1).collect();
+    assert_eq!(values, [Value::I64(1), Value::I64(2)]);
+    reader.abort();
+
+    // Dropping a database makes room for another one.
+    k.open_single("c", StoreOptions::create()).expect("opened");
+    match k.open_single("d", StoreOptions::create()) {
+        Err(StoreError::DbsFull) => {},
+        result => panic!("expected DbsFull, got {:?}", result),
+    }
+    k.drop_db("b").expect("dropped");
+    k.open_single("d", StoreOptions::create()).expect("opened");
+}
+
+#[test]
+fn test_nested_writer() {
+    let root = Builder::new().prefix("test_nested_writer").tempdir().expect("tempdir");
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    mk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+
+    // Aborting a nested writer only rolls back its own changes.
+    let mut nested = writer.begin_nested().expect("nested");
+    assert_eq!(sk.get(&nested, "foo").expect("read"), Some(Value::I64(1)));
+    sk.put(&mut nested, "bar", &Value::I64(2)).expect("wrote");
+    mk.put(&mut nested, "foo", &Value::I64(2)).expect("wrote");
+    sk.clear(&mut nested).expect("cleared");
+    assert_eq!(sk.get(&nested, "foo").expect("read"), None);
+    nested.abort();
+    assert_eq!(sk.get(&writer, "foo").expect("read"), Some(Value::I64(1)));
+    assert_eq!(sk.get(&writer, "bar").expect("read"), None);
+    assert_eq!(mk.get(&writer, "foo").expect("read").count(), 1);
+
+    // Nested writers can be nested too, and are aborted when dropped.
+    let mut nested = writer.begin_nested().expect("nested");
+    sk.put(&mut nested, "baz", &Value::I64(3)).expect("wrote");
+    mk.put(&mut nested, "foo", &Value::I64(3)).expect("wrote");
+    {
+        let mut inner = nested.begin_nested().expect("nested");
+        sk.put(&mut inner, "qux", &Value::I64(4)).expect("wrote");
+        assert_eq!(sk.get(&inner, "baz").expect("read"), Some(Value::I64(3)));
+    }
+    let mut inner = nested.begin_nested().expect("nested");
+    sk.delete(&mut inner, "foo").expect("deleted");
+    inner.commit().expect("committed");
+    nested.commit().expect("committed");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk.iter_start(&reader).expect("iter").map(|item| item.expect("item").0.to_vec()).collect();
+    assert_eq!(keys, [b"baz".to_vec()]);
+    let values: Vec<_> = mk.get(&reader, "foo").expect("read").map(|item| item.expect("value").1).collect();
+    assert_eq!(values, [Value::I64(1), Value::I64(3)]);
+    reader.abort();
+
+    // Committing a writer commits the changes of the nested ones left behind.
+    let mut writer = k.write().expect("writer");
+    let mut nested = writer.begin_nested().expect("nested");
+    sk.put(&mut nested, "foo", &Value::I64(5)).expect("wrote");
+    std::mem::forget(nested);
+    writer.commit().expect("committed");
+    drop(k);
+
+    // What was committed is what was written.
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk.iter_start(&reader).expect("iter").map(|item| item.expect("item").0.to_vec()).collect();
+    assert_eq!(keys, [b"baz".to_vec(), b"foo".to_vec()]);
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(5)));
+    assert_eq!(mk.get(&reader, "foo").expect("read").count(), 2);
+}
+
+#[cfg(feature = "db-dup-sort")]
+type Found<'c, E> = Option<Result<(&'c [u8], &'c [u8]), E>>;
+
+#[cfg(feature = "db-dup-sort")]
+fn found<E: std::fmt::Debug>(found: Found<'_, E>) -> Option<(&str, &str)> {
+    found.map(|result| {
+        let (key, value) = result.expect("read");
+        (std::str::from_utf8(key).expect("utf8"), std::str::from_utf8(value).expect("utf8"))
+    })
+}
+
+/// Positioned cursors move between keys and between the values of a key, and find
+/// nothing when there is nowhere left to go.
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_positioned_cursor() {
+    let root = Builder::new().prefix("test_positioned_cursor").tempdir().expect("tempdir");
+    let mut builder = Lmdb::new();
+    builder.set_max_dbs(3);
+    let env = builder.open(root.path()).expect("opened");
+    let mut flags = LmdbDatabaseFlags::empty();
+    flags.set(DatabaseFlags::DUP_SORT, true);
+    let dups = env.create_db(Some("dups"), flags).expect("created");
+    let single = env.create_db(Some("single"), LmdbDatabaseFlags::empty()).expect("created");
+    let empty = env.create_db(Some("empty"), LmdbDatabaseFlags::empty()).expect("created");
+
+    let mut txn = env.begin_rw_txn().expect("began");
+    for (key, value) in &[("a", "1"), ("c", "3"), ("c", "4"), ("c", "5"), ("e", "7")] {
+        txn.put(&dups, key.as_bytes(), value.as_bytes(), BackendFlags::empty()).expect("wrote");
+        txn.put(&single, key.as_bytes(), value.as_bytes(), BackendFlags::empty()).expect("wrote");
+    }
+    txn.commit().expect("committed");
+
+    let txn = env.begin_ro_txn().expect("began");
+    let mut cursor = txn.open_ro_cursor(&dups).expect("opened");
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.prev()), Some(("e", "7")));
+    assert_eq!(found(cursor.prev()), Some(("c", "5")));
+    assert_eq!(found(cursor.prev_dup()), Some(("c", "4")));
+    assert_eq!(found(cursor.prev_dup()), Some(("c", "3")));
+    assert_eq!(found(cursor.prev_dup()), None);
+    assert_eq!(found(cursor.next_dup()), Some(("c", "4")));
+    assert_eq!(found(cursor.next()), Some(("c", "5")));
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.next()), Some(("e", "7")));
+    assert_eq!(found(cursor.next()), None);
+    assert_eq!(found(cursor.prev()), Some(("c", "5")));
+    assert_eq!(found(cursor.first()), Some(("a", "1")));
+    assert_eq!(found(cursor.prev()), None);
+    assert_eq!(found(cursor.next()), Some(("c", "3")));
+    assert_eq!(found(cursor.seek(b"b")), Some(("c", "3")));
+    assert_eq!(found(cursor.seek(b"c")), Some(("c", "3")));
+    assert_eq!(found(cursor.seek_exact(b"e")), Some(("e", "7")));
+    assert_eq!(found(cursor.last()), Some(("e", "7")));
+
+    // A failed seek leaves the cursor unpositioned.
+    assert_eq!(found(cursor.seek_exact(b"b")), None);
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.next()), Some(("a", "1")));
+    assert_eq!(found(cursor.seek(b"f")), None);
+    assert_eq!(found(cursor.prev()), Some(("e", "7")));
+
+    // Without `DUP_SORT`, there are no other values to move to.
+    let mut cursor = txn.open_ro_cursor(&single).expect("opened");
+    assert_eq!(found(cursor.seek_exact(b"c")), Some(("c", "5")));
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.prev_dup()), None);
+    assert_eq!(found(cursor.prev()), Some(("a", "1")));
+    assert_eq!(found(cursor.seek(b"d")), Some(("e", "7")));
+
+    let mut cursor = txn.open_ro_cursor(&empty).expect("opened");
+    assert_eq!(found(cursor.first()), None);
+    assert_eq!(found(cursor.last()), None);
+    assert_eq!(found(cursor.next()), None);
+    assert_eq!(found(cursor.prev()), None);
+    assert_eq!(found(cursor.seek(b"a")), None);
+}
+
+/// Single and multi stores iterate backwards from the end or from a given key, including
+/// over what a writer hasn't committed yet.
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_iter_rev() {
+    let root = Builder::new().prefix("test_iter_rev").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 3).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let empty = k.open_single("empty", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for (key, value) in &[("a", 1), ("c", 3), ("c", 4), ("e", 7)] {
+        sk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+        mk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+    }
+    let keys: Vec<_> = sk.iter_rev(&writer).expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"e", b"c", b"a"]);
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let items: Vec<_> = sk.iter_rev(&reader).expect("iter").map(|item| item.expect("item")).collect();
+    assert_eq!(items, [(&b"e"[..], Value::I64(7)), (b"c", Value::I64(4)), (b"a", Value::I64(1))]);
+    let keys: Vec<_> = sk.iter_rev_from(&reader, "c").expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"c", b"a"]);
+    let keys: Vec<_> = sk.iter_rev_from(&reader, "d").expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"c", b"a"]);
+    let keys: Vec<_> = sk.iter_rev_from(&reader, "z").expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"e", b"c", b"a"]);
+    assert_eq!(sk.iter_rev_from(&reader, "0").expect("iter").count(), 0);
+    assert_eq!(empty.iter_rev(&reader).expect("iter").count(), 0);
+    assert_eq!(empty.iter_rev_from(&reader, "a").expect("iter").count(), 0);
+
+    let items: Vec<_> = mk.iter_rev(&reader).expect("iter").map(|item| item.expect("item")).collect();
+    let expected = [(&b"e"[..], Value::I64(7)), (b"c", Value::I64(4)), (b"c", Value::I64(3)), (b"a", Value::I64(1))];
+    assert_eq!(items, expected);
+    let items: Vec<_> = mk.iter_rev_from(&reader, "c").expect("iter").map(|item| item.expect("item")).collect();
+    assert_eq!(items, expected[1..]);
+    let items: Vec<_> = mk.iter_rev_from(&reader, "b").expect("iter").map(|item| item.expect("item")).collect();
+    assert_eq!(items, expected[3..]);
+}
+
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn keys<'i>(iter: impl Iterator<Item = Result<(&'i [u8], Value<'i>), StoreError>>) -> Vec<Vec<u8>> {
+    iter.map(|item| item.expect("item").0.to_vec()).collect()
+}
+
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn items<'i>(iter: impl Iterator<Item = Result<(&'i [u8], Value<'i>), StoreError>>) -> Vec<(Vec<u8>, Value<'i>)> {
+    iter.map(|item| item.expect("item")).map(|(key, value)| (key.to_vec(), value)).collect()
+}
+
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn integers<'i, K>(iter: impl Iterator<Item = Result<(K, Value<'i>), StoreError>>) -> Vec<K> {
+    iter.map(|item| item.expect("item").0).collect()
+}
+
+/// Iterating over ranges and prefixes of keys stops at the right place, comparing keys
+/// the way their databases sort them.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_iter_range() {
+    let root = Builder::new().prefix("test_iter_range").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 5).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let ik = k.open_integer("ik", StoreOptions::create()).expect("opened");
+    let mik = k.open_multi_integer("mik", StoreOptions::create()).expect("opened");
+    let sik = k.open_integer("sik", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &[&b"a"[..], b"ab", b"abc", b"b", b"ba", b"c", b"\xfe", b"\xff", b"\xff\xff"] {
+        sk.put(&mut writer, key, &Value::Bool(true)).expect("wrote");
+    }
+    for (key, value) in &[("a", 1), ("a", 2), ("b", 3), ("c", 4), ("c", 5)] {
+        mk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+    }
+    for key in &[1u32, 2, 256, 65536] {
+        ik.put(&mut writer, *key, &Value::Bool(true)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(1)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(2)).expect("wrote");
+    }
+    for key in &[-65536i32, -256, -1, 0, 255] {
+        sik.put(&mut writer, *key, &Value::Bool(true)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(keys(sk.iter_range(&reader, "ab".."b").expect("iter")), [&b"ab"[..], b"abc"]);
+    assert_eq!(keys(sk.iter_range(&reader, "ab"..="b").expect("iter")), [&b"ab"[..], b"abc", b"b"]);
+    assert_eq!(keys(sk.iter_range(&reader, ..="a").expect("iter")), [b"a"]);
+    assert_eq!(keys(sk.iter_range(&reader, "bb".."ba").expect("iter")), Vec::<Vec<u8>>::new());
+    let range = (Bound::Excluded(b"ab".to_vec()), Bound::Excluded(b"c".to_vec()));
+    assert_eq!(keys(sk.iter_range(&reader, range).expect("iter")), [&b"abc"[..], b"b", b"ba"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, "ab").expect("iter")), [&b"ab"[..], b"abc"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, "b").expect("iter")), [&b"b"[..], b"ba"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, b"\xff").expect("iter")), [&b"\xff"[..], b"\xff\xff"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, "d").expect("iter")), Vec::<Vec<u8>>::new());
+    assert_eq!(sk.iter_prefix(&reader, "").expect("iter").count(), 9);
+
+    let expected = vec![(b"a", Value::I64(1)), (b"a", Value::I64(2)), (b"b", Value::I64(3))];
+    let expected: Vec<_> = expected.into_iter().map(|(key, value)| (key.to_vec(), value)).collect();
+    assert_eq!(items(mk.iter_range(&reader, "a".."c").expect("iter")), expected);
+    let range = (Bound::Excluded(b"a".to_vec()), Bound::Included(b"c".to_vec()));
+    let expected = vec![(b"b", Value::I64(3)), (b"c", Value::I64(4)), (b"c", Value::I64(5))];
+    let expected: Vec<_> = expected.into_iter().map(|(key, value)| (key.to_vec(), value)).collect();
+    assert_eq!(items(mk.iter_range(&reader, range).expect("iter")), expected);
+    assert_eq!(items(mk.iter_prefix(&reader, "c").expect("iter")), expected[1..]);
+
+    assert_eq!(integers(ik.iter_range(&reader, 2..=65536).expect("iter")), [2, 256, 65536]);
+    assert_eq!(integers(ik.iter_range(&reader, 2..65536).expect("iter")), [2, 256]);
+    let range = (Bound::Excluded(2), Bound::Unbounded);
+    assert_eq!(integers(ik.iter_range(&reader, range).expect("iter")), [256, 65536]);
+    assert_eq!(integers(mik.iter_range(&reader, 3..).expect("iter")), [256, 256, 65536, 65536]);
+
+    // Integer prefixes are made of the most significant bits of the keys.
+    assert_eq!(integers(ik.iter_prefix(&reader, 0, 24).expect("iter")), [1, 2]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 256, 24).expect("iter")), [256]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 0, 16).expect("iter")), [1, 2, 256]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 65536, 32).expect("iter")), [65536]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 3, 32).expect("iter")), Vec::<u32>::new());
+    assert_eq!(ik.iter_prefix(&reader, 3, 0).expect("iter").count(), 4);
+    assert_eq!(integers(mik.iter_prefix(&reader, 256, 24).expect("iter")), [256, 256]);
+    assert_eq!(integers(sik.iter_prefix(&reader, -1, 24).expect("iter")), [-256, -1]);
+    assert_eq!(integers(sik.iter_prefix(&reader, -1, 1).expect("iter")), [-65536, -256, -1]);
+    assert_eq!(integers(sik.iter_prefix(&reader, 0, 24).expect("iter")), [0, 255]);
+}
+
+/// Deleting ranges and prefixes of keys counts every value of the keys it deletes, and
+/// the deletions are kept once reopened.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_delete_range() {
+    let root = Builder::new().prefix("test_delete_range").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 3).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let ik = k.open_integer("ik", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &["a", "ab", "abc", "b", "ba", "c"] {
+        sk.put(&mut writer, key, &Value::Bool(true)).expect("wrote");
+    }
+    for (key, value) in &[("a", 1), ("a", 2), ("b", 3), ("c", 4), ("c", 5)] {
+        mk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+    }
+    for key in 0u32..1000 {
+        ik.put(&mut writer, key, &Value::U64(u64::from(key))).expect("wrote");
+    }
+
+    assert_eq!(sk.delete_range(&mut writer, "bb".."ba").expect("deleted"), 0);
+    assert_eq!(sk.delete_range(&mut writer, "x"..).expect("deleted"), 0);
+    let range = (Bound::Excluded(b"ab".to_vec()), Bound::Included(b"b".to_vec()));
+    assert_eq!(sk.delete_range(&mut writer, range).expect("deleted"), 2);
+    assert_eq!(keys(sk.iter_start(&writer).expect("iter")), [&b"a"[..], b"ab", b"ba", b"c"]);
+    assert_eq!(sk.delete_prefix(&mut writer, "a").expect("deleted"), 2);
+    assert_eq!(keys(sk.iter_start(&writer).expect("iter")), [&b"ba"[..], b"c"]);
+
+    assert_eq!(mk.delete_range(&mut writer, "a"..="b").expect("deleted"), 3);
+    assert_eq!(mk.get(&writer, "c").expect("read").count(), 2);
+    assert_eq!(mk.delete_prefix(&mut writer, "").expect("deleted"), 2);
+    assert_eq!(mk.get(&writer, "c").expect("read").count(), 0);
+
+    assert_eq!(ik.delete_range(&mut writer, 250..750).expect("deleted"), 500);
+    assert_eq!(ik.delete_range(&mut writer, 600..).expect("deleted"), 250);
+    writer.commit().expect("committed");
+    drop(k);
+
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 3).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let ik = k.open_integer::<&str, u32>("ik", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(keys(sk.iter_start(&reader).expect("iter")), [&b"ba"[..], b"c"]);
+    assert_eq!(ik.get(&reader, 249).expect("read"), Some(Value::U64(249)));
+    assert_eq!(ik.get(&reader, 250).expect("read"), None);
+    assert_eq!(ik.get(&reader, 749).expect("read"), None);
+    assert_eq!(ik.iter_range(&reader, ..).expect("iter").count(), 250);
+}
+
+/// Keys only follow each other by prefix when they're sorted byte by byte, so other
+/// databases refuse prefixes rather than skipping keys or deleting the wrong ones.
+#[test]
+fn test_prefix_unsupported() {
+    let root = Builder::new().prefix("test_prefix_unsupported").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 2).expect("new succeeded");
+    check_prefix_unsupported(&k, "rk", DatabaseFlags::REVERSE_KEY);
+    #[cfg(feature = "db-int-key")]
+    check_prefix_unsupported(&k, "ik", DatabaseFlags::INTEGER_KEY);
+}
+
+fn check_prefix_unsupported(k: &Rkv<LmdbEnvironment>, name: &str, flags: DatabaseFlags) {
+    let mut options = StoreOptions::<LmdbDatabaseFlags>::create();
+    options.flags.set(flags, true);
+    let store = k.open_single(name, options).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &[[0u8, 0, 0, 1], [1, 0, 0, 0], [1, 0, 0, 1]] {
+        store.put(&mut writer, key, &Value::Bool(true)).expect("wrote");
+    }
+    match store.iter_prefix(&writer, [1u8]) {
+        Err(StoreError::PrefixNotSupported) => {},
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    match store.delete_prefix(&mut writer, [1u8]) {
+        Err(StoreError::PrefixNotSupported) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(store.iter_start(&writer).expect("iter").count(), 3);
+}
+
+/// Integer stores iterate in integer order, decoding the keys back into integers.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_integer_iter() {
+    let root = Builder::new().prefix("test_integer_iter").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 2).expect("new succeeded");
+    let ik = k.open_integer("ik", StoreOptions::create()).expect("opened");
+    let mik = k.open_multi_integer("mik", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &[65536u32, 1, 256, 2, u32::MAX] {
+        ik.put(&mut writer, *key, &Value::U64(u64::from(*key))).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(1)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(2)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(integers(ik.iter_start(&reader).expect("iter")), [1, 2, 256, 65536, u32::MAX]);
+    assert_eq!(integers(ik.iter_from(&reader, 3).expect("iter")), [256, 65536, u32::MAX]);
+    assert_eq!(integers(ik.iter_from(&reader, 256).expect("iter")), [256, 65536, u32::MAX]);
+    for item in ik.iter_start(&reader).expect("iter") {
+        let (key, value) = item.expect("item");
+        assert_eq!(value, Value::U64(u64::from(key)));
+    }
+
+    let expected = [1, 1, 2, 2, 256, 256, 65536, 65536, u32::MAX, u32::MAX];
+    assert_eq!(integers(mik.iter_start(&reader).expect("iter")), expected);
+    assert_eq!(integers(mik.iter_from(&reader, 65536).expect("iter")), [65536, 65536, u32::MAX, u32::MAX]);
+    let iter = mik.iter_from(&reader, u32::MAX).expect("iter");
+    let values: Vec<_> = iter.map(|item| item.expect("item").1).collect();
+    assert_eq!(values, [Value::I64(1), Value::I64(2)]);
+}
+
+/// Stores every key into an integer store, and checks that they come back sorted.
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn check_sorted_integers<K>(k: &Rkv<LmdbEnvironment>, name: &str, keys: &[K]) -> IntegerStore<LmdbDatabase, K>
+where
+    K: PrimitiveInt + DecodableKey + Copy + Ord + std::fmt::Debug,
+{
+    let store = k.open_integer(name, StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    for key in keys {
+        store.put(&mut writer, *key, &Value::Bool(true)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let mut sorted = keys.to_vec();
+    sorted.sort();
+    let reader = k.read().expect("reader");
+    assert_eq!(integers(store.iter_start(&reader).expect("iter")), sorted);
+    store
+}
+
+/// Integer stores sort their keys by value, whatever the type of integer, including
+/// signed ones.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_integer_key_types() {
+    let root = Builder::new().prefix("test_integer_key_types").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<Lmdb>(root.path(), 8).expect("new succeeded");
+
+    check_sorted_integers(&k, "u8", &[255u8, 1, 0, 16]);
+    check_sorted_integers(&k, "u16", &[256u16, 1, u16::MAX, 0, 255]);
+    check_sorted_integers(&k, "u32", &[256u32, 1, u32::MAX, 0, 65536]);
+    check_sorted_integers(&k, "u64", &[1u64 << 32, 1, u64::MAX, 0, 256]);
+    check_sorted_integers(&k, "usize", &[256usize, 1, usize::MAX, 0, 65536]);
+    check_sorted_integers(&k, "i32", &[-1i32, 1, i32::MAX, 0, i32::MIN, -256, 256]);
+    let store = check_sorted_integers(&k, "i64", &[-1i64, 1, i64::MAX, 0, i64::MIN, -(1 << 32), 1 << 32]);
+
+    let reader = k.read().expect("reader");
+    assert_eq!(integers(store.iter_range(&reader, -256..=1).expect("iter")), [-1, 0, 1]);
+    assert_eq!(integers(store.iter_from(&reader, 2).expect("iter")), [1 << 32, i64::MAX]);
+    drop(reader);
+
+    let mik = k.open_multi_integer("mik", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    for key in &[3i32, -3, 0] {
+        mik.put(&mut writer, *key, &Value::I64(1)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(2)).expect("wrote");
+    }
+    assert_eq!(integers(mik.iter_start(&writer).expect("iter")), [-3, -3, 0, 0, 3, 3]);
+    assert_eq!(mik.get(&writer, -3).expect("read").count(), 2);
+}
@@ -13,30 +13,68 @@
 #![allow(clippy::complexity)]
 
 use std::{
+    collections::{
+        BTreeMap,
+        BTreeSet,
+        HashMap,
+    },
     fs,
+    ops::Bound,
     path::Path,
+    process,
     str,
     sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
         Arc,
         RwLock,
     },
     thread,
+    time::Duration,
 };
 
 use byteorder::{
     ByteOrder,
     LittleEndian,
+    WriteBytesExt,
 };
+use serde_derive::Serialize;
 use tempfile::Builder;
 
 use rkv::{
     backend::{
+        BackendEnvironment,
         BackendEnvironmentBuilder,
+        BackendFlags,
+        BackendInfo,
+        BackendIter,
+        BackendPositionedCursor,
+        BackendRoCursor,
+        BackendRoCursorTransaction,
+        BackendRwCursor,
+        BackendRwCursorTransaction,
+        BackendRwTransaction,
+        BackendStat,
+        BackendWriteFlags,
+        EnvironmentFlags,
         SafeMode,
         SafeModeDatabase,
+        SafeModeDatabaseFlags,
         SafeModeEnvironment,
+        SafeModeEnvironmentFlags,
+        SafeModeError,
         SafeModeRwTransaction,
+        SafeModeWriteFlags,
+        WriteFlags,
     },
+    CloseOptions,
+    CopyOptions,
+    DecodableKey,
+    IntegerStore,
+    MapGrowth,
+    PrimitiveInt,
     Rkv,
     SingleStore,
     StoreError,
@@ -703,6 +741,344 @@ fn test_sync_safe() {
     assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
 }
 
+#[test]
+fn test_commits_replayed_safe() {
+    let root = Builder::new().prefix("test_commits_replayed_safe").tempdir().expect("tempdir");
+
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let _empty = k.open_single("empty", StoreOptions::create()).expect("opened");
+
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        sk.put(&mut writer, "bar", &Value::Bool(true)).expect("wrote");
+        writer.commit().expect("committed");
+
+        let mut writer = k.write().expect("writer");
+        sk.delete(&mut writer, "bar").expect("deleted");
+        sk.put(&mut writer, "baz", &Value::Str("héllo")).expect("wrote");
+        writer.commit().expect("committed");
+
+        // Nothing but the log has been written to so far.
+        assert!(!root.path().join("data.safe.bin").exists());
+        assert!(root.path().join("data.safe.log").exists());
+    }
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let mut dbs = k.get_dbs().expect("dbs");
+    dbs.sort();
+    assert_eq!(dbs, vec![Some("empty".to_owned()), Some("sk".to_owned())]);
+
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    assert_eq!(sk.get(&reader, "bar").expect("read"), None);
+    assert_eq!(sk.get(&reader, "baz").expect("read"), Some(Value::Str("héllo")));
+}
+
+#[test]
+fn test_checkpoint_safe() {
+    let root = Builder::new().prefix("test_checkpoint_safe").tempdir().expect("tempdir");
+    let log = root.path().join("data.safe.log");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_checkpoint_threshold(256);
+
+    {
+        let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        writer.commit().expect("committed");
+        assert!(fs::metadata(&log).expect("log").len() > 0);
+
+        // Crossing the threshold folds the log into the snapshot.
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "bar", &Value::Blob(&[0; 256])).expect("wrote");
+        writer.commit().expect("committed");
+        assert_eq!(fs::metadata(&log).expect("log").len(), 0);
+        assert!(root.path().join("data.safe.bin").exists());
+
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "baz", &Value::Bool(false)).expect("wrote");
+        writer.commit().expect("committed");
+        assert!(fs::metadata(&log).expect("log").len() > 0);
+    }
+
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    assert_eq!(sk.get(&reader, "bar").expect("read"), Some(Value::Blob(&[0; 256])));
+    assert_eq!(sk.get(&reader, "baz").expect("read"), Some(Value::Bool(false)));
+}
+
+#[test]
+fn test_failed_commit_rolls_back_safe() {
+    let root = Builder::new().prefix("test_failed_commit_rolls_back_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+    k.sync(true).expect("synced");
+
+    // Make appending to the log fail.
+    let log = root.path().join("data.safe.log");
+    fs::remove_file(&log).expect("removed");
+    fs::create_dir(&log).expect("dir created");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+    writer.commit().expect_err("failed");
+    fs::remove_dir(&log).expect("dir removed");
+
+    // Readers don't observe a commit that didn't make it to disk.
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    reader.abort();
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(9012)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(9012)));
+}
+#[test]
+fn test_crash_during_checkpoint_safe() {
+    let root = Builder::new().prefix("test_crash_during_checkpoint_safe").tempdir().expect("tempdir");
+    let log = root.path().join("data.safe.log");
+
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        writer.commit().expect("committed");
+        k.sync(true).expect("synced");
+
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+        sk.put(&mut writer, "bar", &Value::Bool(true)).expect("wrote");
+        writer.commit().expect("committed");
+    }
+
+    // Leave a half written temporary file next to every database file, as if the
+    // process died while writing the next snapshot.
+    for entry in fs::read_dir(root.path()).expect("listed") {
+        let path = entry.expect("entry").path();
+        if path.extension().map_or(false, |extension| extension == "bin") {
+            let bytes = fs::read(&path).expect("read");
+            let mut temp = path.into_os_string();
+            temp.push(".tmp");
+            fs::write(&temp, &bytes[..bytes.len() / 2]).expect("written");
+        }
+    }
+
+    // The environment must still be readable after the crash, since the log still
+    // contains the last commit.
+    let k = Rkv::new::<SafeMode>(root.path()).expect("reopened");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(5678)));
+    assert_eq!(sk.get(&reader, "bar").expect("read"), Some(Value::Bool(true)));
+    reader.abort();
+
+    // The leftover temporary files don't prevent further checkpoints. Restoring the
+    // log afterwards, as if the process died right before truncating it, replays
+    // commits that are already in the new snapshot, which is harmless.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "baz", &Value::Str("hello")).expect("wrote");
+    writer.commit().expect("committed");
+    let logged = fs::read(&log).expect("read");
+    k.sync(true).expect("synced");
+    drop(k);
+    fs::write(&log, &logged).expect("written");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("reopened");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(5678)));
+    assert_eq!(sk.get(&reader, "bar").expect("read"), Some(Value::Bool(true)));
+    assert_eq!(sk.get(&reader, "baz").expect("read"), Some(Value::Str("hello")));
+}
+
+/// The serialized fields of a database, as written by older versions.
+#[derive(Serialize)]
+struct LegacySnapshot {
+    flags: u32,
+    #[cfg(not(feature = "db-dup-sort"))]
+    map: BTreeMap<Vec<u8>, Vec<u8>>,
+    #[cfg(feature = "db-dup-sort")]
+    map: BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>,
+}
+
+/// Frames a payload with the header of the given version of the data file.
+fn framed(version: u32, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = b"RKVSAFE\0".to_vec();
+    bytes.write_u32::<LittleEndian>(version).expect("written");
+    bytes.write_u32::<LittleEndian>(0).expect("written");
+    bytes.write_u64::<LittleEndian>(payload.len() as u64).expect("written");
+    bytes.write_u32::<LittleEndian>(crc32fast::hash(payload)).expect("written");
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+#[test]
+fn test_read_legacy_file_safe() {
+    // Older versions wrote all the databases to the data file, either without any
+    // header, or with a header of version 1.
+    let value = Value::I64(1234).to_bytes().expect("encoded");
+    #[cfg(not(feature = "db-dup-sort"))]
+    let map = vec![(b"foo".to_vec(), value)].into_iter().collect();
+    #[cfg(feature = "db-dup-sort")]
+    let map = vec![(b"foo".to_vec(), vec![value].into_iter().collect())].into_iter().collect();
+    let snapshot = LegacySnapshot {
+        flags: 0,
+        map,
+    };
+    let dbs: HashMap<_, _> = vec![(Some("sk".to_string()), snapshot)].into_iter().collect();
+    let payload = bincode::serialize(&dbs).expect("serialized");
+    let framed = framed(1, &payload);
+
+    for bytes in vec![payload, framed] {
+        let root = Builder::new().prefix("test_read_legacy_file_safe").tempdir().expect("tempdir");
+        let safebin = root.path().join("data.safe.bin");
+        fs::write(&safebin, &bytes).expect("written");
+
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+        let reader = k.read().expect("reader");
+        assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+        reader.abort();
+
+        // The databases are moved to files of their own on the next checkpoint.
+        k.sync(true).expect("synced");
+        let bytes = fs::read(&safebin).expect("read");
+        assert!(bytes.starts_with(b"RKVSAFE\0"));
+        assert_eq!(LittleEndian::read_u32(&bytes[8..12]), 2);
+        assert!(root.path().join("data.safe.0.bin").exists());
+
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+        let reader = k.read().expect("reader");
+        assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    }
+}
+
+#[test]
+fn test_discard_if_checksum_mismatch_safe() {
+    let root = Builder::new().prefix("test_discard_if_checksum_mismatch_safe").tempdir().expect("tempdir");
+    let safebin = root.path().join("data.safe.bin");
+
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        writer.commit().expect("committed");
+        k.sync(true).expect("synced");
+    }
+
+    // Flip a bit in the stored value. The payload still decodes just fine.
+    let mut bytes = fs::read(&safebin).expect("read");
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    fs::write(&safebin, &bytes).expect("written");
+
+    match Rkv::new::<SafeMode>(root.path()) {
+        Err(StoreError::DatabaseCorrupted) => {},
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_discard_if_corrupted(true);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    assert!(k.get_dbs().expect("dbs").is_empty());
+}
+
+#[test]
+fn test_database_files_safe() {
+    let root = Builder::new().prefix("test_database_files_safe").tempdir().expect("tempdir");
+
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let a = k.open_single("a", StoreOptions::create()).expect("opened");
+        let b = k.open_single("b", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        a.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        b.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+        writer.commit().expect("committed");
+        k.sync(true).expect("synced");
+    }
+    let mut files: Vec<_> =
+        fs::read_dir(root.path()).expect("listed").map(|entry| entry.expect("entry").file_name()).collect();
+    files.sort();
+    assert_eq!(files, ["data.safe.0.bin", "data.safe.1.bin", "data.safe.bin", "data.safe.log", "lock.safe"]);
+
+    // Break both databases. As long as neither is opened, nothing reads their files.
+    fs::write(root.path().join("data.safe.0.bin"), b"garbage").expect("written");
+    fs::write(root.path().join("data.safe.1.bin"), b"garbage").expect("written");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    assert_eq!(k.get_dbs().expect("dbs").len(), 2);
+
+    // Only the databases that changed are written by a checkpoint.
+    let c = k.open_single("c", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    c.put(&mut writer, "foo", &Value::Bool(true)).expect("wrote");
+    writer.commit().expect("committed");
+    k.sync(true).expect("synced");
+    assert_eq!(fs::read(root.path().join("data.safe.0.bin")).expect("read"), b"garbage");
+    assert_eq!(fs::read(root.path().join("data.safe.1.bin")).expect("read"), b"garbage");
+    assert!(root.path().join("data.safe.2.bin").exists());
+
+    match k.open_single("a", StoreOptions::default()) {
+        Err(StoreError::DatabaseCorrupted) => {},
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_logged_changes_to_unloaded_database_safe() {
+    let root = Builder::new().prefix("test_logged_changes_to_unloaded_database_safe").tempdir().expect("tempdir");
+
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let a = k.open_single("a", StoreOptions::create()).expect("opened");
+        let b = k.open_single("b", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        a.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        b.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+        writer.commit().expect("committed");
+    }
+
+    // The changes to `b` are only in the log, and are folded into its file by the
+    // next checkpoint, even though it wasn't opened.
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let a = k.open_single("a", StoreOptions::default()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        a.put(&mut writer, "bar", &Value::I64(9012)).expect("wrote");
+        writer.commit().expect("committed");
+        k.sync(true).expect("synced");
+    }
+    assert_eq!(fs::metadata(root.path().join("data.safe.log")).expect("metadata").len(), 0);
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let a = k.open_single("a", StoreOptions::default()).expect("opened");
+    let b = k.open_single("b", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(a.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    assert_eq!(a.get(&reader, "bar").expect("read"), Some(Value::I64(9012)));
+    assert_eq!(b.get(&reader, "foo").expect("read"), Some(Value::I64(5678)));
+}
+
 #[test]
 fn test_iter_safe() {
     let root = Builder::new().prefix("test_iter_safe").tempdir().expect("tempdir");
@@ -989,11 +1365,10 @@ fn test_store_multiple_thread_safe() {
         read_handles.push(thread::spawn(move || {
             let rkv = rkv_arc.read().expect("rkv");
             let reader = rkv.read().expect("reader");
-            let value = match store.get(&reader, i.to_string()) {
-                Ok(Some(Value::U64(value))) => value,
-                Ok(Some(_)) => panic!("value type unexpected"),
-                Ok(None) => panic!("value not found"),
-                Err(err) => panic!(err),
+            let value = match store.get(&reader, i.to_string()).expect("read") {
+                Some(Value::U64(value)) => value,
+                Some(_) => panic!("value type unexpected"),
+                None => panic!("value not found"),
             };
             assert_eq!(value, i);
             value
@@ -1088,3 +1463,1403 @@ fn test_use_value_as_key_safe() {
         writer.commit().expect("committed");
     }
 }
+
+fn list_dir(path: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(path)
+        .expect("listed")
+        .map(|entry| entry.expect("entry").file_name().into_string().expect("name"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_read_only_safe() {
+    let root = Builder::new().prefix("test_read_only_safe").tempdir().expect("tempdir");
+
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        writer.commit().expect("committed");
+    }
+
+    let files = list_dir(root.path());
+    let contents: Vec<_> = files.iter().map(|name| fs::read(root.path().join(name)).expect("read")).collect();
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_flags(EnvironmentFlags::READ_ONLY);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+
+    // Reading works as usual.
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    reader.abort();
+
+    // Anything that would need to write to disk is refused.
+    assert!(matches!(k.write(), Err(StoreError::SafeModeError(SafeModeError::EnvIsReadOnly))));
+    match k.open_single("other", StoreOptions::create()) {
+        Err(StoreError::SafeModeError(SafeModeError::EnvIsReadOnly)) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    match k.sync(true) {
+        Err(StoreError::SafeModeError(SafeModeError::EnvIsReadOnly)) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    drop(k);
+    assert_eq!(list_dir(root.path()), files);
+    for (name, contents) in files.iter().zip(contents.iter()) {
+        assert_eq!(&fs::read(root.path().join(name)).expect("read"), contents);
+    }
+}
+
+#[test]
+fn test_read_only_empty_safe() {
+    let root = Builder::new().prefix("test_read_only_empty_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_flags(EnvironmentFlags::READ_ONLY);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    assert!(k.get_dbs().expect("dbs").is_empty());
+
+    // Not even the lock file is created.
+    drop(k);
+    assert!(list_dir(root.path()).is_empty());
+}
+
+#[test]
+fn test_read_only_before_writer_safe() {
+    let root = Builder::new().prefix("test_read_only_before_writer_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_flags(EnvironmentFlags::READ_ONLY);
+    let read_only = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+
+    // The writer creates the lock file after the read-only environment was opened,
+    // which still picks up its commits.
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let sk = read_only.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = read_only.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+}
+
+#[test]
+fn test_no_sub_dir_safe() {
+    let root = Builder::new().prefix("test_no_sub_dir_safe").tempdir().expect("tempdir");
+    let path = root.path().join("data.bin");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+
+    // The path must not be a directory.
+    match Rkv::from_builder(root.path(), builder) {
+        Err(StoreError::UnsuitableEnvironmentPath(_)) => {},
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+
+    {
+        let k = Rkv::from_builder(&path, builder).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        writer.commit().expect("committed");
+        k.sync(true).expect("synced");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "bar", &Value::Bool(true)).expect("wrote");
+        writer.commit().expect("committed");
+    }
+    assert_eq!(list_dir(root.path()), vec!["data.bin", "data.bin-0", "data.bin-lock", "data.bin-log"]);
+
+    let k = Rkv::from_builder(&path, builder).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    assert_eq!(sk.get(&reader, "bar").expect("read"), Some(Value::Bool(true)));
+    reader.abort();
+
+    k.close(CloseOptions::delete_files_on_disk()).expect("closed");
+    assert!(list_dir(root.path()).is_empty());
+}
+
+#[test]
+fn test_no_lock_no_sync_safe() {
+    let root = Builder::new().prefix("test_no_lock_no_sync_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_flags(SafeModeEnvironmentFlags::NO_LOCK | SafeModeEnvironmentFlags::NO_SYNC);
+
+    {
+        let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        writer.commit().expect("committed");
+    }
+    assert_eq!(list_dir(root.path()), vec!["data.safe.log"]);
+
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+}
+
+#[test]
+fn test_ignored_flags_safe() {
+    let root = Builder::new().prefix("test_ignored_flags_safe").tempdir().expect("tempdir");
+
+    for flag in [
+        EnvironmentFlags::FIXED_MAP,
+        EnvironmentFlags::WRITE_MAP,
+        EnvironmentFlags::MAP_ASYNC,
+        EnvironmentFlags::NO_TLS,
+        EnvironmentFlags::NO_READAHEAD,
+        EnvironmentFlags::NO_MEM_INIT,
+    ] {
+        let mut builder = Rkv::environment_builder::<SafeMode>();
+        builder.set_flags(flag);
+        let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+        check_rkv(&k);
+    }
+}
+
+#[test]
+fn test_shared_environment_safe() {
+    let root = Builder::new().prefix("test_shared_environment_safe").tempdir().expect("tempdir");
+
+    // Two environments opened on the same path behave like two processes would.
+    let k1 = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let k2 = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk1 = k1.open_single("sk", StoreOptions::create()).expect("opened");
+    let sk2 = k2.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k1.write().expect("writer");
+    sk1.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // The second environment notices the commit and reloads.
+    let reader = k2.read().expect("reader");
+    assert_eq!(sk2.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    reader.abort();
+
+    // Writers build upon each other's commits instead of overwriting them.
+    let mut writer = k2.write().expect("writer");
+    sk2.put(&mut writer, "bar", &Value::Bool(true)).expect("wrote");
+    writer.commit().expect("committed");
+    let mut writer = k1.write().expect("writer");
+    sk1.put(&mut writer, "baz", &Value::Str("héllo")).expect("wrote");
+    writer.commit().expect("committed");
+    k2.sync(true).expect("synced");
+
+    for k in [&k1, &k2, &Rkv::new::<SafeMode>(root.path()).expect("new succeeded")].iter() {
+        let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+        let reader = k.read().expect("reader");
+        assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+        assert_eq!(sk.get(&reader, "bar").expect("read"), Some(Value::Bool(true)));
+        assert_eq!(sk.get(&reader, "baz").expect("read"), Some(Value::Str("héllo")));
+    }
+}
+
+#[test]
+fn test_exclusive_writer_safe() {
+    let root = Builder::new().prefix("test_exclusive_writer_safe").tempdir().expect("tempdir");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let other_k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let other_sk = other_k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+
+    let started = Arc::new(AtomicBool::new(false));
+    let other = {
+        let started = started.clone();
+        thread::spawn(move || {
+            let k = other_k;
+            let sk = other_sk;
+
+            // Readers don't wait for the writer, and don't see its changes.
+            let reader = k.read().expect("reader");
+            assert_eq!(sk.get(&reader, "foo").expect("read"), None);
+            reader.abort();
+
+            let mut writer = k.write().expect("writer");
+            started.store(true, Ordering::SeqCst);
+            assert_eq!(sk.get(&writer, "foo").expect("read"), Some(Value::I64(1234)));
+            sk.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+            writer.commit().expect("committed");
+        })
+    };
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(!started.load(Ordering::SeqCst));
+    writer.commit().expect("committed");
+    other.join().expect("joined");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(5678)));
+}
+
+#[test]
+fn test_lock_file_on_disk_safe() {
+    let root = Builder::new().prefix("test_lock_file_on_disk_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+    k.sync(true).expect("synced");
+    assert!(root.path().join("lock.safe").exists());
+
+    k.close(CloseOptions::delete_files_on_disk()).expect("closed");
+    assert_eq!(fs::read_dir(root.path()).expect("listed").count(), 0);
+}
+
+#[test]
+fn test_stat_safe() {
+    let root = Builder::new().prefix("test_stat_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+
+    let stat = k.stat().expect("stat");
+    assert_eq!(stat.entries(), 0);
+    assert_eq!(stat.bytes_on_disk(), 0);
+    assert_eq!(stat.leaf_pages(), 0);
+
+    for i in 0..5 {
+        let sk = k.open_single(&format!("sk{}", i)[..], StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(i)).expect("wrote");
+        sk.put(&mut writer, "bar", &Value::I64(i)).expect("wrote");
+        writer.commit().expect("committed");
+    }
+
+    // Like with LMDB, the environment counts the named stores, and the pairs of the
+    // unnamed one.
+    let stat = k.stat().expect("stat");
+    assert_eq!(stat.depth(), 1);
+    assert_eq!(stat.entries(), 5);
+    assert_eq!(stat.branch_pages(), 0);
+    assert_eq!(stat.overflow_pages(), 0);
+    assert!(stat.bytes_on_disk() > 0);
+    assert_eq!(stat.leaf_pages(), 1);
+
+    let sk = k.open_single(None, StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    sk.put(&mut writer, "bar", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+    assert_eq!(k.stat().expect("stat").entries(), 7);
+
+    // Checkpointing moves the data around without changing the entries, which are then
+    // known without reading the stores back.
+    k.sync(true).expect("synced");
+    assert_eq!(k.stat().expect("stat").entries(), 7);
+    drop(k);
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    assert_eq!(k.stat().expect("stat").entries(), 7);
+    let sk = k.open_single("sk0", StoreOptions::default()).expect("opened");
+    assert_eq!(k.stat_db(&sk).expect("stat").entries(), 2);
+}
+
+#[test]
+fn test_stat_shared_environment_safe() {
+    let root = Builder::new().prefix("test_stat_shared_environment_safe").tempdir().expect("tempdir");
+    let k1 = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let k2 = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk1 = k1.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k1.write().expect("writer");
+    sk1.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+    k1.sync(true).expect("synced");
+
+    // The other environment catches up before reading the checkpointed file.
+    let sk2 = k2.open_single("sk", StoreOptions::default()).expect("opened");
+    assert_eq!(k2.stat_db(&sk2).expect("stat").entries(), 1);
+
+    // Statistics can be read while writing, without waiting for the writer's own lock.
+    let mut writer = k2.write().expect("writer");
+    sk2.put(&mut writer, "bar", &Value::Bool(true)).expect("wrote");
+    assert_eq!(k2.stat().expect("stat").entries(), 1);
+    writer.commit().expect("committed");
+    k2.sync(true).expect("synced");
+
+    assert_eq!(k1.stat().expect("stat").entries(), 1);
+    assert_eq!(k1.stat_db(&sk1).expect("stat").entries(), 2);
+}
+
+#[test]
+fn test_stat_db_safe() {
+    let root = Builder::new().prefix("test_stat_db_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+
+    let stat = k.stat_db(&sk).expect("stat");
+    assert_eq!(stat.entries(), 0);
+    assert_eq!(stat.leaf_pages(), 1);
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str("bar")).expect("wrote");
+    sk.put(&mut writer, "big", &Value::Str(&"x".repeat(10000))).expect("wrote");
+    mk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    mk.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    mk.put(&mut writer, "bar", &Value::I64(3)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Each store only accounts for its own entries, while the environment counts the
+    // stores themselves.
+    let stat = k.stat_db(&sk).expect("stat");
+    assert_eq!(stat.entries(), 2);
+    assert!(stat.bytes_on_disk() > 10000);
+    assert_eq!(stat.leaf_pages(), 3);
+    let stat = k.stat_db(&mk).expect("stat");
+    assert_eq!(stat.entries(), 3);
+    assert_eq!(stat.leaf_pages(), 1);
+    assert_eq!(k.stat().expect("stat").entries(), 2);
+}
+
+#[test]
+fn test_info_safe() {
+    let root = Builder::new().prefix("test_info_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let info = k.info().expect("info");
+    assert_eq!(info.map_size(), usize::MAX);
+    assert_eq!(info.max_readers(), usize::MAX);
+    assert_eq!(info.last_txnid(), 0);
+    assert_eq!(info.last_pgno(), 0);
+    assert_eq!(info.num_readers(), 0);
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str("bar")).expect("wrote");
+    writer.commit().expect("committed");
+
+    let info = k.info().expect("info");
+    assert_eq!(info.last_txnid(), 1);
+    assert!(info.bytes_on_disk() > 0);
+
+    // A new reader should increment the reader counter.
+    let reader = k.read().expect("reader");
+    assert_eq!(k.info().expect("info").num_readers(), 1);
+    reader.abort();
+    assert_eq!(k.info().expect("info").num_readers(), 0);
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_map_size(1024 * 1024);
+    builder.set_max_readers(42);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let info = k.info().expect("info");
+    assert_eq!(info.map_size(), 1024 * 1024);
+    assert_eq!(info.max_readers(), 42);
+    assert_eq!(info.last_txnid(), 1);
+}
+
+#[test]
+fn test_drop_and_rename_db_shared_safe() {
+    let root = Builder::new().prefix("test_drop_and_rename_db_shared_safe").tempdir().expect("tempdir");
+
+    let k1 = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let a = k1.open_single("a", StoreOptions::create()).expect("opened");
+    let b = k1.open_single("b", StoreOptions::create()).expect("opened");
+    let mut writer = k1.write().expect("writer");
+    a.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    b.put(&mut writer, "bar", &Value::Str("baz")).expect("wrote");
+    writer.commit().expect("committed");
+    k1.sync(true).expect("synced");
+
+    let k2 = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let a2 = k2.open_single("a", StoreOptions::default()).expect("opened");
+    k1.drop_db("a").expect("dropped");
+    k1.rename_db("b", "c").expect("renamed");
+
+    // Other environments on the same path pick up the changes, like other processes.
+    assert_eq!(k2.get_dbs().expect("dbs"), [Some("c".to_owned())]);
+    let reader = k2.read().expect("reader");
+    a2.get(&reader, "foo").expect_err("read");
+    reader.abort();
+
+    // The catalog still lists the dropped database until the next checkpoint, which
+    // deletes its file.
+    let k3 = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    assert_eq!(k3.get_dbs().expect("dbs"), [Some("c".to_owned())]);
+    let db_files = |names: Vec<String>| names.into_iter().filter(|name| name.ends_with(".bin")).count();
+    assert_eq!(db_files(list_dir(root.path())), 3);
+    k3.sync(true).expect("synced");
+    assert_eq!(db_files(list_dir(root.path())), 2);
+
+    let c = k3.open_single("c", StoreOptions::default()).expect("opened");
+    let reader = k3.read().expect("reader");
+    assert_eq!(c.get(&reader, "bar").expect("read"), Some(Value::Str("baz")));
+}
+
+#[test]
+fn test_copy_to_while_writing_safe() {
+    let root = Builder::new().prefix("test_copy_to_while_writing_safe").tempdir().expect("tempdir");
+    let copy = Builder::new().prefix("test_copy_to_while_writing_safe_copy").tempdir().expect("tempdir");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Unlike with LMDB, the copy doesn't wait for the write transaction of this thread,
+    // and leaves out its changes.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+    k.copy_to(copy.path(), CopyOptions::default()).expect("copied");
+    writer.commit().expect("committed");
+
+    let mut files: Vec<_> = fs::read_dir(copy.path()).expect("listed").map(|e| e.expect("entry").file_name()).collect();
+    files.sort();
+    assert_eq!(files, ["data.safe.0.bin", "data.safe.bin"]);
+
+    let k = Rkv::new::<SafeMode>(copy.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+}
+
+#[test]
+fn test_readers_safe() {
+    let root = Builder::new().prefix("test_readers_safe").tempdir().expect("tempdir");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    assert_eq!(k.readers().expect("readers"), vec![]);
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str("bar")).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Readers on different threads are told apart.
+    let reader = k.read().expect("reader");
+    let other_thread = thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                let _reader = k.read().expect("reader");
+                k.readers().expect("readers")
+            })
+            .join()
+            .expect("joined")
+    });
+    assert_eq!(other_thread.len(), 2);
+    assert!(other_thread.iter().all(|slot| slot.pid == process::id()));
+    assert!(other_thread.iter().all(|slot| slot.txnid == Some(k.info().expect("info").last_txnid())));
+    assert_ne!(other_thread[0].thread, other_thread[1].thread);
+
+    let readers = k.readers().expect("readers");
+    assert_eq!(readers.len(), 1);
+    assert!(other_thread.contains(&readers[0]));
+
+    // Readers go away as soon as they're done, and never outlive their process.
+    reader.abort();
+    assert_eq!(k.readers().expect("readers"), vec![]);
+    assert_eq!(k.reader_check().expect("checked"), 0);
+}
+
+#[test]
+fn test_exceed_map_size_safe() {
+    let root = Builder::new().prefix("test_exceed_map_size_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_map_size(1024);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str("bar")).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Writing a large enough value fails, and so does committing the transaction.
+    let mut writer = k.write().expect("writer");
+    match sk.put(&mut writer, "big", &Value::Str(&"x".repeat(1024))) {
+        Err(StoreError::MapFull) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    match writer.commit() {
+        Err(StoreError::MapFull) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    // Deleting enough data makes room again within the same transaction.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "big", &Value::Str(&"x".repeat(1024))).expect_err("map full");
+    sk.delete(&mut writer, "big").expect("deleted");
+    sk.put(&mut writer, "baz", &Value::Str("qux")).expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::Str("bar")));
+    assert_eq!(sk.get(&reader, "big").expect("read"), None);
+    assert_eq!(sk.get(&reader, "baz").expect("read"), Some(Value::Str("qux")));
+}
+
+#[test]
+fn test_set_map_size_safe() {
+    let root = Builder::new().prefix("test_set_map_size_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_map_size(1024);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let val = "x".repeat(1024);
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str(&val)).expect_err("map full");
+    writer.abort();
+
+    k.set_map_size(4096).expect("resized");
+    assert_eq!(k.info().expect("info").map_size(), 4096);
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::Str(&val)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // The size of databases which aren't loaded yet counts as well.
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_map_size(4096);
+    k.sync(true).expect("synced");
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let other = k.open_single("other", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    other.put(&mut writer, "foo", &Value::Str(&val)).expect("wrote");
+    other.put(&mut writer, "bar", &Value::Str(&val)).expect("wrote");
+    other.put(&mut writer, "baz", &Value::Str(&val)).expect_err("map full");
+}
+
+#[test]
+fn test_write_batch_grows_map_safe() {
+    let root = Builder::new().prefix("test_write_batch_grows_map_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_map_size(1024);
+    builder.set_map_growth(MapGrowth::new(2.0, 8192));
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    // Unlike with LMDB, the map grows even while readers are active.
+    let _reader = k.read().expect("reader");
+    let val = "x".repeat(3000);
+    let mut calls = 0;
+    k.write_batch(|writer| {
+        calls += 1;
+        sk.put(writer, "foo", &Value::Str(&val))
+    })
+    .expect("wrote");
+    assert_eq!(calls, 3);
+    assert_eq!(k.info().expect("info").map_size(), 4096);
+
+    match k.write_batch(|writer| sk.put(writer, "bar", &Value::Str(&"x".repeat(8192)))) {
+        Err(StoreError::MapFull) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(k.info().expect("info").map_size(), 8192);
+}
+
+#[test]
+fn test_write_batch_grows_map_by_load_ratio_safe() {
+    let root = Builder::new().prefix("test_write_batch_grows_map_by_load_ratio_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_map_size(1024);
+    builder.set_map_growth(MapGrowth {
+        load_ratio: Some(0.5),
+        ..MapGrowth::new(2.0, 8192)
+    });
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    k.write_batch(|writer| sk.put(writer, "foo", &Value::Str(&"x".repeat(600)))).expect("wrote");
+    assert_eq!(k.info().expect("info").map_size(), 1024);
+    assert!(k.load_ratio().expect("ratio").unwrap() > 0.5);
+
+    // The map is more than half full, so it grows before the next transaction.
+    let mut calls = 0;
+    k.write_batch(|writer| {
+        calls += 1;
+        sk.put(writer, "bar", &Value::Str("baz"))
+    })
+    .expect("wrote");
+    assert_eq!(calls, 1);
+    assert_eq!(k.info().expect("info").map_size(), 2048);
+    assert!(k.load_ratio().expect("ratio").unwrap() < 0.5);
+
+    // Without a map size, the map never gets full.
+    let root = Builder::new().prefix("test_write_batch_grows_map_by_load_ratio_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    assert_eq!(k.load_ratio().expect("ratio"), None);
+}
+
+#[test]
+fn test_readers_full_safe() {
+    let root = Builder::new().prefix("test_readers_full_safe").tempdir().expect("tempdir");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_max_readers(2);
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let _ = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let first = k.read().expect("reader");
+    let second = k.read().expect("reader");
+    match k.read() {
+        Err(StoreError::ReadersFull) => {},
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    assert_eq!(k.info().expect("info").num_readers(), 2);
+
+    // Finishing a read transaction frees up its slot.
+    first.abort();
+    let _third = k.read().expect("reader");
+    drop(second);
+}
+
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_dup_fixed_safe() {
+    let root = Builder::new().prefix("test_dup_fixed_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let mut options = StoreOptions::<SafeModeDatabaseFlags>::create();
+    options.flags.set(SafeModeDatabaseFlags::DUP_FIXED, true);
+    let mk = k.open_multi("mk", options).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    mk.put(&mut writer, "foo", &Value::U64(2)).expect("wrote");
+    mk.put(&mut writer, "foo", &Value::U64(1)).expect("wrote");
+    match mk.put(&mut writer, "foo", &Value::Str("bar")) {
+        Err(StoreError::KeyValuePairBadSize) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    // The values of other keys can have a different size.
+    mk.put(&mut writer, "bar", &Value::Str("bar")).expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let values: Vec<_> = mk.get(&reader, "foo").expect("read").map(|result| result.expect("value").1).collect();
+    assert_eq!(values, vec![Value::U64(1), Value::U64(2)]);
+}
+
+#[test]
+fn test_rw_cursor_without_current_safe() {
+    let root = Builder::new().prefix("test_rw_cursor_without_current_safe").tempdir().expect("tempdir");
+    let env = SafeMode::new().open(root.path()).expect("opened");
+    let db = env.create_db(None, SafeModeDatabaseFlags::empty()).expect("created");
+    let mut txn = env.begin_rw_txn().expect("began");
+    txn.put(&db, b"foo", b"bar", SafeModeWriteFlags::empty()).expect("wrote");
+
+    // There's nothing to change before moving to the first pair, nor after deleting it.
+    let mut cursor = txn.open_rw_cursor(&db).expect("opened");
+    assert!(matches!(cursor.del_current(), Err(SafeModeError::KeyValuePairNotFound)));
+    assert!(matches!(cursor.put_current(b"baz"), Err(SafeModeError::KeyValuePairNotFound)));
+    assert_eq!(cursor.next().expect("pair").expect("read"), (&b"foo"[..], &b"bar"[..]));
+    cursor.del_current().expect("deleted");
+    assert!(matches!(cursor.del_current(), Err(SafeModeError::KeyValuePairNotFound)));
+    assert!(matches!(cursor.put_current(b"baz"), Err(SafeModeError::KeyValuePairNotFound)));
+    assert!(cursor.next().is_none());
+    drop(cursor);
+    assert!(matches!(txn.get(&db, b"foo"), Err(SafeModeError::KeyValuePairNotFound)));
+}
+
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_rw_cursor_put_current_dup_sort_safe() {
+    let root = Builder::new().prefix("test_rw_cursor_put_current_dup_sort_safe").tempdir().expect("tempdir");
+    let env = SafeMode::new().open(root.path()).expect("opened");
+    let db = env.create_db(None, SafeModeDatabaseFlags::DUP_SORT).expect("created");
+    let mut txn = env.begin_rw_txn().expect("began");
+    for (key, value) in &[("a", "1"), ("b", "2"), ("b", "4")] {
+        txn.put(&db, key.as_bytes(), value.as_bytes(), SafeModeWriteFlags::empty()).expect("wrote");
+    }
+
+    // The only value of a key can be replaced by any other, but one of several
+    // values can't move to another place among them.
+    let mut cursor = txn.open_rw_cursor(&db).expect("opened");
+    assert_eq!(cursor.next().expect("pair").expect("read"), (&b"a"[..], &b"1"[..]));
+    cursor.put_current(b"9").expect("replaced");
+    assert_eq!(cursor.next().expect("pair").expect("read"), (&b"b"[..], &b"2"[..]));
+    assert!(matches!(cursor.put_current(b"3"), Err(SafeModeError::InvalidArgument)));
+    assert!(matches!(cursor.put_current(b"5"), Err(SafeModeError::InvalidArgument)));
+    cursor.put_current(b"2").expect("replaced");
+    assert_eq!(cursor.next().expect("pair").expect("read"), (&b"b"[..], &b"4"[..]));
+    assert!(cursor.next().is_none());
+    drop(cursor);
+
+    let mut iter = txn.open_ro_cursor(&db).expect("opened").into_iter();
+    let mut pairs = vec![];
+    while let Some(result) = iter.next() {
+        pairs.push(result.expect("read"));
+    }
+    assert_eq!(pairs, vec![(&b"a"[..], &b"9"[..]), (&b"b"[..], &b"2"[..]), (&b"b"[..], &b"4"[..])]);
+}
+
+#[test]
+fn test_put_current_flag_safe() {
+    let root = Builder::new().prefix("test_put_current_flag_safe").tempdir().expect("tempdir");
+    let env = SafeMode::new().open(root.path()).expect("opened");
+    let db = env.create_db(None, SafeModeDatabaseFlags::empty()).expect("created");
+    let mut txn = env.begin_rw_txn().expect("began");
+    txn.put(&db, b"foo", b"bar", SafeModeWriteFlags::empty()).expect("wrote");
+
+    // Replacing the current pair is only possible through a read-write cursor.
+    let mut flags = SafeModeWriteFlags::empty();
+    BackendWriteFlags::set(&mut flags, WriteFlags::CURRENT, true);
+    assert!(matches!(txn.put(&db, b"foo", b"baz", flags), Err(SafeModeError::InvalidArgument)));
+    assert_eq!(txn.get(&db, b"foo").expect("read"), b"bar");
+}
+
+fn write_flags<F: BackendWriteFlags>(flag: WriteFlags) -> F {
+    let mut flags = F::empty();
+    flags.set(flag, true);
+    flags
+}
+
+/// Write flags make writes fail when they would break the rules of the flags, like
+/// with LMDB.
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_write_flags_safe() {
+    let root = Builder::new().prefix("test_write_flags_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    mk.put(&mut writer, "b", &Value::Str("2")).expect("wrote");
+
+    // NO_OVERWRITE rejects keys that already exist.
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("3"), write_flags(WriteFlags::NO_OVERWRITE));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "c", &Value::Str("1"), write_flags(WriteFlags::NO_OVERWRITE)).expect("wrote");
+
+    // NO_DUP_DATA only rejects key/value pairs that already exist.
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("2"), write_flags(WriteFlags::NO_DUP_DATA));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "b", &Value::Str("3"), write_flags(WriteFlags::NO_DUP_DATA)).expect("wrote");
+
+    // APPEND requires keys to be greater than the last one.
+    let result = mk.put_with_flags(&mut writer, "a", &Value::Str("1"), write_flags(WriteFlags::APPEND));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    let result = mk.put_with_flags(&mut writer, "c", &Value::Str("2"), write_flags(WriteFlags::APPEND));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "d", &Value::Str("1"), write_flags(WriteFlags::APPEND)).expect("wrote");
+
+    // APPEND_DUP requires values to be greater than the last one of their key.
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("1"), write_flags(WriteFlags::APPEND_DUP));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    let result = mk.put_with_flags(&mut writer, "b", &Value::Str("3"), write_flags(WriteFlags::APPEND_DUP));
+    assert!(matches!(result, Err(StoreError::KeyExists)));
+    mk.put_with_flags(&mut writer, "b", &Value::Str("4"), write_flags(WriteFlags::APPEND_DUP)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let values: Vec<_> = mk.get(&reader, "b").expect("read").map(|result| result.expect("value").1).collect();
+    assert_eq!(values, vec![Value::Str("2"), Value::Str("3"), Value::Str("4")]);
+    let values: Vec<_> = mk.get(&reader, "c").expect("read").map(|result| result.expect("value").1).collect();
+    assert_eq!(values, vec![Value::Str("1")]);
+    let values: Vec<_> = mk.get(&reader, "d").expect("read").map(|result| result.expect("value").1).collect();
+    assert_eq!(values, vec![Value::Str("1")]);
+}
+
+/// Deletes and replaces key/value pairs while walking over them with a read-write
+/// cursor, which must visit every pair exactly once.
+#[cfg(feature = "db-dup-sort")]
+fn check_rw_cursor(
+    flags: SafeModeDatabaseFlags,
+    expected_visits: Vec<(Vec<u8>, Vec<u8>)>,
+    expected_entries: Vec<(Vec<u8>, Vec<u8>)>,
+) {
+    let root = Builder::new().prefix("test_rw_cursor_safe").tempdir().expect("tempdir");
+    let mut builder = SafeMode::new();
+    builder.set_max_dbs(1);
+    let env = builder.open(root.path()).expect("opened");
+    let db = env.create_db(Some("db"), flags).expect("created");
+
+    let mut txn = env.begin_rw_txn().expect("began");
+    for (key, value) in &[("a", "1"), ("b", "2"), ("c", "3"), ("c", "4"), ("d", "6"), ("e", "7")] {
+        txn.put(&db, key.as_bytes(), value.as_bytes(), BackendFlags::empty()).expect("wrote");
+    }
+
+    // Even values are deleted and the value of "e" is replaced, while the cursor
+    // keeps visiting every pair exactly once.
+    let mut visited = vec![];
+    let mut cursor = txn.open_rw_cursor(&db).expect("opened");
+    while let Some(result) = cursor.next() {
+        let (key, value) = result.expect("read");
+        let (key, value) = (key.to_vec(), value.to_vec());
+        if value[0] % 2 == 0 {
+            cursor.del_current().expect("deleted");
+        } else if key == b"e" {
+            cursor.put_current(b"8").expect("replaced");
+        }
+        visited.push((key, value));
+    }
+    drop(cursor);
+    assert_eq!(visited, expected_visits);
+    txn.commit().expect("committed");
+    drop(env);
+
+    let env = builder.open(root.path()).expect("opened");
+    let db = env.open_db(Some("db")).expect("opened");
+    let txn = env.begin_ro_txn().expect("began");
+    let mut iter = txn.open_ro_cursor(&db).expect("opened").into_iter();
+    let mut entries = vec![];
+    while let Some(result) = iter.next() {
+        let (key, value) = result.expect("read");
+        entries.push((key.to_vec(), value.to_vec()));
+    }
+    assert_eq!(entries, expected_entries);
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    pairs.iter().map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec())).collect()
+}
+
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_rw_cursor_safe() {
+    let visited = pairs(&[("a", "1"), ("b", "2"), ("c", "4"), ("d", "6"), ("e", "7")]);
+    check_rw_cursor(SafeModeDatabaseFlags::empty(), visited, pairs(&[("a", "1"), ("e", "8")]));
+    let visited = pairs(&[("a", "1"), ("b", "2"), ("c", "3"), ("c", "4"), ("d", "6"), ("e", "7")]);
+    check_rw_cursor(SafeModeDatabaseFlags::DUP_SORT, visited, pairs(&[("a", "1"), ("c", "3"), ("e", "8")]));
+}
+
+/// Copies an environment while one of its databases isn't loaded, then checks that the
+/// copy has all the data, and is left alone by later commits.
+fn check_copy_to(options: CopyOptions) {
+    let root = Builder::new().prefix("test_copy_to_safe").tempdir().expect("tempdir");
+    let copy = Builder::new().prefix("test_copy_to_safe_copy").tempdir().expect("tempdir");
+    {
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let a = k.open_single("a", StoreOptions::create()).expect("opened");
+        let b = k.open_single("b", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        a.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        b.put(&mut writer, "bar", &Value::Str("baz")).expect("wrote");
+        writer.commit().expect("committed");
+        k.sync(true).expect("synced");
+    }
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let a = k.open_single("a", StoreOptions::create()).expect("opened");
+    k.copy_to(copy.path(), options).expect("copied");
+    k.copy_to(copy.path(), options).expect_err("copied twice");
+    let mut writer = k.write().expect("writer");
+    a.put(&mut writer, "foo", &Value::I64(5678)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let k = Rkv::new::<SafeMode>(copy.path()).expect("new succeeded");
+    let a = k.open_single("a", StoreOptions::default()).expect("opened");
+    let b = k.open_single("b", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(a.get(&reader, "foo").expect("read"), Some(Value::I64(1234)));
+    assert_eq!(b.get(&reader, "bar").expect("read"), Some(Value::Str("baz")));
+}
+
+#[test]
+fn test_copy_to_safe() {
+    check_copy_to(CopyOptions::default());
+    check_copy_to(CopyOptions::compact());
+}
+
+#[test]
+fn test_drop_and_rename_db_safe() {
+    let root = Builder::new().prefix("test_drop_and_rename_db_safe").tempdir().expect("tempdir");
+    {
+        let k = Rkv::with_capacity::<SafeMode>(root.path(), 3).expect("new succeeded");
+        let a = k.open_single("a", StoreOptions::create()).expect("opened");
+        let b = k.open_single("b", StoreOptions::create()).expect("opened");
+        let m = k.open_multi("m", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        a.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+        b.put(&mut writer, "bar", &Value::Str("baz")).expect("wrote");
+        m.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+        m.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+        writer.commit().expect("committed");
+
+        let reader = k.read().expect("reader");
+        match k.drop_db("a") {
+            Err(StoreError::OpenAttemptedDuringTransaction(_)) => {},
+            result => panic!("expected OpenAttemptedDuringTransaction, got {:?}", result),
+        }
+        drop(reader);
+
+        k.drop_db("a").expect("dropped");
+        k.drop_db("a").expect_err("dropped twice");
+        k.open_single("a", StoreOptions::default()).expect_err("opened");
+        match k.rename_db("m", "b") {
+            Err(StoreError::KeyExists) => {},
+            result => panic!("expected KeyExists, got {:?}", result),
+        }
+        k.rename_db("m", "n").expect("renamed");
+        k.open_multi("m", StoreOptions::default()).expect_err("opened");
+        k.sync(true).expect("synced");
+    }
+
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 3).expect("new succeeded");
+    let mut dbs = k.get_dbs().expect("dbs");
+    dbs.sort();
+    assert_eq!(dbs, [Some("b".to_owned()), Some("n".to_owned())]);
+
+    // The renamed database keeps its contents and flags.
+    let b = k.open_single("b", StoreOptions::default()).expect("opened");
+    let n = k.open_multi("n", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(b.get(&reader, "bar").expect("read"), Some(Value::Str("baz")));
+    let values: Vec<_> = n.get(&reader, "foo").expect("read").map(|item| item.expect("value").1).collect();
+    assert_eq!(values, [Value::I64(1), Value::I64(2)]);
+    reader.abort();
+
+    // Dropping a database makes room for another one.
+    k.open_single("c", StoreOptions::create()).expect("opened");
+    match k.open_single("d", StoreOptions::create()) {
+        Err(StoreError::DbsFull) => {},
+        result => panic!("expected DbsFull, got {:?}", result),
+    }
+    k.drop_db("b").expect("dropped");
+    k.open_single("d", StoreOptions::create()).expect("opened");
+}
+
+#[test]
+fn test_nested_writer_safe() {
+    let root = Builder::new().prefix("test_nested_writer_safe").tempdir().expect("tempdir");
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    mk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+
+    // Aborting a nested writer only rolls back its own changes.
+    let mut nested = writer.begin_nested().expect("nested");
+    assert_eq!(sk.get(&nested, "foo").expect("read"), Some(Value::I64(1)));
+    sk.put(&mut nested, "bar", &Value::I64(2)).expect("wrote");
+    mk.put(&mut nested, "foo", &Value::I64(2)).expect("wrote");
+    sk.clear(&mut nested).expect("cleared");
+    assert_eq!(sk.get(&nested, "foo").expect("read"), None);
+    nested.abort();
+    assert_eq!(sk.get(&writer, "foo").expect("read"), Some(Value::I64(1)));
+    assert_eq!(sk.get(&writer, "bar").expect("read"), None);
+    assert_eq!(mk.get(&writer, "foo").expect("read").count(), 1);
+
+    // Nested writers can be nested too, and are aborted when dropped.
+    let mut nested = writer.begin_nested().expect("nested");
+    sk.put(&mut nested, "baz", &Value::I64(3)).expect("wrote");
+    mk.put(&mut nested, "foo", &Value::I64(3)).expect("wrote");
+    {
+        let mut inner = nested.begin_nested().expect("nested");
+        sk.put(&mut inner, "qux", &Value::I64(4)).expect("wrote");
+        assert_eq!(sk.get(&inner, "baz").expect("read"), Some(Value::I64(3)));
+    }
+    let mut inner = nested.begin_nested().expect("nested");
+    sk.delete(&mut inner, "foo").expect("deleted");
+    inner.commit().expect("committed");
+    nested.commit().expect("committed");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk.iter_start(&reader).expect("iter").map(|item| item.expect("item").0.to_vec()).collect();
+    assert_eq!(keys, [b"baz".to_vec()]);
+    let values: Vec<_> = mk.get(&reader, "foo").expect("read").map(|item| item.expect("value").1).collect();
+    assert_eq!(values, [Value::I64(1), Value::I64(3)]);
+    reader.abort();
+
+    // Committing a writer commits the changes of the nested ones left behind.
+    let mut writer = k.write().expect("writer");
+    let mut nested = writer.begin_nested().expect("nested");
+    sk.put(&mut nested, "foo", &Value::I64(5)).expect("wrote");
+    std::mem::forget(nested);
+    writer.commit().expect("committed");
+    drop(k);
+
+    // What was committed is what was written.
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk.iter_start(&reader).expect("iter").map(|item| item.expect("item").0.to_vec()).collect();
+    assert_eq!(keys, [b"baz".to_vec(), b"foo".to_vec()]);
+    assert_eq!(sk.get(&reader, "foo").expect("read"), Some(Value::I64(5)));
+    assert_eq!(mk.get(&reader, "foo").expect("read").count(), 2);
+}
+
+#[cfg(feature = "db-dup-sort")]
+type Found<'c, E> = Option<Result<(&'c [u8], &'c [u8]), E>>;
+
+#[cfg(feature = "db-dup-sort")]
+fn found<E: std::fmt::Debug>(found: Found<'_, E>) -> Option<(&str, &str)> {
+    found.map(|result| {
+        let (key, value) = result.expect("read");
+        (std::str::from_utf8(key).expect("utf8"), std::str::from_utf8(value).expect("utf8"))
+    })
+}
+
+/// Positioned cursors move between keys and between the values of a key, and find
+/// nothing when there is nowhere left to go.
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_positioned_cursor_safe() {
+    let root = Builder::new().prefix("test_positioned_cursor_safe").tempdir().expect("tempdir");
+    let mut builder = SafeMode::new();
+    builder.set_max_dbs(3);
+    let env = builder.open(root.path()).expect("opened");
+    let dups = env.create_db(Some("dups"), SafeModeDatabaseFlags::DUP_SORT).expect("created");
+    let single = env.create_db(Some("single"), SafeModeDatabaseFlags::empty()).expect("created");
+    let empty = env.create_db(Some("empty"), SafeModeDatabaseFlags::empty()).expect("created");
+
+    let mut txn = env.begin_rw_txn().expect("began");
+    for (key, value) in &[("a", "1"), ("c", "3"), ("c", "4"), ("c", "5"), ("e", "7")] {
+        txn.put(&dups, key.as_bytes(), value.as_bytes(), BackendFlags::empty()).expect("wrote");
+        txn.put(&single, key.as_bytes(), value.as_bytes(), BackendFlags::empty()).expect("wrote");
+    }
+    txn.commit().expect("committed");
+
+    let txn = env.begin_ro_txn().expect("began");
+    let mut cursor = txn.open_ro_cursor(&dups).expect("opened");
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.prev()), Some(("e", "7")));
+    assert_eq!(found(cursor.prev()), Some(("c", "5")));
+    assert_eq!(found(cursor.prev_dup()), Some(("c", "4")));
+    assert_eq!(found(cursor.prev_dup()), Some(("c", "3")));
+    assert_eq!(found(cursor.prev_dup()), None);
+    assert_eq!(found(cursor.next_dup()), Some(("c", "4")));
+    assert_eq!(found(cursor.next()), Some(("c", "5")));
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.next()), Some(("e", "7")));
+    assert_eq!(found(cursor.next()), None);
+    assert_eq!(found(cursor.prev()), Some(("c", "5")));
+    assert_eq!(found(cursor.first()), Some(("a", "1")));
+    assert_eq!(found(cursor.prev()), None);
+    assert_eq!(found(cursor.next()), Some(("c", "3")));
+    assert_eq!(found(cursor.seek(b"b")), Some(("c", "3")));
+    assert_eq!(found(cursor.seek(b"c")), Some(("c", "3")));
+    assert_eq!(found(cursor.seek_exact(b"e")), Some(("e", "7")));
+    assert_eq!(found(cursor.last()), Some(("e", "7")));
+
+    // A failed seek leaves the cursor unpositioned.
+    assert_eq!(found(cursor.seek_exact(b"b")), None);
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.next()), Some(("a", "1")));
+    assert_eq!(found(cursor.seek(b"f")), None);
+    assert_eq!(found(cursor.prev()), Some(("e", "7")));
+
+    // Without `DUP_SORT`, there are no other values to move to.
+    let mut cursor = txn.open_ro_cursor(&single).expect("opened");
+    assert_eq!(found(cursor.seek_exact(b"c")), Some(("c", "5")));
+    assert_eq!(found(cursor.next_dup()), None);
+    assert_eq!(found(cursor.prev_dup()), None);
+    assert_eq!(found(cursor.prev()), Some(("a", "1")));
+    assert_eq!(found(cursor.seek(b"d")), Some(("e", "7")));
+
+    let mut cursor = txn.open_ro_cursor(&empty).expect("opened");
+    assert_eq!(found(cursor.first()), None);
+    assert_eq!(found(cursor.last()), None);
+    assert_eq!(found(cursor.next()), None);
+    assert_eq!(found(cursor.prev()), None);
+    assert_eq!(found(cursor.seek(b"a")), None);
+}
+
+/// Single and multi stores iterate backwards from the end or from a given key, including
+/// over what a writer hasn't committed yet.
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_iter_rev_safe() {
+    let root = Builder::new().prefix("test_iter_rev_safe").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 3).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let empty = k.open_single("empty", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for (key, value) in &[("a", 1), ("c", 3), ("c", 4), ("e", 7)] {
+        sk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+        mk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+    }
+    let keys: Vec<_> = sk.iter_rev(&writer).expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"e", b"c", b"a"]);
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let items: Vec<_> = sk.iter_rev(&reader).expect("iter").map(|item| item.expect("item")).collect();
+    assert_eq!(items, [(&b"e"[..], Value::I64(7)), (b"c", Value::I64(4)), (b"a", Value::I64(1))]);
+    let keys: Vec<_> = sk.iter_rev_from(&reader, "c").expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"c", b"a"]);
+    let keys: Vec<_> = sk.iter_rev_from(&reader, "d").expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"c", b"a"]);
+    let keys: Vec<_> = sk.iter_rev_from(&reader, "z").expect("iter").map(|item| item.expect("item").0).collect();
+    assert_eq!(keys, [b"e", b"c", b"a"]);
+    assert_eq!(sk.iter_rev_from(&reader, "0").expect("iter").count(), 0);
+    assert_eq!(empty.iter_rev(&reader).expect("iter").count(), 0);
+    assert_eq!(empty.iter_rev_from(&reader, "a").expect("iter").count(), 0);
+
+    let items: Vec<_> = mk.iter_rev(&reader).expect("iter").map(|item| item.expect("item")).collect();
+    let expected = [(&b"e"[..], Value::I64(7)), (b"c", Value::I64(4)), (b"c", Value::I64(3)), (b"a", Value::I64(1))];
+    assert_eq!(items, expected);
+    let items: Vec<_> = mk.iter_rev_from(&reader, "c").expect("iter").map(|item| item.expect("item")).collect();
+    assert_eq!(items, expected[1..]);
+    let items: Vec<_> = mk.iter_rev_from(&reader, "b").expect("iter").map(|item| item.expect("item")).collect();
+    assert_eq!(items, expected[3..]);
+}
+
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn keys<'i>(iter: impl Iterator<Item = Result<(&'i [u8], Value<'i>), StoreError>>) -> Vec<Vec<u8>> {
+    iter.map(|item| item.expect("item").0.to_vec()).collect()
+}
+
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn items<'i>(iter: impl Iterator<Item = Result<(&'i [u8], Value<'i>), StoreError>>) -> Vec<(Vec<u8>, Value<'i>)> {
+    iter.map(|item| item.expect("item")).map(|(key, value)| (key.to_vec(), value)).collect()
+}
+
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn integers<'i, K>(iter: impl Iterator<Item = Result<(K, Value<'i>), StoreError>>) -> Vec<K> {
+    iter.map(|item| item.expect("item").0).collect()
+}
+
+/// Iterating over ranges and prefixes of keys stops at the right place, comparing keys
+/// the way their databases sort them.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_iter_range_safe() {
+    let root = Builder::new().prefix("test_iter_range_safe").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 5).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let ik = k.open_integer("ik", StoreOptions::create()).expect("opened");
+    let mik = k.open_multi_integer("mik", StoreOptions::create()).expect("opened");
+    let sik = k.open_integer("sik", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &[&b"a"[..], b"ab", b"abc", b"b", b"ba", b"c", b"\xfe", b"\xff", b"\xff\xff"] {
+        sk.put(&mut writer, key, &Value::Bool(true)).expect("wrote");
+    }
+    for (key, value) in &[("a", 1), ("a", 2), ("b", 3), ("c", 4), ("c", 5)] {
+        mk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+    }
+    for key in &[1u32, 2, 256, 65536] {
+        ik.put(&mut writer, *key, &Value::Bool(true)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(1)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(2)).expect("wrote");
+    }
+    for key in &[-65536i32, -256, -1, 0, 255] {
+        sik.put(&mut writer, *key, &Value::Bool(true)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(keys(sk.iter_range(&reader, "ab".."b").expect("iter")), [&b"ab"[..], b"abc"]);
+    assert_eq!(keys(sk.iter_range(&reader, "ab"..="b").expect("iter")), [&b"ab"[..], b"abc", b"b"]);
+    assert_eq!(keys(sk.iter_range(&reader, ..="a").expect("iter")), [b"a"]);
+    assert_eq!(keys(sk.iter_range(&reader, "bb".."ba").expect("iter")), Vec::<Vec<u8>>::new());
+    let range = (Bound::Excluded(b"ab".to_vec()), Bound::Excluded(b"c".to_vec()));
+    assert_eq!(keys(sk.iter_range(&reader, range).expect("iter")), [&b"abc"[..], b"b", b"ba"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, "ab").expect("iter")), [&b"ab"[..], b"abc"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, "b").expect("iter")), [&b"b"[..], b"ba"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, b"\xff").expect("iter")), [&b"\xff"[..], b"\xff\xff"]);
+    assert_eq!(keys(sk.iter_prefix(&reader, "d").expect("iter")), Vec::<Vec<u8>>::new());
+    assert_eq!(sk.iter_prefix(&reader, "").expect("iter").count(), 9);
+
+    let expected = vec![(b"a", Value::I64(1)), (b"a", Value::I64(2)), (b"b", Value::I64(3))];
+    let expected: Vec<_> = expected.into_iter().map(|(key, value)| (key.to_vec(), value)).collect();
+    assert_eq!(items(mk.iter_range(&reader, "a".."c").expect("iter")), expected);
+    let range = (Bound::Excluded(b"a".to_vec()), Bound::Included(b"c".to_vec()));
+    let expected = vec![(b"b", Value::I64(3)), (b"c", Value::I64(4)), (b"c", Value::I64(5))];
+    let expected: Vec<_> = expected.into_iter().map(|(key, value)| (key.to_vec(), value)).collect();
+    assert_eq!(items(mk.iter_range(&reader, range).expect("iter")), expected);
+    assert_eq!(items(mk.iter_prefix(&reader, "c").expect("iter")), expected[1..]);
+
+    assert_eq!(integers(ik.iter_range(&reader, 2..=65536).expect("iter")), [2, 256, 65536]);
+    assert_eq!(integers(ik.iter_range(&reader, 2..65536).expect("iter")), [2, 256]);
+    let range = (Bound::Excluded(2), Bound::Unbounded);
+    assert_eq!(integers(ik.iter_range(&reader, range).expect("iter")), [256, 65536]);
+    assert_eq!(integers(mik.iter_range(&reader, 3..).expect("iter")), [256, 256, 65536, 65536]);
+
+    // Integer prefixes are made of the most significant bits of the keys.
+    assert_eq!(integers(ik.iter_prefix(&reader, 0, 24).expect("iter")), [1, 2]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 256, 24).expect("iter")), [256]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 0, 16).expect("iter")), [1, 2, 256]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 65536, 32).expect("iter")), [65536]);
+    assert_eq!(integers(ik.iter_prefix(&reader, 3, 32).expect("iter")), Vec::<u32>::new());
+    assert_eq!(ik.iter_prefix(&reader, 3, 0).expect("iter").count(), 4);
+    assert_eq!(integers(mik.iter_prefix(&reader, 256, 24).expect("iter")), [256, 256]);
+    assert_eq!(integers(sik.iter_prefix(&reader, -1, 24).expect("iter")), [-256, -1]);
+    assert_eq!(integers(sik.iter_prefix(&reader, -1, 1).expect("iter")), [-65536, -256, -1]);
+    assert_eq!(integers(sik.iter_prefix(&reader, 0, 24).expect("iter")), [0, 255]);
+}
+
+/// Deleting ranges and prefixes of keys counts every value of the keys it deletes, and
+/// the deletions are kept once reopened.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_delete_range_safe() {
+    let root = Builder::new().prefix("test_delete_range_safe").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 3).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let ik = k.open_integer("ik", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &["a", "ab", "abc", "b", "ba", "c"] {
+        sk.put(&mut writer, key, &Value::Bool(true)).expect("wrote");
+    }
+    for (key, value) in &[("a", 1), ("a", 2), ("b", 3), ("c", 4), ("c", 5)] {
+        mk.put(&mut writer, key, &Value::I64(*value)).expect("wrote");
+    }
+    for key in 0u32..1000 {
+        ik.put(&mut writer, key, &Value::U64(u64::from(key))).expect("wrote");
+    }
+
+    assert_eq!(sk.delete_range(&mut writer, "bb".."ba").expect("deleted"), 0);
+    assert_eq!(sk.delete_range(&mut writer, "x"..).expect("deleted"), 0);
+    let range = (Bound::Excluded(b"ab".to_vec()), Bound::Included(b"b".to_vec()));
+    assert_eq!(sk.delete_range(&mut writer, range).expect("deleted"), 2);
+    assert_eq!(keys(sk.iter_start(&writer).expect("iter")), [&b"a"[..], b"ab", b"ba", b"c"]);
+    assert_eq!(sk.delete_prefix(&mut writer, "a").expect("deleted"), 2);
+    assert_eq!(keys(sk.iter_start(&writer).expect("iter")), [&b"ba"[..], b"c"]);
+
+    assert_eq!(mk.delete_range(&mut writer, "a"..="b").expect("deleted"), 3);
+    assert_eq!(mk.get(&writer, "c").expect("read").count(), 2);
+    assert_eq!(mk.delete_prefix(&mut writer, "").expect("deleted"), 2);
+    assert_eq!(mk.get(&writer, "c").expect("read").count(), 0);
+
+    assert_eq!(ik.delete_range(&mut writer, 250..750).expect("deleted"), 500);
+    assert_eq!(ik.delete_range(&mut writer, 600..).expect("deleted"), 250);
+    writer.commit().expect("committed");
+    drop(k);
+
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 3).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::default()).expect("opened");
+    let ik = k.open_integer::<&str, u32>("ik", StoreOptions::default()).expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(keys(sk.iter_start(&reader).expect("iter")), [&b"ba"[..], b"c"]);
+    assert_eq!(ik.get(&reader, 249).expect("read"), Some(Value::U64(249)));
+    assert_eq!(ik.get(&reader, 250).expect("read"), None);
+    assert_eq!(ik.get(&reader, 749).expect("read"), None);
+    assert_eq!(ik.iter_range(&reader, ..).expect("iter").count(), 250);
+}
+
+/// Keys only follow each other by prefix when they're sorted byte by byte, so other
+/// databases refuse prefixes rather than skipping keys or deleting the wrong ones.
+#[test]
+fn test_prefix_unsupported_safe() {
+    let root = Builder::new().prefix("test_prefix_unsupported_safe").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 2).expect("new succeeded");
+    check_prefix_unsupported(&k, "rk", SafeModeDatabaseFlags::REVERSE_KEY);
+    #[cfg(feature = "db-int-key")]
+    check_prefix_unsupported(&k, "ik", SafeModeDatabaseFlags::INTEGER_KEY);
+}
+
+fn check_prefix_unsupported(k: &Rkv<SafeModeEnvironment>, name: &str, flags: SafeModeDatabaseFlags) {
+    let mut options = StoreOptions::<SafeModeDatabaseFlags>::create();
+    options.flags.set(flags, true);
+    let store = k.open_single(name, options).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &[[0u8, 0, 0, 1], [1, 0, 0, 0], [1, 0, 0, 1]] {
+        store.put(&mut writer, key, &Value::Bool(true)).expect("wrote");
+    }
+    match store.iter_prefix(&writer, [1u8]) {
+        Err(StoreError::PrefixNotSupported) => {},
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    match store.delete_prefix(&mut writer, [1u8]) {
+        Err(StoreError::PrefixNotSupported) => {},
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(store.iter_start(&writer).expect("iter").count(), 3);
+}
+
+/// Integer stores iterate in integer order, decoding the keys back into integers.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_integer_iter_safe() {
+    let root = Builder::new().prefix("test_integer_iter_safe").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 2).expect("new succeeded");
+    let ik = k.open_integer("ik", StoreOptions::create()).expect("opened");
+    let mik = k.open_multi_integer("mik", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for key in &[65536u32, 1, 256, 2, u32::MAX] {
+        ik.put(&mut writer, *key, &Value::U64(u64::from(*key))).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(1)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(2)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(integers(ik.iter_start(&reader).expect("iter")), [1, 2, 256, 65536, u32::MAX]);
+    assert_eq!(integers(ik.iter_from(&reader, 3).expect("iter")), [256, 65536, u32::MAX]);
+    assert_eq!(integers(ik.iter_from(&reader, 256).expect("iter")), [256, 65536, u32::MAX]);
+    for item in ik.iter_start(&reader).expect("iter") {
+        let (key, value) = item.expect("item");
+        assert_eq!(value, Value::U64(u64::from(key)));
+    }
+
+    let expected = [1, 1, 2, 2, 256, 256, 65536, 65536, u32::MAX, u32::MAX];
+    assert_eq!(integers(mik.iter_start(&reader).expect("iter")), expected);
+    assert_eq!(integers(mik.iter_from(&reader, 65536).expect("iter")), [65536, 65536, u32::MAX, u32::MAX]);
+    let iter = mik.iter_from(&reader, u32::MAX).expect("iter");
+    let values: Vec<_> = iter.map(|item| item.expect("item").1).collect();
+    assert_eq!(values, [Value::I64(1), Value::I64(2)]);
+}
+
+/// Stores every key into an integer store, and checks that they come back sorted.
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn check_sorted_integers<K>(k: &Rkv<SafeModeEnvironment>, name: &str, keys: &[K]) -> IntegerStore<SafeModeDatabase, K>
+where
+    K: PrimitiveInt + DecodableKey + Copy + Ord + std::fmt::Debug,
+{
+    let store = k.open_integer(name, StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    for key in keys {
+        store.put(&mut writer, *key, &Value::Bool(true)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let mut sorted = keys.to_vec();
+    sorted.sort();
+    let reader = k.read().expect("reader");
+    assert_eq!(integers(store.iter_start(&reader).expect("iter")), sorted);
+    store
+}
+
+/// Integer stores sort their keys by value, whatever the type of integer, including
+/// signed ones.
+#[test]
+#[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
+fn test_integer_key_types_safe() {
+    let root = Builder::new().prefix("test_integer_key_types_safe").tempdir().expect("tempdir");
+    let k = Rkv::with_capacity::<SafeMode>(root.path(), 8).expect("new succeeded");
+
+    check_sorted_integers(&k, "u8", &[255u8, 1, 0, 16]);
+    check_sorted_integers(&k, "u16", &[256u16, 1, u16::MAX, 0, 255]);
+    check_sorted_integers(&k, "u32", &[256u32, 1, u32::MAX, 0, 65536]);
+    check_sorted_integers(&k, "u64", &[1u64 << 32, 1, u64::MAX, 0, 256]);
+    check_sorted_integers(&k, "usize", &[256usize, 1, usize::MAX, 0, 65536]);
+    check_sorted_integers(&k, "i32", &[-1i32, 1, i32::MAX, 0, i32::MIN, -256, 256]);
+    let store = check_sorted_integers(&k, "i64", &[-1i64, 1, i64::MAX, 0, i64::MIN, -(1 << 32), 1 << 32]);
+
+    let reader = k.read().expect("reader");
+    assert_eq!(integers(store.iter_range(&reader, -256..=1).expect("iter")), [-1, 0, 1]);
+    assert_eq!(integers(store.iter_from(&reader, 2).expect("iter")), [1 << 32, i64::MAX]);
+    drop(reader);
+
+    let mik = k.open_multi_integer("mik", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    for key in &[3i32, -3, 0] {
+        mik.put(&mut writer, *key, &Value::I64(1)).expect("wrote");
+        mik.put(&mut writer, *key, &Value::I64(2)).expect("wrote");
+    }
+    assert_eq!(integers(mik.iter_start(&writer).expect("iter")), [-3, -3, 0, 0, 3, 3]);
+    assert_eq!(mik.get(&writer, -3).expect("read").count(), 2);
+}
@@ -44,14 +44,15 @@ fn test_integer_keys() {
         }};
     }
 
-    // The integer module provides only the u32 integer key variant
-    // of IntegerStore, so we can use it without further ado.
+    // The integer module provides integer key variants of IntegerStore
+    // for the primitive integers, so we can use them without further ado.
     test_integer_keys!(s, std::u32::MIN);
     test_integer_keys!(s, std::u32::MAX);
 
-    // If you want to use another integer key variant, you need to implement
+    // If you want to use your own integer key variant, you need to implement
     // a newtype, implement PrimitiveInt, and implement or derive Serialize
-    // for it.  Here we do so for the i32 type.
+    // for it.  Here we do so for the i32 type, which then gets encoded like
+    // any other key instead of in an order preserving way.
 
     // DANGER!  Doing this enables you to open a store with multiple,
     // different integer key types, which may result in unexpected behavior.